@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS trade_history CASCADE;
+DROP TABLE IF EXISTS trade_items CASCADE;
+DROP TABLE IF EXISTS trades CASCADE;
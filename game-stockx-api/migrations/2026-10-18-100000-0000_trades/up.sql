@@ -0,0 +1,34 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS trades (
+    id SERIAL PRIMARY KEY,
+    proposer_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    recipient_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    -- участник, от которого ожидается ответ на текущее предложение
+    awaiting_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'countered', 'accepted', 'rejected', 'cancelled')),
+    message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS trade_items (
+    trade_id INTEGER REFERENCES trades(id) ON DELETE CASCADE NOT NULL,
+    release_id INTEGER REFERENCES releases(id) ON DELETE CASCADE NOT NULL,
+    from_login TEXT REFERENCES users(user_login) ON DELETE CASCADE NOT NULL,
+    PRIMARY KEY (trade_id, release_id, from_login)
+);
+
+CREATE TABLE IF NOT EXISTS trade_history (
+    id SERIAL PRIMARY KEY,
+    trade_id INTEGER REFERENCES trades(id) ON DELETE CASCADE NOT NULL,
+    actor_login TEXT NOT NULL,
+    status TEXT NOT NULL,
+    items JSONB NOT NULL DEFAULT '[]',
+    message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_trades_proposer ON trades (proposer_login);
+CREATE INDEX IF NOT EXISTS idx_trades_recipient ON trades (recipient_login);
+CREATE INDEX IF NOT EXISTS idx_trade_history_trade ON trade_history (trade_id);
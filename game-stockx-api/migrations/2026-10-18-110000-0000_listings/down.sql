@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE IF NOT EXISTS users_have_bids (
+    release_id  INTEGER REFERENCES releases(id) ON DELETE CASCADE NOT NULL,
+    user_login  TEXT REFERENCES users(user_login) ON DELETE CASCADE NOT NULL,
+    PRIMARY KEY (release_id, user_login)
+);
+
+INSERT INTO users_have_bids (release_id, user_login)
+SELECT DISTINCT release_id, seller_login FROM listings WHERE status IN ('active', 'reserved')
+ON CONFLICT DO NOTHING;
+
+DROP TABLE IF EXISTS listings CASCADE;
+DROP TABLE IF EXISTS item_conditions CASCADE;
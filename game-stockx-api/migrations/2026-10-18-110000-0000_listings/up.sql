@@ -0,0 +1,41 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS item_conditions (
+    code TEXT PRIMARY KEY NOT NULL,
+    -- чем меньше, тем лучше состояние
+    rank INTEGER NOT NULL UNIQUE,
+    name TEXT NOT NULL
+);
+
+INSERT INTO item_conditions (code, rank, name) VALUES
+    ('sealed', 1, 'Sealed'),
+    ('mint', 2, 'Mint'),
+    ('very_good', 3, 'Very good'),
+    ('good', 4, 'Good'),
+    ('acceptable', 5, 'Acceptable'),
+    ('poor', 6, 'Poor')
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS listings (
+    id SERIAL PRIMARY KEY,
+    seller_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    release_id INTEGER NOT NULL REFERENCES releases(id) ON DELETE CASCADE,
+    price INTEGER NULL CHECK (price >= 0),
+    currency TEXT NOT NULL DEFAULT 'RUB',
+    condition TEXT NULL REFERENCES item_conditions(code),
+    description TEXT NULL,
+    quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity >= 0),
+    expires_at TIMESTAMPTZ NULL,
+    status TEXT NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'reserved', 'sold', 'withdrawn')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_listings_release_status ON listings (release_id, status);
+CREATE INDEX IF NOT EXISTS idx_listings_seller ON listings (seller_login);
+
+-- Старые флаги "готов продать" переносим в объявления без цены
+INSERT INTO listings (seller_login, release_id)
+SELECT user_login, release_id FROM users_have_bids;
+
+DROP TABLE IF EXISTS users_have_bids CASCADE;
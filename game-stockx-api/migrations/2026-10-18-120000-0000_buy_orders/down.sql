@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS buy_order_matches CASCADE;
+DROP TABLE IF EXISTS buy_orders CASCADE;
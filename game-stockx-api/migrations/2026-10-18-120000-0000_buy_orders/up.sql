@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS buy_orders (
+    id SERIAL PRIMARY KEY,
+    buyer_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    release_id INTEGER NOT NULL REFERENCES releases(id) ON DELETE CASCADE,
+    max_price INTEGER NOT NULL CHECK (max_price >= 0),
+    currency TEXT NOT NULL DEFAULT 'RUB',
+    -- худшее допустимое состояние, NULL - любое
+    min_condition TEXT NULL REFERENCES item_conditions(code),
+    quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity >= 0),
+    note TEXT NULL,
+    expires_at TIMESTAMPTZ NULL,
+    status TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'filled', 'cancelled')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_buy_orders_release_status ON buy_orders (release_id, status);
+CREATE INDEX IF NOT EXISTS idx_buy_orders_buyer ON buy_orders (buyer_login);
+
+-- Найденные пересечения объявлений о продаже с заявками на покупку
+CREATE TABLE IF NOT EXISTS buy_order_matches (
+    buy_order_id INTEGER REFERENCES buy_orders(id) ON DELETE CASCADE NOT NULL,
+    listing_id INTEGER REFERENCES listings(id) ON DELETE CASCADE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (buy_order_id, listing_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_buy_order_matches_listing ON buy_order_matches (listing_id);
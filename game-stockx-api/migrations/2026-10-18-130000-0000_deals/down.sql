@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_sales_product_created;
+DROP INDEX IF EXISTS idx_sales_release_created;
+ALTER TABLE sales DROP COLUMN IF EXISTS deal_id;
+ALTER TABLE sales DROP COLUMN IF EXISTS buyer_login;
+ALTER TABLE sales DROP COLUMN IF EXISTS seller_login;
+ALTER TABLE sales DROP COLUMN IF EXISTS condition;
+ALTER TABLE sales DROP COLUMN IF EXISTS currency;
+ALTER TABLE sales DROP COLUMN IF EXISTS quantity;
+ALTER TABLE sales DROP COLUMN IF EXISTS unit_price;
+ALTER TABLE sales DROP COLUMN IF EXISTS release_id;
+DROP TABLE IF EXISTS deals CASCADE;
@@ -0,0 +1,44 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS sales (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    product_id INTEGER NOT NULL,
+    total_price INTEGER NOT NULL
+);
+
+-- Сделка по объявлению: завершается, когда её подтвердили продавец и покупатель
+CREATE TABLE IF NOT EXISTS deals (
+    id SERIAL PRIMARY KEY,
+    listing_id INTEGER NOT NULL REFERENCES listings(id) ON DELETE CASCADE,
+    release_id INTEGER NOT NULL REFERENCES releases(id) ON DELETE CASCADE,
+    seller_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    buyer_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    unit_price INTEGER NOT NULL CHECK (unit_price >= 0),
+    currency TEXT NOT NULL,
+    condition TEXT NULL REFERENCES item_conditions(code),
+    quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    seller_confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    buyer_confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'completed', 'cancelled')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ NULL,
+    CHECK (seller_login <> buyer_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_deals_seller ON deals (seller_login, status);
+CREATE INDEX IF NOT EXISTS idx_deals_buyer ON deals (buyer_login, status);
+CREATE INDEX IF NOT EXISTS idx_deals_listing ON deals (listing_id);
+
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS release_id INTEGER NULL REFERENCES releases(id) ON DELETE SET NULL;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS unit_price INTEGER NULL;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS quantity INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS currency TEXT NULL;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS condition TEXT NULL REFERENCES item_conditions(code);
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS seller_login TEXT NULL;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS buyer_login TEXT NULL;
+ALTER TABLE sales ADD COLUMN IF NOT EXISTS deal_id INTEGER NULL REFERENCES deals(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_sales_release_created ON sales (release_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_sales_product_created ON sales (product_id, created_at);
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+-- Ценовой ориентир по ценам покупки из коллекций пользователей.
+-- Выбросы отсекаются по Тьюки (за пределами 1.5 IQR от квартилей),
+-- ориентир показывается только при наличии минимум 5 цен.
+CREATE OR REPLACE VIEW release_price_guide AS
+SELECT
+    uhr.release_id,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY uhr.price)::int AS median_price,
+    percentile_cont(0.25) WITHIN GROUP (ORDER BY uhr.price)::int AS q1_price,
+    percentile_cont(0.75) WITHIN GROUP (ORDER BY uhr.price)::int AS q3_price,
+    COUNT(*) AS sample_count
+FROM users_have_releases AS uhr
+INNER JOIN (
+    SELECT
+        release_id,
+        percentile_cont(0.25) WITHIN GROUP (ORDER BY price) AS q1,
+        percentile_cont(0.75) WITHIN GROUP (ORDER BY price) AS q3
+    FROM users_have_releases
+    WHERE price > 0
+    GROUP BY release_id
+) AS fences ON fences.release_id = uhr.release_id
+WHERE uhr.price > 0
+  AND uhr.price BETWEEN fences.q1 - 1.5 * (fences.q3 - fences.q1)
+                    AND fences.q3 + 1.5 * (fences.q3 - fences.q1)
+GROUP BY uhr.release_id
+HAVING COUNT(*) >= 5;
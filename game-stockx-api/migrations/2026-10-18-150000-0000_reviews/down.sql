@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS trg_product_reviews_stats ON product_reviews;
+DROP FUNCTION IF EXISTS product_reviews_stats_trigger();
+DROP FUNCTION IF EXISTS refresh_product_rating_stats(INTEGER);
+DROP TABLE IF EXISTS product_rating_stats;
+DROP TABLE IF EXISTS review_votes;
+DROP TABLE IF EXISTS product_reviews;
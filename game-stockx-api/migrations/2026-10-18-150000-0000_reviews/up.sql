@@ -0,0 +1,70 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS product_reviews (
+    id SERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    -- релиз, на котором играл автор отзыва
+    release_id INTEGER NULL REFERENCES releases(id) ON DELETE SET NULL,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 10),
+    body TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (product_id, user_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_product_reviews_user ON product_reviews (user_login);
+
+CREATE TABLE IF NOT EXISTS review_votes (
+    review_id INTEGER NOT NULL REFERENCES product_reviews(id) ON DELETE CASCADE,
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    helpful BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (review_id, user_login)
+);
+
+-- Агрегаты оценок сообщества, поддерживаются триггером
+CREATE TABLE IF NOT EXISTS product_rating_stats (
+    product_id INTEGER PRIMARY KEY REFERENCES products(id) ON DELETE CASCADE,
+    rating_count INTEGER NOT NULL,
+    rating_avg DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_product_rating_stats_avg ON product_rating_stats (rating_avg DESC);
+
+CREATE OR REPLACE FUNCTION refresh_product_rating_stats(p_product_id INTEGER) RETURNS VOID AS $$
+BEGIN
+    -- Одним upsert, чтобы параллельные отзывы на один товар не сталкивались на первичном ключе
+    INSERT INTO product_rating_stats (product_id, rating_count, rating_avg)
+    SELECT product_id, COUNT(*), AVG(rating)::double precision
+    FROM product_reviews
+    WHERE product_id = p_product_id
+    GROUP BY product_id
+    ON CONFLICT (product_id) DO UPDATE
+    SET rating_count = EXCLUDED.rating_count,
+        rating_avg = EXCLUDED.rating_avg,
+        updated_at = NOW();
+
+    -- Строка статистики удаляется, только когда отзывов не осталось
+    DELETE FROM product_rating_stats AS s
+    WHERE s.product_id = p_product_id
+      AND NOT EXISTS (SELECT 1 FROM product_reviews AS r WHERE r.product_id = p_product_id);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION product_reviews_stats_trigger() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM refresh_product_rating_stats(OLD.product_id);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND (TG_OP = 'INSERT' OR NEW.product_id <> OLD.product_id) THEN
+        PERFORM refresh_product_rating_stats(NEW.product_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_product_reviews_stats ON product_reviews;
+CREATE TRIGGER trg_product_reviews_stats
+    AFTER INSERT OR UPDATE OF rating, product_id OR DELETE ON product_reviews
+    FOR EACH ROW EXECUTE FUNCTION product_reviews_stats_trigger();
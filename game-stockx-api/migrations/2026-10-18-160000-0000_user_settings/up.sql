@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Настройки приватности; отсутствие строки означает значения по умолчанию
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_login TEXT PRIMARY KEY REFERENCES users(user_login) ON DELETE CASCADE,
+    collection_private BOOLEAN NOT NULL DEFAULT FALSE,
+    wishlist_private BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS trg_listings_collection_event ON listings;
+DROP TRIGGER IF EXISTS trg_uhw_collection_event ON users_have_wishes;
+DROP TRIGGER IF EXISTS trg_uhr_collection_event ON users_have_releases;
+DROP FUNCTION IF EXISTS record_collection_event();
+DROP TABLE IF EXISTS collection_events;
+DROP TABLE IF EXISTS user_follows;
@@ -0,0 +1,53 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS user_follows (
+    follower_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    followee_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (follower_login, followee_login),
+    CHECK (follower_login <> followee_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_follows_followee ON user_follows (followee_login);
+
+-- События коллекций для ленты активности
+CREATE TABLE IF NOT EXISTS collection_events (
+    id BIGSERIAL PRIMARY KEY,
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    event_type TEXT NOT NULL CHECK (event_type IN ('acquired', 'wished', 'listed')),
+    release_id INTEGER NOT NULL REFERENCES releases(id) ON DELETE CASCADE,
+    listing_id INTEGER NULL REFERENCES listings(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_collection_events_user ON collection_events (user_login, id DESC);
+
+CREATE OR REPLACE FUNCTION record_collection_event() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'users_have_releases' THEN
+        INSERT INTO collection_events (user_login, event_type, release_id)
+        VALUES (NEW.user_login, 'acquired', NEW.release_id);
+    ELSIF TG_TABLE_NAME = 'users_have_wishes' THEN
+        INSERT INTO collection_events (user_login, event_type, release_id)
+        VALUES (NEW.user_login, 'wished', NEW.release_id);
+    ELSIF TG_TABLE_NAME = 'listings' THEN
+        INSERT INTO collection_events (user_login, event_type, release_id, listing_id)
+        VALUES (NEW.seller_login, 'listed', NEW.release_id, NEW.id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_uhr_collection_event ON users_have_releases;
+CREATE TRIGGER trg_uhr_collection_event
+    AFTER INSERT ON users_have_releases
+    FOR EACH ROW EXECUTE FUNCTION record_collection_event();
+
+DROP TRIGGER IF EXISTS trg_uhw_collection_event ON users_have_wishes;
+CREATE TRIGGER trg_uhw_collection_event
+    AFTER INSERT ON users_have_wishes
+    FOR EACH ROW EXECUTE FUNCTION record_collection_event();
+
+DROP TRIGGER IF EXISTS trg_listings_collection_event ON listings;
+CREATE TRIGGER trg_listings_collection_event
+    AFTER INSERT ON listings
+    FOR EACH ROW EXECUTE FUNCTION record_collection_event();
@@ -0,0 +1,80 @@
+-- This file should undo anything in `up.sql`
+DROP MATERIALIZED VIEW IF EXISTS collector_leaderboard;
+DROP FUNCTION IF EXISTS refresh_rarity();
+DROP TABLE IF EXISTS product_rarity;
+DROP TABLE IF EXISTS release_rarity;
+
+-- Рейтинг коллекционеров по всем срезам: 0 в platform_scope/region_scope означает «все»,
+-- -1 в region_scope - релизы без региона. Пересчитывается фоновым актором.
+CREATE MATERIALIZED VIEW collector_leaderboard AS
+WITH owned AS (
+    SELECT
+        uhr.user_login,
+        uhr.price,
+        r.product_id,
+        r.platform AS platform_id,
+        COALESCE(r.release_region, -1) AS region_id,
+        -- чем меньше владельцев у релиза, тем больше он весит
+        1.0 / COUNT(*) OVER (PARTITION BY uhr.release_id) AS rarity
+    FROM users_have_releases AS uhr
+    INNER JOIN releases AS r ON r.id = uhr.release_id
+),
+user_scopes AS (
+    SELECT
+        user_login,
+        CASE WHEN GROUPING(platform_id) = 1 THEN 0 ELSE platform_id END AS platform_scope,
+        CASE WHEN GROUPING(region_id) = 1 THEN 0 ELSE region_id END AS region_scope,
+        COUNT(*) AS release_count,
+        COALESCE(SUM(price), 0)::bigint AS collection_value,
+        COUNT(DISTINCT product_id) AS product_count,
+        SUM(rarity)::double precision AS rarity_score
+    FROM owned
+    GROUP BY GROUPING SETS (
+        (user_login),
+        (user_login, platform_id),
+        (user_login, region_id),
+        (user_login, platform_id, region_id)
+    )
+),
+catalog AS (
+    SELECT
+        CASE WHEN GROUPING(platform) = 1 THEN 0 ELSE platform END AS platform_scope,
+        CASE WHEN GROUPING(region) = 1 THEN 0 ELSE region END AS region_scope,
+        COUNT(DISTINCT product_id) AS product_total
+    FROM (
+        SELECT product_id, platform, COALESCE(release_region, -1) AS region
+        FROM releases
+    ) AS r
+    GROUP BY GROUPING SETS ((), (platform), (region), (platform, region))
+),
+scored AS (
+    SELECT
+        us.*,
+        (100.0 * us.product_count / NULLIF(c.product_total, 0))::double precision AS completion_pct
+    FROM user_scopes AS us
+    INNER JOIN catalog AS c
+        ON c.platform_scope = us.platform_scope AND c.region_scope = us.region_scope
+    WHERE NOT EXISTS (
+        SELECT 1 FROM user_settings AS s
+        WHERE s.user_login = us.user_login AND s.collection_private
+    )
+)
+SELECT
+    scored.*,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY release_count DESC) AS rank_count,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY collection_value DESC) AS rank_value,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY completion_pct DESC NULLS LAST) AS rank_completion,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY rarity_score DESC) AS rank_rarity,
+    NOW() AS refreshed_at
+FROM scored;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_collector_leaderboard_user
+    ON collector_leaderboard (platform_scope, region_scope, user_login);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_count
+    ON collector_leaderboard (platform_scope, region_scope, rank_count);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_value
+    ON collector_leaderboard (platform_scope, region_scope, rank_value);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_completion
+    ON collector_leaderboard (platform_scope, region_scope, rank_completion);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_rarity
+    ON collector_leaderboard (platform_scope, region_scope, rank_rarity);
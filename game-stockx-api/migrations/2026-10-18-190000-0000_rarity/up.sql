@@ -0,0 +1,167 @@
+-- Your SQL goes here
+-- Редкость и спрос по данным сообщества: мало владельцев и много желающих - редкий релиз.
+-- rarity_score - перцентиль (0-100) отношения (желающие + 1) / (владельцы + 1).
+CREATE TABLE IF NOT EXISTS release_rarity (
+    release_id INTEGER PRIMARY KEY REFERENCES releases(id) ON DELETE CASCADE,
+    owner_count INTEGER NOT NULL,
+    wisher_count INTEGER NOT NULL,
+    demand_ratio DOUBLE PRECISION NOT NULL,
+    rarity_score DOUBLE PRECISION NOT NULL,
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS product_rarity (
+    product_id INTEGER PRIMARY KEY REFERENCES products(id) ON DELETE CASCADE,
+    owner_count INTEGER NOT NULL,
+    wisher_count INTEGER NOT NULL,
+    demand_ratio DOUBLE PRECISION NOT NULL,
+    rarity_score DOUBLE PRECISION NOT NULL,
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE OR REPLACE FUNCTION refresh_rarity() RETURNS VOID AS $$
+BEGIN
+    WITH counts AS (
+        SELECT release_id, SUM(is_owner)::int AS owner_count, SUM(is_wisher)::int AS wisher_count
+        FROM (
+            SELECT release_id, 1 AS is_owner, 0 AS is_wisher FROM users_have_releases
+            UNION ALL
+            SELECT release_id, 0, 1 FROM users_have_wishes
+        ) AS marks
+        GROUP BY release_id
+    ),
+    ratios AS (
+        SELECT *, (wisher_count + 1.0) / (owner_count + 1.0) AS demand_ratio
+        FROM counts
+    )
+    INSERT INTO release_rarity (release_id, owner_count, wisher_count, demand_ratio, rarity_score, refreshed_at)
+    SELECT
+        release_id, owner_count, wisher_count, demand_ratio,
+        100.0 * PERCENT_RANK() OVER (ORDER BY demand_ratio),
+        NOW()
+    FROM ratios
+    ON CONFLICT (release_id) DO UPDATE
+    SET owner_count = EXCLUDED.owner_count,
+        wisher_count = EXCLUDED.wisher_count,
+        demand_ratio = EXCLUDED.demand_ratio,
+        rarity_score = EXCLUDED.rarity_score,
+        refreshed_at = EXCLUDED.refreshed_at;
+
+    -- релизы, которых больше нет ни в коллекциях, ни в вишлистах
+    DELETE FROM release_rarity WHERE refreshed_at < NOW();
+
+    -- для продукта считаем уникальных пользователей по всем его релизам
+    WITH counts AS (
+        SELECT
+            product_id,
+            COUNT(DISTINCT user_login) FILTER (WHERE is_owner) AS owner_count,
+            COUNT(DISTINCT user_login) FILTER (WHERE NOT is_owner) AS wisher_count
+        FROM (
+            SELECT r.product_id, uhr.user_login, TRUE AS is_owner
+            FROM users_have_releases AS uhr
+            INNER JOIN releases AS r ON r.id = uhr.release_id
+            UNION ALL
+            SELECT r.product_id, uhw.user_login, FALSE
+            FROM users_have_wishes AS uhw
+            INNER JOIN releases AS r ON r.id = uhw.release_id
+        ) AS marks
+        GROUP BY product_id
+    ),
+    ratios AS (
+        SELECT *, (wisher_count + 1.0) / (owner_count + 1.0) AS demand_ratio
+        FROM counts
+    )
+    INSERT INTO product_rarity (product_id, owner_count, wisher_count, demand_ratio, rarity_score, refreshed_at)
+    SELECT
+        product_id, owner_count, wisher_count, demand_ratio,
+        100.0 * PERCENT_RANK() OVER (ORDER BY demand_ratio),
+        NOW()
+    FROM ratios
+    ON CONFLICT (product_id) DO UPDATE
+    SET owner_count = EXCLUDED.owner_count,
+        wisher_count = EXCLUDED.wisher_count,
+        demand_ratio = EXCLUDED.demand_ratio,
+        rarity_score = EXCLUDED.rarity_score,
+        refreshed_at = EXCLUDED.refreshed_at;
+
+    DELETE FROM product_rarity WHERE refreshed_at < NOW();
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT refresh_rarity();
+
+-- Рейтинг коллекционеров по редкости теперь считается по release_rarity
+DROP MATERIALIZED VIEW IF EXISTS collector_leaderboard;
+
+CREATE MATERIALIZED VIEW collector_leaderboard AS
+WITH owned AS (
+    SELECT
+        uhr.user_login,
+        uhr.price,
+        r.product_id,
+        r.platform AS platform_id,
+        COALESCE(r.release_region, -1) AS region_id,
+        COALESCE(rr.rarity_score, 0) / 100.0 AS rarity
+    FROM users_have_releases AS uhr
+    INNER JOIN releases AS r ON r.id = uhr.release_id
+    LEFT JOIN release_rarity AS rr ON rr.release_id = uhr.release_id
+),
+user_scopes AS (
+    SELECT
+        user_login,
+        CASE WHEN GROUPING(platform_id) = 1 THEN 0 ELSE platform_id END AS platform_scope,
+        CASE WHEN GROUPING(region_id) = 1 THEN 0 ELSE region_id END AS region_scope,
+        COUNT(*) AS release_count,
+        COALESCE(SUM(price), 0)::bigint AS collection_value,
+        COUNT(DISTINCT product_id) AS product_count,
+        SUM(rarity)::double precision AS rarity_score
+    FROM owned
+    GROUP BY GROUPING SETS (
+        (user_login),
+        (user_login, platform_id),
+        (user_login, region_id),
+        (user_login, platform_id, region_id)
+    )
+),
+catalog AS (
+    SELECT
+        CASE WHEN GROUPING(platform) = 1 THEN 0 ELSE platform END AS platform_scope,
+        CASE WHEN GROUPING(region) = 1 THEN 0 ELSE region END AS region_scope,
+        COUNT(DISTINCT product_id) AS product_total
+    FROM (
+        SELECT product_id, platform, COALESCE(release_region, -1) AS region
+        FROM releases
+    ) AS r
+    GROUP BY GROUPING SETS ((), (platform), (region), (platform, region))
+),
+scored AS (
+    SELECT
+        us.*,
+        (100.0 * us.product_count / NULLIF(c.product_total, 0))::double precision AS completion_pct
+    FROM user_scopes AS us
+    INNER JOIN catalog AS c
+        ON c.platform_scope = us.platform_scope AND c.region_scope = us.region_scope
+    WHERE NOT EXISTS (
+        SELECT 1 FROM user_settings AS s
+        WHERE s.user_login = us.user_login AND s.collection_private
+    )
+)
+SELECT
+    scored.*,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY release_count DESC) AS rank_count,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY collection_value DESC) AS rank_value,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY completion_pct DESC NULLS LAST) AS rank_completion,
+    RANK() OVER (PARTITION BY platform_scope, region_scope ORDER BY rarity_score DESC) AS rank_rarity,
+    NOW() AS refreshed_at
+FROM scored;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_collector_leaderboard_user
+    ON collector_leaderboard (platform_scope, region_scope, user_login);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_count
+    ON collector_leaderboard (platform_scope, region_scope, rank_count);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_value
+    ON collector_leaderboard (platform_scope, region_scope, rank_value);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_completion
+    ON collector_leaderboard (platform_scope, region_scope, rank_completion);
+CREATE INDEX IF NOT EXISTS idx_collector_leaderboard_rarity
+    ON collector_leaderboard (platform_scope, region_scope, rank_rarity);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP FUNCTION IF EXISTS refresh_product_recommendations();
+DROP TABLE IF EXISTS product_recommendations;
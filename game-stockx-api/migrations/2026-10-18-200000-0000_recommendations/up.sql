@@ -0,0 +1,83 @@
+-- Your SQL goes here
+-- Предрасчитанные рекомендации «владельцы этого также собирают».
+-- platform_id = 0 - по всем платформам, иначе только совладение релизами этой платформы.
+CREATE TABLE IF NOT EXISTS product_recommendations (
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    platform_id INTEGER NOT NULL,
+    related_product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    co_owner_count INTEGER NOT NULL,
+    PRIMARY KEY (product_id, platform_id, related_product_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_product_recommendations_score
+    ON product_recommendations (product_id, platform_id, score DESC);
+
+-- Косинусная близость продуктов по интересу пользователей:
+-- владение релизом даёт вес 1, вишлист - 0.5. Для пары нужно минимум 2 общих пользователя,
+-- на продукт сохраняется не больше 20 рекомендаций.
+CREATE OR REPLACE FUNCTION refresh_product_recommendations() RETURNS VOID AS $$
+BEGIN
+    DELETE FROM product_recommendations;
+
+    WITH interest AS (
+        SELECT uhr.user_login, r.product_id, r.platform AS platform_id, 1.0 AS weight
+        FROM users_have_releases AS uhr
+        INNER JOIN releases AS r ON r.id = uhr.release_id
+        UNION ALL
+        SELECT uhw.user_login, r.product_id, r.platform, 0.5
+        FROM users_have_wishes AS uhw
+        INNER JOIN releases AS r ON r.id = uhw.release_id
+    ),
+    signals AS (
+        SELECT
+            user_login,
+            product_id,
+            CASE WHEN GROUPING(platform_id) = 1 THEN 0 ELSE platform_id END AS platform_id,
+            MAX(weight) AS weight
+        FROM interest
+        GROUP BY GROUPING SETS ((user_login, product_id, platform_id), (user_login, product_id))
+    ),
+    norms AS (
+        SELECT product_id, platform_id, SQRT(SUM(weight * weight)) AS norm
+        FROM signals
+        GROUP BY product_id, platform_id
+    ),
+    pairs AS (
+        SELECT
+            a.product_id,
+            a.platform_id,
+            b.product_id AS related_product_id,
+            SUM(a.weight * b.weight) AS dot,
+            COUNT(*) FILTER (WHERE a.weight = 1 AND b.weight = 1) AS co_owner_count
+        FROM signals AS a
+        INNER JOIN signals AS b
+            ON b.user_login = a.user_login
+           AND b.platform_id = a.platform_id
+           AND b.product_id <> a.product_id
+        GROUP BY a.product_id, a.platform_id, b.product_id
+        HAVING COUNT(*) >= 2
+    ),
+    ranked AS (
+        SELECT
+            p.product_id,
+            p.platform_id,
+            p.related_product_id,
+            p.dot / (na.norm * nb.norm) AS score,
+            p.co_owner_count,
+            ROW_NUMBER() OVER (
+                PARTITION BY p.product_id, p.platform_id
+                ORDER BY p.dot / (na.norm * nb.norm) DESC, p.co_owner_count DESC, p.related_product_id
+            ) AS position
+        FROM pairs AS p
+        INNER JOIN norms AS na ON na.product_id = p.product_id AND na.platform_id = p.platform_id
+        INNER JOIN norms AS nb ON nb.product_id = p.related_product_id AND nb.platform_id = p.platform_id
+    )
+    INSERT INTO product_recommendations (product_id, platform_id, related_product_id, score, co_owner_count)
+    SELECT product_id, platform_id, related_product_id, score, co_owner_count
+    FROM ranked
+    WHERE position <= 20;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT refresh_product_recommendations();
@@ -0,0 +1,33 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS notifications (
+    id BIGSERIAL PRIMARY KEY,
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    notification_type TEXT NOT NULL CHECK (notification_type IN (
+        'wishlist_listing', 'buy_order_match', 'offline_message',
+        'trade_proposed', 'trade_countered', 'trade_accepted', 'trade_rejected', 'trade_cancelled',
+        'deal_proposed', 'deal_completed', 'deal_cancelled'
+    )),
+    -- кто вызвал событие: продавец, собеседник, контрагент по обмену или сделке
+    actor_login TEXT NULL REFERENCES users(user_login) ON DELETE SET NULL,
+    release_id INTEGER NULL REFERENCES releases(id) ON DELETE CASCADE,
+    listing_id INTEGER NULL REFERENCES listings(id) ON DELETE CASCADE,
+    buy_order_id INTEGER NULL REFERENCES buy_orders(id) ON DELETE CASCADE,
+    trade_id INTEGER NULL REFERENCES trades(id) ON DELETE CASCADE,
+    deal_id INTEGER NULL REFERENCES deals(id) ON DELETE CASCADE,
+    body TEXT NULL,
+    is_read BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    read_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications (user_login, id DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_unread ON notifications (user_login) WHERE NOT is_read;
+
+-- Отключённые типы уведомлений; отсутствие строки означает, что тип включён
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    notification_type TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_login, notification_type)
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE messages
+  ADD COLUMN IF NOT EXISTS read_at TIMESTAMPTZ NULL;
+
+CREATE INDEX IF NOT EXISTS idx_messages_unread ON messages (recipient_login, sender_login) WHERE NOT read;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Постраничная загрузка переписки по id сообщения
+CREATE INDEX IF NOT EXISTS idx_messages_dialog ON messages (sender_login, recipient_login, id);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Идентификатор, который генерирует клиент: повторная отправка не создаёт дубликат
+ALTER TABLE messages
+  ADD COLUMN IF NOT EXISTS client_id TEXT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_client_id
+    ON messages (sender_login, client_id) WHERE client_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS user_presence;
+ALTER TABLE user_settings DROP COLUMN IF EXISTS hide_online_status;
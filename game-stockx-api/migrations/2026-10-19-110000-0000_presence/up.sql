@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE user_settings
+  ADD COLUMN IF NOT EXISTS hide_online_status BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Когда пользователь последний раз был в чате; обновляется при отключении последней сессии
+CREATE TABLE IF NOT EXISTS user_presence (
+    user_login TEXT PRIMARY KEY REFERENCES users(user_login) ON DELETE CASCADE,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
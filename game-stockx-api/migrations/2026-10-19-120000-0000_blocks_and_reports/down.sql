@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS abuse_reports;
+DROP TABLE IF EXISTS user_blocks;
@@ -0,0 +1,32 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS user_blocks (
+    blocker_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    blocked_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blocker_login, blocked_login),
+    CHECK (blocker_login <> blocked_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_blocks_blocked ON user_blocks (blocked_login);
+
+-- Очередь модерации. Текст сообщения копируется в жалобу, чтобы его нельзя было
+-- подменить или удалить после отправки жалобы.
+CREATE TABLE IF NOT EXISTS abuse_reports (
+    id BIGSERIAL PRIMARY KEY,
+    reporter_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    reported_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    message_id INTEGER NULL REFERENCES messages(id) ON DELETE SET NULL,
+    message_body TEXT NULL,
+    message_sent_at TIMESTAMPTZ NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('spam', 'harassment', 'scam', 'inappropriate', 'other')),
+    comment TEXT NULL,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'resolved', 'dismissed')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ NULL,
+    CHECK (reporter_login <> reported_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_abuse_reports_queue ON abuse_reports (status, created_at);
+CREATE INDEX IF NOT EXISTS idx_abuse_reports_reporter ON abuse_reports (reporter_login, id DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_abuse_reports_message
+    ON abuse_reports (reporter_login, message_id) WHERE message_id IS NOT NULL;
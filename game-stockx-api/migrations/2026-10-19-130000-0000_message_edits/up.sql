@@ -0,0 +1,19 @@
+-- Your SQL goes here
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS edited_at TIMESTAMPTZ NULL,
+    -- удалено отправителем для обоих; текст переносится в message_edits
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ NULL,
+    -- скрыто только у одного из участников
+    ADD COLUMN IF NOT EXISTS hidden_for_sender BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS hidden_for_recipient BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Прежние версии текста для модерации
+CREATE TABLE IF NOT EXISTS message_edits (
+    id BIGSERIAL PRIMARY KEY,
+    message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    action TEXT NOT NULL CHECK (action IN ('edit', 'delete')),
+    previous_body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_edits_message ON message_edits (message_id, id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE messages
+    DROP CONSTRAINT IF EXISTS messages_attachment_check,
+    DROP COLUMN IF EXISTS attachment_id,
+    DROP COLUMN IF EXISTS attachment_type;
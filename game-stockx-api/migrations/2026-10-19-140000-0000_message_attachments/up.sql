@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Ссылка сообщения на релиз, объявление или обмен; карточка собирается при чтении
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS attachment_type TEXT NULL
+        CHECK (attachment_type IN ('release', 'listing', 'trade')),
+    ADD COLUMN IF NOT EXISTS attachment_id INTEGER NULL,
+    ADD CONSTRAINT messages_attachment_check
+        CHECK ((attachment_type IS NULL) = (attachment_id IS NULL));
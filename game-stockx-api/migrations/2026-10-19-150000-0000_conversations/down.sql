@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM messages WHERE conversation_id IS NOT NULL;
+ALTER TABLE messages
+    DROP CONSTRAINT IF EXISTS messages_target_check,
+    DROP COLUMN IF EXISTS conversation_id,
+    ALTER COLUMN recipient_login SET NOT NULL;
+DROP TABLE IF EXISTS conversation_members;
+DROP TABLE IF EXISTS conversations;
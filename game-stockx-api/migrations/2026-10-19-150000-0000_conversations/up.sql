@@ -0,0 +1,39 @@
+-- Your SQL goes here
+-- Групповые беседы и ветки обсуждения объявления или обмена
+CREATE TABLE IF NOT EXISTS conversations (
+    id SERIAL PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('group', 'listing', 'trade')),
+    title TEXT NULL,
+    listing_id INTEGER NULL REFERENCES listings(id) ON DELETE CASCADE,
+    trade_id INTEGER NULL REFERENCES trades(id) ON DELETE CASCADE,
+    created_by TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((kind = 'listing') = (listing_id IS NOT NULL)),
+    CHECK ((kind = 'trade') = (trade_id IS NOT NULL))
+);
+
+-- По объявлению у каждого покупателя своя ветка с продавцом, у обмена ветка одна
+CREATE UNIQUE INDEX IF NOT EXISTS idx_conversations_listing_thread
+    ON conversations (listing_id, created_by) WHERE kind = 'listing';
+CREATE UNIQUE INDEX IF NOT EXISTS idx_conversations_trade_thread
+    ON conversations (trade_id) WHERE kind = 'trade';
+
+CREATE TABLE IF NOT EXISTS conversation_members (
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    user_login TEXT NOT NULL REFERENCES users(user_login) ON DELETE CASCADE,
+    role TEXT NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'member')),
+    last_read_message_id INTEGER NULL,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, user_login)
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_members_user ON conversation_members (user_login);
+
+-- Сообщение адресовано либо собеседнику, либо беседе
+ALTER TABLE messages
+    ALTER COLUMN recipient_login DROP NOT NULL,
+    ADD COLUMN IF NOT EXISTS conversation_id INTEGER NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    ADD CONSTRAINT messages_target_check CHECK ((recipient_login IS NULL) <> (conversation_id IS NULL));
+
+CREATE INDEX IF NOT EXISTS idx_messages_conversation
+    ON messages (conversation_id, id) WHERE conversation_id IS NOT NULL;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sales ALTER COLUMN total_price TYPE INTEGER;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Сумма сделки - цена за штуку на количество, в int она может не поместиться
+ALTER TABLE sales ALTER COLUMN total_price TYPE BIGINT;
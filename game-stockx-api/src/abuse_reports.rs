@@ -0,0 +1,217 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::ApiError;
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::conversations::member_logins;
+use crate::DBPool;
+
+const REASONS: [&str; 5] = ["spam", "harassment", "scam", "inappropriate", "other"];
+const MAX_COMMENT_LENGTH: usize = 2000;
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    // жалоба на конкретное сообщение; автор определяется по нему
+    message_id: Option<i32>,
+    // жалоба на пользователя без сообщения
+    reported_login: Option<String>,
+    reason: String,
+    comment: Option<String>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct ReportDto {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+
+    #[diesel(sql_type = Text)]
+    pub reported_login: String,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub message_id: Option<i32>,
+
+    // текст сообщения на момент жалобы
+    #[diesel(sql_type = Nullable<Text>)]
+    pub message_body: Option<String>,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub message_sent_at: Option<DateTime<Utc>>,
+
+    #[diesel(sql_type = Text)]
+    pub reason: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub comment: Option<String>,
+
+    // open, resolved или dismissed
+    #[diesel(sql_type = Text)]
+    pub status: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(QueryableByName)]
+struct ReportedMessage {
+    #[diesel(sql_type = Text)]
+    sender_login: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    recipient_login: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    conversation_id: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    body: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+}
+
+const REPORT_COLUMNS: &str = r#"
+    id, reported_login, message_id, message_body, message_sent_at, reason, comment, status, created_at
+"#;
+
+// Жалоба в очередь модерации. Пожаловаться на сообщение может только тот, кому оно адресовано.
+#[post("/reports")]
+async fn create_report(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    data: web::Json<ReportRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if !REASONS.contains(&data.reason.as_str()) {
+        return HttpResponse::BadRequest().body(format!("Reason must be one of: {}", REASONS.join(", ")));
+    }
+
+    let comment = data.comment.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    if comment.is_some_and(|c| c.chars().count() > MAX_COMMENT_LENGTH) {
+        return HttpResponse::BadRequest().body("Comment is too long");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let (reported_login, message) = match data.message_id {
+            Some(message_id) => {
+                // у удалённого сообщения берём текст, каким он был до удаления
+                let message = diesel::sql_query(
+                    r#"
+                    SELECT
+                        m.sender_login,
+                        m.recipient_login,
+                        m.conversation_id,
+                        COALESCE(
+                            (SELECT e.previous_body FROM message_edits AS e
+                             WHERE e.message_id = m.id AND e.action = 'delete'
+                             ORDER BY e.id DESC LIMIT 1),
+                            m.body
+                        ) AS body,
+                        m.created_at
+                    FROM messages AS m
+                    WHERE m.id = $1
+                    "#,
+                )
+                .bind::<Integer, _>(message_id)
+                .get_result::<ReportedMessage>(conn)?;
+
+                // в беседе пожаловаться может любой участник, кроме автора
+                let can_report = match (&message.recipient_login, message.conversation_id) {
+                    (Some(recipient), _) => *recipient == user_login,
+                    (None, Some(conversation_id)) => {
+                        message.sender_login != user_login
+                            && member_logins(conn, conversation_id)?.contains(&user_login)
+                    }
+                    (None, None) => false,
+                };
+                if !can_report {
+                    return Err(ApiError::Forbidden);
+                }
+                if data.reported_login.as_ref().is_some_and(|l| *l != message.sender_login) {
+                    return Err(ApiError::BadRequest("Message was sent by another user".to_string()));
+                }
+                (message.sender_login.clone(), Some(message))
+            }
+            None => match &data.reported_login {
+                Some(login) => (login.clone(), None),
+                None => {
+                    return Err(ApiError::BadRequest(
+                        "Either message_id or reported_login is required".to_string(),
+                    ));
+                }
+            },
+        };
+
+        if reported_login == user_login {
+            return Err(ApiError::BadRequest("Cannot report yourself".to_string()));
+        }
+
+        let insert_query = format!(
+            r#"
+            INSERT INTO abuse_reports
+                (reporter_login, reported_login, message_id, message_body, message_sent_at, reason, comment)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {}
+            "#,
+            REPORT_COLUMNS
+        );
+
+        diesel::sql_query(insert_query)
+            .bind::<Text, _>(&user_login)
+            .bind::<Text, _>(&reported_login)
+            .bind::<Nullable<Integer>, _>(data.message_id)
+            .bind::<Nullable<Text>, _>(message.as_ref().map(|m| m.body.as_str()))
+            .bind::<Nullable<Timestamptz>, _>(message.as_ref().map(|m| m.created_at))
+            .bind::<Text, _>(&data.reason)
+            .bind::<Nullable<Text>, _>(comment)
+            .get_result::<ReportDto>(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                    _,
+                ) => ApiError::NotFound,
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UniqueViolation,
+                    _,
+                ) => ApiError::Conflict("Message already reported".to_string()),
+                other => ApiError::from(other),
+            })
+    });
+
+    match result {
+        Ok(report) => HttpResponse::Created().json(report),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Свои жалобы и их статус
+#[get("/reports")]
+async fn get_my_reports(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let query = format!(
+        "SELECT {} FROM abuse_reports WHERE reporter_login = $1 ORDER BY id DESC LIMIT 100",
+        REPORT_COLUMNS
+    );
+
+    match diesel::sql_query(query).bind::<Text, _>(&user_login).load::<ReportDto>(conn) {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use actix_web::HttpResponse;
+use std::fmt;
+
+// Ошибка бизнес-операции, которую нужно отдать клиенту соответствующим HTTP-статусом
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound,
+    Forbidden,
+    BadRequest(String),
+    Conflict(String),
+    Db(diesel::result::Error),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Not found"),
+            Self::Forbidden => write!(f, "Action is not allowed for this user"),
+            Self::BadRequest(msg) => write!(f, "{}", msg),
+            Self::Conflict(msg) => write!(f, "{}", msg),
+            Self::Db(e) => write!(f, "DB error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => Self::NotFound,
+            other => Self::Db(other),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn into_response(self) -> HttpResponse {
+        match self {
+            Self::NotFound => HttpResponse::NotFound().body(self.to_string()),
+            Self::Forbidden => HttpResponse::Forbidden().body(self.to_string()),
+            Self::BadRequest(_) => HttpResponse::BadRequest().body(self.to_string()),
+            Self::Conflict(_) => HttpResponse::Conflict().body(self.to_string()),
+            Self::Db(ref e) => {
+                eprintln!("DB error: {:?}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+// Нарушение внешнего ключа - клиент сослался на неизвестный релиз или состояние товара
+pub fn reference_error(err: diesel::result::Error) -> ApiError {
+    match err {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+            _,
+        ) => ApiError::BadRequest("Unknown release or condition".to_string()),
+        other => ApiError::from(other),
+    }
+}
+
+// Ответ на ошибку запроса к объявлению или заявке; not_found - текст для 404
+pub fn db_error_response(err: diesel::result::Error, not_found: &str) -> HttpResponse {
+    match err {
+        diesel::result::Error::NotFound => HttpResponse::NotFound().body(not_found.to_string()),
+        other => reference_error(other).into_response(),
+    }
+}
@@ -1,5 +1,6 @@
 use crate::constants::{CONNECTION_POOL_ERROR};
 use actix_web::{HttpRequest, HttpResponse, post, web};
+use actix_web::http::header;
 use crate::DBPool;
 use jsonwebtoken::{encode, decode, Header, EncodingKey, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
@@ -43,6 +44,17 @@ pub fn verify_jwt(token: &str) -> Option<Claims> {
         .ok()
 }
 
+// Достаёт логин пользователя из заголовка Authorization: Bearer <jwt>
+pub fn require_login(req: &HttpRequest) -> Result<String, HttpResponse> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(verify_jwt)
+        .map(|claims| claims.sub)
+        .ok_or_else(|| HttpResponse::Unauthorized().body("Invalid or missing token"))
+}
+
 fn verify_password(password: &str, hash: &str) -> bool {
     let parsed_hash = PasswordHash::new(hash).unwrap();
     Argon2::default()
@@ -0,0 +1,145 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text, Timestamptz};
+use serde::Serialize;
+use std::collections::HashSet;
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+#[derive(Serialize, QueryableByName)]
+pub struct BlockDto {
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub blocked_at: DateTime<Utc>,
+}
+
+#[derive(QueryableByName)]
+struct BlockedResult {
+    #[diesel(sql_type = Bool)]
+    blocked: bool,
+}
+
+// Заблокировал ли кто-то из двоих другого. Блокировка действует в обе стороны:
+// ни сообщений, ни статуса, ни предложений обмена.
+pub fn is_blocked_between(conn: &mut PgConnection, a: &str, b: &str) -> QueryResult<bool> {
+    let query = r#"
+        SELECT EXISTS (
+            SELECT 1 FROM user_blocks
+            WHERE (blocker_login = $1 AND blocked_login = $2)
+               OR (blocker_login = $2 AND blocked_login = $1)
+        ) AS blocked
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Text, _>(a)
+        .bind::<Text, _>(b)
+        .get_result::<BlockedResult>(conn)
+        .map(|r| r.blocked)
+}
+
+#[derive(QueryableByName)]
+struct BlockedLogin {
+    #[diesel(sql_type = Text)]
+    login: String,
+}
+
+// Все, с кем у пользователя есть блокировка в любую сторону
+pub fn blocked_logins(conn: &mut PgConnection, login: &str) -> QueryResult<HashSet<String>> {
+    let query = r#"
+        SELECT blocked_login AS login FROM user_blocks WHERE blocker_login = $1
+        UNION
+        SELECT blocker_login AS login FROM user_blocks WHERE blocked_login = $1
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Text, _>(login)
+        .load::<BlockedLogin>(conn)
+        .map(|rows| rows.into_iter().map(|r| r.login).collect())
+}
+
+#[post("/users/{login}/block")]
+async fn block_user(pool: web::Data<DBPool>, req: HttpRequest, path: Path<String>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let blocked = path.into_inner();
+    if blocked == user_login {
+        return HttpResponse::BadRequest().body("Cannot block yourself");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = diesel::sql_query(
+        "INSERT INTO user_blocks (blocker_login, blocked_login) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind::<Text, _>(&user_login)
+    .bind::<Text, _>(&blocked)
+    .execute(conn);
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+            _,
+        )) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/users/{login}/unblock")]
+async fn unblock_user(pool: web::Data<DBPool>, req: HttpRequest, path: Path<String>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = diesel::sql_query("DELETE FROM user_blocks WHERE blocker_login = $1 AND blocked_login = $2")
+        .bind::<Text, _>(&user_login)
+        .bind::<Text, _>(path.into_inner())
+        .execute(conn);
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/blocks")]
+async fn get_blocks(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let query = r#"
+        SELECT blocked_login AS user_login, created_at AS blocked_at
+        FROM user_blocks
+        WHERE blocker_login = $1
+        ORDER BY created_at DESC
+    "#;
+
+    match diesel::sql_query(query).bind::<Text, _>(&user_login).load::<BlockDto>(conn) {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::db_error_response;
+use crate::auth::require_login;
+use crate::collection::{CountResult, IdResult};
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::listings::{is_valid_currency, ListingDto, LISTING_SELECT};
+use crate::DBPool;
+
+const NOT_FOUND: &str = "Buy order not found";
+const BUY_ORDER_STATUSES: [&str; 3] = ["open", "filled", "cancelled"];
+
+#[derive(Deserialize)]
+pub struct CreateBuyOrderRequest {
+    release_id: i32,
+    max_price: i32,
+    currency: Option<String>,
+    min_condition: Option<String>,
+    quantity: Option<i32>,
+    note: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBuyOrderRequest {
+    max_price: Option<i32>,
+    min_condition: Option<String>,
+    quantity: Option<i32>,
+    note: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct BuyOrderQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct BuyOrderDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub buyer_login: String,
+
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    pub max_price: i32,
+
+    #[diesel(sql_type = Text)]
+    pub currency: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub min_condition: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    pub quantity: i32,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub note: Option<String>,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[diesel(sql_type = Text)]
+    pub status: String,
+
+    #[diesel(sql_type = BigInt)]
+    pub match_count: i64,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct BuyOrderListResponse {
+    items: Vec<BuyOrderDto>,
+    total_count: i64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct BuyOrderMatch {
+    #[diesel(sql_type = Integer)]
+    pub buy_order_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub listing_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub buyer_login: String,
+}
+
+const BUY_ORDER_SELECT: &str = r#"
+    SELECT
+        bo.id,
+        bo.buyer_login,
+        bo.release_id,
+        prod.id AS product_id,
+        prod.name AS product_name,
+        p.name AS platform_name,
+        reg.name AS region_name,
+        '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+        bo.max_price,
+        bo.currency,
+        bo.min_condition,
+        bo.quantity,
+        bo.note,
+        bo.expires_at,
+        bo.status,
+        (
+            SELECT COUNT(*)
+            FROM buy_order_matches AS m
+            INNER JOIN listings AS l ON l.id = m.listing_id
+            WHERE m.buy_order_id = bo.id AND l.status = 'active'
+        ) AS match_count,
+        bo.created_at,
+        bo.updated_at
+    FROM buy_orders AS bo
+    INNER JOIN releases AS r ON r.id = bo.release_id
+    INNER JOIN products AS prod ON prod.id = r.product_id
+    INNER JOIN platforms AS p ON p.id = r.platform
+    LEFT JOIN regions AS reg ON reg.id = r.release_region
+"#;
+
+// Ищет пересечения активных объявлений с открытыми заявками и сохраняет новые.
+// Можно ограничить поиск конкретным объявлением или конкретной заявкой.
+pub fn record_matches(
+    conn: &mut PgConnection,
+    listing_id: Option<i32>,
+    buy_order_id: Option<i32>,
+) -> Result<Vec<BuyOrderMatch>, diesel::result::Error> {
+    let query = r#"
+        WITH found AS (
+            INSERT INTO buy_order_matches (buy_order_id, listing_id)
+            SELECT bo.id, l.id
+            FROM listings AS l
+            INNER JOIN buy_orders AS bo ON bo.release_id = l.release_id
+            LEFT JOIN item_conditions AS lc ON lc.code = l.condition
+            LEFT JOIN item_conditions AS bc ON bc.code = bo.min_condition
+            WHERE ($1::int IS NULL OR l.id = $1)
+              AND ($2::int IS NULL OR bo.id = $2)
+              AND l.status = 'active'
+              AND (l.expires_at IS NULL OR l.expires_at > NOW())
+              AND bo.status = 'open'
+              AND (bo.expires_at IS NULL OR bo.expires_at > NOW())
+              AND bo.buyer_login <> l.seller_login
+              AND bo.currency = l.currency
+              AND l.price IS NOT NULL
+              AND l.price <= bo.max_price
+              AND (bo.min_condition IS NULL OR lc.rank <= bc.rank)
+            ON CONFLICT DO NOTHING
+            RETURNING buy_order_id, listing_id
+        )
+        SELECT f.buy_order_id, f.listing_id, bo.buyer_login
+        FROM found AS f
+        INNER JOIN buy_orders AS bo ON bo.id = f.buy_order_id
+    "#;
+
+    let matches = diesel::sql_query(query)
+        .bind::<Nullable<Integer>, _>(listing_id)
+        .bind::<Nullable<Integer>, _>(buy_order_id)
+        .load::<BuyOrderMatch>(conn)?;
+
+    for m in &matches {
+        log::info!(
+            "Listing {} matches buy order {} of {}",
+            m.listing_id, m.buy_order_id, m.buyer_login
+        );
+    }
+    Ok(matches)
+}
+
+fn validate_order(max_price: Option<i32>, currency: Option<&str>, quantity: Option<i32>) -> Result<(), HttpResponse> {
+    if max_price.is_some_and(|p| p < 0) {
+        return Err(HttpResponse::BadRequest().body("Price must not be negative"));
+    }
+    if quantity.is_some_and(|q| q < 1) {
+        return Err(HttpResponse::BadRequest().body("Quantity must be at least 1"));
+    }
+    if currency.is_some_and(|c| !is_valid_currency(c)) {
+        return Err(HttpResponse::BadRequest().body("Currency must be an ISO 4217 code"));
+    }
+    Ok(())
+}
+
+fn load_buy_order(conn: &mut PgConnection, buy_order_id: i32) -> Result<BuyOrderDto, diesel::result::Error> {
+    let query = format!("{} WHERE bo.id = $1", BUY_ORDER_SELECT);
+
+    diesel::sql_query(query)
+        .bind::<Integer, _>(buy_order_id)
+        .get_result::<BuyOrderDto>(conn)
+}
+
+#[post("/buy-orders")]
+async fn create_buy_order(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    data: web::Json<CreateBuyOrderRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if let Err(resp) = validate_order(Some(data.max_price), data.currency.as_deref(), data.quantity) {
+        return resp;
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let insert_query = r#"
+        INSERT INTO buy_orders (buyer_login, release_id, max_price, currency, min_condition, quantity, note, expires_at)
+        VALUES ($1, $2, $3, COALESCE($4, 'RUB'), $5, COALESCE($6, 1), $7, $8)
+        RETURNING id
+    "#;
+
+    let result = conn.transaction(|conn| {
+        let row = diesel::sql_query(insert_query)
+            .bind::<Text, _>(&user_login)
+            .bind::<Integer, _>(data.release_id)
+            .bind::<Integer, _>(data.max_price)
+            .bind::<Nullable<Text>, _>(data.currency.as_deref())
+            .bind::<Nullable<Text>, _>(data.min_condition.as_deref())
+            .bind::<Nullable<Integer>, _>(data.quantity)
+            .bind::<Nullable<Text>, _>(data.note.as_deref())
+            .bind::<Nullable<Timestamptz>, _>(data.expires_at)
+            .get_result::<IdResult>(conn)?;
+        record_matches(conn, None, Some(row.id))?;
+        load_buy_order(conn, row.id)
+    });
+
+    match result {
+        Ok(order) => HttpResponse::Created().json(order),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[post("/buy-orders/{id}/update")]
+async fn update_buy_order(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<UpdateBuyOrderRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if let Err(resp) = validate_order(data.max_price, None, data.quantity) {
+        return resp;
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let update_query = r#"
+        UPDATE buy_orders
+        SET max_price = COALESCE($3, max_price),
+            min_condition = COALESCE($4, min_condition),
+            quantity = COALESCE($5, quantity),
+            note = COALESCE($6, note),
+            expires_at = COALESCE($7, expires_at),
+            updated_at = NOW()
+        WHERE id = $1 AND buyer_login = $2 AND status = 'open'
+        RETURNING id
+    "#;
+
+    let result = conn.transaction(|conn| {
+        let row = diesel::sql_query(update_query)
+            .bind::<Integer, _>(path.into_inner())
+            .bind::<Text, _>(&user_login)
+            .bind::<Nullable<Integer>, _>(data.max_price)
+            .bind::<Nullable<Text>, _>(data.min_condition.as_deref())
+            .bind::<Nullable<Integer>, _>(data.quantity)
+            .bind::<Nullable<Text>, _>(data.note.as_deref())
+            .bind::<Nullable<Timestamptz>, _>(data.expires_at)
+            .get_result::<IdResult>(conn)?;
+        record_matches(conn, None, Some(row.id))?;
+        load_buy_order(conn, row.id)
+    });
+
+    match result {
+        Ok(order) => HttpResponse::Ok().json(order),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[post("/buy-orders/{id}/cancel")]
+async fn cancel_buy_order(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let update_query = r#"
+        UPDATE buy_orders
+        SET status = 'cancelled', updated_at = NOW()
+        WHERE id = $1 AND buyer_login = $2 AND status = 'open'
+        RETURNING id
+    "#;
+
+    let result = diesel::sql_query(update_query)
+        .bind::<Integer, _>(path.into_inner())
+        .bind::<Text, _>(&user_login)
+        .get_result::<IdResult>(conn)
+        .and_then(|row| load_buy_order(conn, row.id));
+
+    match result {
+        Ok(order) => HttpResponse::Ok().json(order),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[get("/buy-orders/my")]
+async fn get_my_buy_orders(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<BuyOrderQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if query.status.as_deref().is_some_and(|s| !BUY_ORDER_STATUSES.contains(&s)) {
+        return HttpResponse::BadRequest().body("Unknown buy order status");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = query.offset.unwrap_or(0);
+
+    let list_query = format!(
+        r#"{}
+        WHERE bo.buyer_login = $1 AND ($2::text IS NULL OR bo.status = $2)
+        ORDER BY bo.updated_at DESC, bo.id DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        BUY_ORDER_SELECT
+    );
+
+    let count_query = r#"
+        SELECT COUNT(*) AS total FROM buy_orders AS bo
+        WHERE bo.buyer_login = $1 AND ($2::text IS NULL OR bo.status = $2)
+    "#;
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<BuyOrderDto>(conn);
+
+    let count = diesel::sql_query(count_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .get_result::<CountResult>(conn);
+
+    match (items, count) {
+        (Ok(items), Ok(count)) => HttpResponse::Ok().json(BuyOrderListResponse {
+            items,
+            total_count: count.total,
+        }),
+        (Err(err), _) | (_, Err(err)) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+// Активные объявления, которые подходят под заявку
+#[get("/buy-orders/{id}/matches")]
+async fn get_buy_order_matches(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let query = format!(
+        r#"{}
+        INNER JOIN buy_order_matches AS m ON m.listing_id = l.id
+        INNER JOIN buy_orders AS bo ON bo.id = m.buy_order_id
+        WHERE m.buy_order_id = $1 AND bo.buyer_login = $2 AND l.status = 'active'
+        ORDER BY l.price ASC, m.created_at ASC
+        "#,
+        LISTING_SELECT
+    );
+
+    match diesel::sql_query(query)
+        .bind::<Integer, _>(path.into_inner())
+        .bind::<Text, _>(&user_login)
+        .load::<ListingDto>(conn)
+    {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn validate_order_accepts_empty_and_valid_values() {
+        assert!(validate_order(None, None, None).is_ok());
+        assert!(validate_order(Some(0), Some("EUR"), Some(1)).is_ok());
+    }
+
+    #[test]
+    async fn validate_order_rejects_bad_values() {
+        assert!(validate_order(Some(-100), None, None).is_err());
+        assert!(validate_order(None, None, Some(0)).is_err());
+        assert!(validate_order(None, None, Some(-2)).is_err());
+        assert!(validate_order(None, Some("eur"), None).is_err());
+        assert!(validate_order(None, Some("E1R"), None).is_err());
+    }
+}
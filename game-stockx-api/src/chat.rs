@@ -5,11 +5,12 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager};
 use diesel::PgConnection;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use actix_web_actors::ws::ProtocolError;
-use diesel::sql_types::{Text, Timestamptz};
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text, Timestamptz};
 use crate::constants::{CONNECTION_POOL_ERROR};
-use crate::auth::{verify_jwt};
+use crate::api_error::ApiError;
+use crate::auth::{require_login, verify_jwt};
 use actix_web::http::header;
 use crate::{DBPool};
 use actix_rt::task::spawn_blocking;
@@ -17,57 +18,443 @@ use chrono::{DateTime, Utc};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use crate::metrics::{WS_CONNECTIONS, CHAT_MESSAGES_SENT};
+use crate::notifications::{notify_offline_message, NotificationDto, TYPE_OFFLINE_MESSAGE};
+use crate::blocks::is_blocked_between;
+use crate::chat_attachments::{attachment_ref, load_attachments, resolve_attachment};
+use crate::chat_bus::{self, ClusterEvent};
+use crate::chat_history::{HistoryCursor, HistoryRow};
+use crate::chat_limits::ChatLimits;
+use crate::conversations::{member_logins, store_conversation_message};
+use crate::chat_protocol::{
+    AttachmentRef, ChatMessage, ClientFrame, ConversationMessage, ErrorFrame, InboundFrame, MessageAck, MessageDeleted, MessageEdited, OutboundFrame, ReadReceipt, ReplayDone, ServerFrame,
+    TypingEvent,
+    ERROR_BAD_FRAME, ERROR_BLOCKED, ERROR_INTERNAL, ERROR_INVALID_ATTACHMENT, ERROR_INVALID_MESSAGE, ERROR_UNKNOWN_RECIPIENT,
+    MAX_CLIENT_ID_LEN, PROTOCOL_VERSION,
+};
+use crate::presence;
+use crate::redis::RedisPool;
 
-#[derive(Message, Serialize, Deserialize, Debug, Clone)]
-#[rtype(result = "()")]
-pub struct ClientMessage {
-    pub sender: String,
-    pub recipient: String,
-    pub body: String,
-    pub created_at: String,
-}
+// Сколько пропущенных сообщений досылается при переподключении; остальное - через GET /messages
+const REPLAY_LIMIT: usize = 200;
+// Как часто из лимитов чата убираются счётчики неактивных пользователей
+const LIMITS_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub enum ChatCommand {
     Connect {
         login: String,
-        addr: Recipient<ClientMessage>,
+        addr: Addr<ChatSession>,
     },
     Disconnect {
         login: String,
-        addr: Recipient<ClientMessage>,
+        addr: Addr<ChatSession>,
     },
     SendMessage {
         sender: String,
         recipient: String,
         body: String,
+        attachment: Option<AttachmentRef>,
+        client_id: Option<String>,
+        // сессия отправителя, которой уходит подтверждение или ошибка
+        reply_to: Addr<ChatSession>,
+    },
+    // Сообщение в беседу: доставляется всем участникам, кроме отправителя
+    SendToConversation {
+        sender: String,
+        conversation_id: i32,
+        body: String,
+        attachment: Option<AttachmentRef>,
+        client_id: Option<String>,
+        reply_to: Addr<ChatSession>,
+    },
+    // Индикатор набора текста; не доходит, если между собеседниками есть блокировка
+    Typing {
+        sender: String,
+        recipient: String,
+    },
+    // Уведомления уже сохранены в базе, остаётся доставить их в живые сессии
+    Notify {
+        notifications: Vec<NotificationDto>,
+    },
+    // Читатель открыл диалог с собеседником
+    MarkRead {
+        reader: String,
+        companion: String,
+    },
+    // Досылка сообщений, пришедших после last_seen_id, в только что подключённую сессию
+    Replay {
+        login: String,
+        after_id: i32,
+        reply_to: Addr<ChatSession>,
+    },
+    // Доставка кадра на этом узле и публикация для остальных
+    Deliver {
+        event: ClusterEvent,
+    },
+    // Событие, пришедшее от другого узла через Redis
+    Remote {
+        event: ClusterEvent,
     },
 }
 
+// Сессии хранятся локально, а события рассылаются всем узлам через Redis,
+// поэтому несколько экземпляров API могут работать за балансировщиком.
 pub struct ChatServer {
-    sessions: HashMap<String, HashSet<Recipient<ClientMessage>>>,
+    sessions: HashMap<String, HashSet<Addr<ChatSession>>>,
     db_pool: r2d2::Pool<ConnectionManager<PgConnection>>,
+    redis_pool: RedisPool,
+    redis_url: String,
+    node_id: String,
+    // Пользователи, для которых узел мог оставить отметку в Redis
+    reported: HashSet<String>,
+    // Очереди сообщений по отправителям; ключ есть, пока сохраняется сообщение
+    sending: HashMap<String, VecDeque<OutgoingMessage>>,
+    // Обновления присутствия по пользователям; ключ есть, пока обновление выполняется
+    syncing: HashMap<String, PendingPresence>,
+    limits: Arc<ChatLimits>,
+}
+
+// Обновление присутствия, запрошенное, пока предыдущее ещё не завершилось.
+// Каждое обновление берёт текущее число сессий, поэтому повторные запросы
+// сливаются в одно.
+#[derive(Default)]
+struct PendingPresence {
+    requested: bool,
+    // отметку пользователя снял упавший узел: уход из сети надо разослать,
+    // даже если этот узел его в сети не отмечал
+    lost: bool,
+}
+
+// Куда отправлено сообщение: собеседнику или в беседу
+enum Destination {
+    User(String),
+    Conversation(i32),
+}
+
+struct OutgoingMessage {
+    sender: String,
+    destination: Destination,
+    body: String,
+    attachment: Option<AttachmentRef>,
+    client_id: Option<String>,
+    reply_to: Addr<ChatSession>,
 }
 
 impl ChatServer {
-    pub fn new(db_pool: r2d2::Pool<ConnectionManager<PgConnection>>) -> ChatServer {
+    pub fn new(
+        db_pool: r2d2::Pool<ConnectionManager<PgConnection>>,
+        redis_pool: RedisPool,
+        redis_url: String,
+        limits: Arc<ChatLimits>,
+    ) -> ChatServer {
         WS_CONNECTIONS.set(0);
         ChatServer {
             sessions: HashMap::new(),
             db_pool,
+            redis_pool,
+            redis_url,
+            node_id: uuid::Uuid::new_v4().to_string(),
+            reported: HashSet::new(),
+            sending: HashMap::new(),
+            syncing: HashMap::new(),
+            limits,
+        }
+    }
+
+    fn deliver_local(&self, event: &ClusterEvent) {
+        for login in &event.recipients {
+            if let Some(sessions) = self.sessions.get(login) {
+                // Отправляем каждому активному соединению
+                for addr in sessions {
+                    addr.do_send(event.frame.clone());
+                }
+            }
+        }
+    }
+
+    // Доставка своим сессиям и публикация для остальных узлов
+    fn broadcast(&self, event: ClusterEvent) {
+        self.deliver_local(&event);
+
+        let redis_pool = self.redis_pool.clone();
+        let node_id = self.node_id.clone();
+        actix_rt::spawn(async move {
+            chat_bus::publish(&redis_pool, &node_id, event).await;
+        });
+    }
+
+    // Ставит в очередь обновление отметки пользователя в Redis. Обновления одного
+    // пользователя идут строго по очереди, чтобы отметка подключения не легла
+    // поверх последовавшего за ним отключения; остальные команды сервер
+    // при этом обрабатывает как обычно.
+    fn sync_presence(&mut self, login: String, lost: bool, ctx: &mut Context<Self>) {
+        match self.syncing.get_mut(&login) {
+            Some(pending) => {
+                pending.requested = true;
+                pending.lost |= lost;
+            }
+            None => {
+                self.syncing.insert(login.clone(), PendingPresence::default());
+                self.update_presence(login, lost, ctx);
+            }
+        }
+    }
+
+    // Освобождает очередь пользователя или запускает отложенное обновление
+    fn presence_done(&mut self, login: String, ctx: &mut Context<Self>) {
+        if let Some(pending) = self.syncing.remove(&login).filter(|pending| pending.requested) {
+            self.syncing.insert(login.clone(), PendingPresence::default());
+            self.update_presence(login, pending.lost, ctx);
+        }
+    }
+
+    // Записывает число сессий пользователя на этом узле. Если пользователь при этом
+    // вошёл в сеть или вышел из неё на всех узлах, оповещает его собеседников.
+    fn update_presence(&mut self, login: String, lost: bool, ctx: &mut Context<Self>) {
+        let sessions = self.sessions.get(&login).map_or(0, |s| s.len());
+        if sessions > 0 {
+            self.reported.insert(login.clone());
+        }
+        let pool = self.db_pool.clone();
+        let redis_pool = self.redis_pool.clone();
+        let node_id = self.node_id.clone();
+        let queued = login.clone();
+
+        let update = async move {
+            let (online_before, online_after) =
+                match chat_bus::update_presence(&redis_pool, &node_id, &login, sessions).await {
+                    Ok(online) => online,
+                    Err(e) => {
+                        eprintln!("Failed to update presence: {}", e);
+                        return None;
+                    }
+                };
+            if online_before == online_after && (online_after || !lost) {
+                return None;
+            }
+
+            let changed = spawn_blocking(move || {
+                let conn = &mut pool.get().map_err(|err| format!("{:?}", err))?;
+                presence::presence_changed(conn, &login, online_after).map_err(|err| format!("{:?}", err))
+            })
+            .await;
+
+            match changed {
+                Ok(Ok(event)) => event,
+                Ok(Err(err)) => {
+                    eprintln!("Failed to update presence: {}", err);
+                    None
+                }
+                Err(err) => {
+                    eprintln!("Presence task failed: {:?}", err);
+                    None
+                }
+            }
+        };
+
+        ctx.spawn(update.into_actor(self).map(move |event, act, ctx| {
+            if let Some(event) = event {
+                act.broadcast(event);
+            }
+            act.presence_done(queued, ctx);
+        }));
+    }
+
+    // Продлевает пульс узла и переписывает его отметки по текущим сессиям.
+    // Переписываемые пользователи на это время занимают свои очереди присутствия,
+    // а те, у кого обновление уже идёт, пропускаются: их отметку запишет оно.
+    // Затем снимаются отметки упавших узлов, и их пользователи проходят
+    // через очередь как ушедшие из сети.
+    fn heartbeat(&mut self, ctx: &mut Context<Self>) {
+        let local: HashMap<String, usize> = self
+            .sessions
+            .iter()
+            .filter(|(login, _)| !self.syncing.contains_key(*login))
+            .map(|(login, sessions)| (login.clone(), sessions.len()))
+            .collect();
+        let stale: Vec<String> = self
+            .reported
+            .iter()
+            .filter(|login| !self.sessions.contains_key(*login) && !self.syncing.contains_key(*login))
+            .cloned()
+            .collect();
+        for login in local.keys().chain(&stale) {
+            self.syncing.insert(login.clone(), PendingPresence::default());
+        }
+        let redis_pool = self.redis_pool.clone();
+        let node_id = self.node_id.clone();
+        let written_stale = stale.clone();
+
+        let beat = async move {
+            if !chat_bus::heartbeat(&redis_pool, &node_id, &local, &written_stale).await {
+                return (local, false, Vec::new());
+            }
+            let lost = chat_bus::reap_dead_nodes(&redis_pool).await.unwrap_or_else(|e| {
+                eprintln!("Failed to clean up dead chat nodes: {}", e);
+                Vec::new()
+            });
+            (local, true, lost)
+        };
+
+        ctx.spawn(beat.into_actor(self).map(move |(local, written, lost), act, ctx| {
+            if written {
+                for login in &stale {
+                    act.reported.remove(login);
+                }
+            }
+            for login in local.into_keys().chain(stale) {
+                act.presence_done(login, ctx);
+            }
+            for login in lost {
+                act.sync_presence(login, true, ctx);
+            }
+        }));
+    }
+
+    // Ставит сообщение в очередь отправителя или сразу отправляет, если очередь пуста
+    fn enqueue(&mut self, message: OutgoingMessage, ctx: &mut Context<Self>) {
+        match self.sending.get_mut(&message.sender) {
+            Some(queue) => queue.push_back(message),
+            None => {
+                self.sending.insert(message.sender.clone(), VecDeque::new());
+                self.send_next(message, ctx);
+            }
         }
     }
+
+    // Сохраняет сообщение, подтверждает его отправителю и доставляет получателям.
+    // Следующее сообщение того же отправителя, личное или в беседу, берётся
+    // из очереди только после этого, поэтому порядок сообщений не меняется.
+    // Нужно ли уведомление о пропущенном личном сообщении, выясняется уже
+    // после сохранения и очередь не задерживает.
+    fn send_next(&mut self, message: OutgoingMessage, ctx: &mut Context<Self>) {
+        let pool = self.db_pool.clone();
+        let redis_pool = self.redis_pool.clone();
+        let online_here = match &message.destination {
+            Destination::User(recipient) => self.sessions.contains_key(recipient),
+            Destination::Conversation(_) => false,
+        };
+        let server = ctx.address();
+        let sender = message.sender.clone();
+
+        let store = async move {
+            let OutgoingMessage {
+                sender,
+                destination,
+                body,
+                attachment,
+                client_id,
+                reply_to,
+            } = message;
+            let frame_client_id = client_id.clone();
+            let store_pool = pool.clone();
+            let ack_client_id = client_id.clone();
+            let ack = move |id, created_at, duplicate| MessageAck {
+                client_id: ack_client_id,
+                id,
+                created_at,
+                duplicate,
+            };
+
+            let stored = spawn_blocking(move || match destination {
+                Destination::User(recipient) => store_message(
+                    &store_pool,
+                    &sender,
+                    &recipient,
+                    &body,
+                    attachment.as_ref(),
+                    client_id.as_deref(),
+                )
+                .map(|(message, duplicate)| {
+                    let recipients = vec![message.recipient.clone()];
+                    (ack(message.id, message.created_at, duplicate), ServerFrame::Message(message), recipients)
+                }),
+                Destination::Conversation(conversation_id) => store_conversation_message(
+                    &store_pool,
+                    &sender,
+                    conversation_id,
+                    &body,
+                    attachment.as_ref(),
+                    client_id.as_deref(),
+                )
+                .map(|(message, duplicate, recipients)| {
+                    (
+                        ack(message.id, message.created_at, duplicate),
+                        ServerFrame::ConversationMessage(message),
+                        recipients,
+                    )
+                }),
+            })
+            .await;
+
+            match stored {
+                Ok(Ok((ack, frame, recipients))) => {
+                    let duplicate = ack.duplicate;
+                    reply_to.do_send(ServerFrame::Ack(ack));
+
+                    // повторная отправка уже доставленного сообщения
+                    if duplicate {
+                        return;
+                    }
+
+                    if !online_here
+                        && let ServerFrame::Message(message) = &frame
+                    {
+                        actix_rt::spawn(notify_if_offline(pool, redis_pool, message.clone()));
+                    }
+                    CHAT_MESSAGES_SENT.inc();
+                    server.do_send(ChatCommand::Deliver {
+                        event: ClusterEvent { recipients, frame },
+                    });
+                }
+                Ok(Err(error)) => reply_to.do_send(ServerFrame::Error(error)),
+                Err(err) => {
+                    eprintln!("Message persistence task failed: {:?}", err);
+                    reply_to.do_send(ServerFrame::error(
+                        ERROR_INTERNAL,
+                        "Message could not be saved",
+                        frame_client_id,
+                    ));
+                }
+            }
+        };
+
+        ctx.spawn(store.into_actor(self).map(move |_, act, ctx| {
+            match act.sending.get_mut(&sender).and_then(|queue| queue.pop_front()) {
+                Some(next) => act.send_next(next, ctx),
+                None => {
+                    act.sending.remove(&sender);
+                }
+            }
+        }));
+    }
 }
 
+
 impl Actor for ChatServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        actix_rt::spawn(chat_bus::run_subscriber(
+            self.redis_url.clone(),
+            self.node_id.clone(),
+            ctx.address(),
+        ));
+
+        self.heartbeat(ctx);
+        ctx.run_interval(
+            std::time::Duration::from_secs(chat_bus::NODE_TTL_SECS / 3),
+            |act, ctx| act.heartbeat(ctx),
+        );
+        ctx.run_interval(LIMITS_PRUNE_INTERVAL, |act, _| act.limits.prune());
+    }
 }
 
 impl Handler<ChatCommand> for ChatServer {
     type Result = ();
 
-    fn handle(&mut self, msg: ChatCommand, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: ChatCommand, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
             ChatCommand::Connect { login, addr } => {
                 println!("ConnectedWS: {}", login.clone());
@@ -75,13 +462,14 @@ impl Handler<ChatCommand> for ChatServer {
                 let was_online = self.sessions.contains_key(&login);
 
                 self.sessions
-                    .entry(login)
+                    .entry(login.clone())
                     .or_insert_with(HashSet::new)
                     .insert(addr);
 
                 if !was_online {
                     WS_CONNECTIONS.inc();
                 }
+                self.sync_presence(login, false, ctx);
             }
             ChatCommand::Disconnect { login, addr } => {
                 if let Some(sessions) = self.sessions.get_mut(&login) {
@@ -92,55 +480,534 @@ impl Handler<ChatCommand> for ChatServer {
                     }
                 }
                 println!("DisconnectedWS: {}", login);
+                self.sync_presence(login, false, ctx);
             }
             ChatCommand::SendMessage {
                 sender,
                 recipient,
                 body,
+                attachment,
+                client_id,
+                reply_to,
             } => {
-                CHAT_MESSAGES_SENT.inc();
+                self.enqueue(
+                    OutgoingMessage {
+                        sender,
+                        destination: Destination::User(recipient),
+                        body,
+                        attachment,
+                        client_id,
+                        reply_to,
+                    },
+                    ctx,
+                );
+            }
+            ChatCommand::SendToConversation {
+                sender,
+                conversation_id,
+                body,
+                attachment,
+                client_id,
+                reply_to,
+            } => {
+                self.enqueue(
+                    OutgoingMessage {
+                        sender,
+                        destination: Destination::Conversation(conversation_id),
+                        body,
+                        attachment,
+                        client_id,
+                        reply_to,
+                    },
+                    ctx,
+                );
+            }
+            ChatCommand::Typing { sender, recipient } => {
                 let pool = self.db_pool.clone();
-                
-                let message = ClientMessage {
-                    sender: sender.clone(),
-                    recipient: recipient.clone(),
-                    body: body.clone(),
-                    created_at: Utc::now().to_rfc3339(),
-                };
+                let server = ctx.address();
+
+                spawn_blocking(move || {
+                    let blocked = pool
+                        .get()
+                        .map_err(|err| format!("{:?}", err))
+                        .and_then(|mut conn| {
+                            is_blocked_between(&mut conn, &sender, &recipient).map_err(|err| format!("{:?}", err))
+                        });
 
-                // Отправка сообщения пользователю, если он онлайн
-                if let Some(sessions) = self.sessions.get(&recipient) {
-                    // Отправляем каждому активному соединению
-                    for addr in sessions {
-                        let _ = addr.do_send(message.clone());
+                    match blocked {
+                        Ok(false) => server.do_send(ChatCommand::Deliver {
+                            event: ClusterEvent {
+                                recipients: vec![recipient.clone()],
+                                frame: ServerFrame::Typing(TypingEvent { sender, recipient }),
+                            },
+                        }),
+                        Ok(true) => {}
+                        Err(err) => eprintln!("Failed to check user block: {}", err),
                     }
+                });
+            }
+            ChatCommand::Notify { notifications } => {
+                for notification in notifications {
+                    self.broadcast(ClusterEvent {
+                        recipients: vec![notification.user_login.clone()],
+                        frame: ServerFrame::Notification(notification),
+                    });
                 }
+            }
+            ChatCommand::MarkRead { reader, companion } => {
+                let pool = self.db_pool.clone();
+                let server = ctx.address();
 
                 spawn_blocking(move || {
-                    // Сохранение сообщения в базу данных
-                    let query = r#"
-                        INSERT INTO messages (sender_login, recipient_login, body)
-                        VALUES ($1, $2, $3)
-                    "#;
+                    let conn = &mut match pool.get() {
+                        Ok(conn) => conn,
+                        Err(err) => {
+                            eprintln!("Failed to mark messages as read: {:?}", err);
+                            return;
+                        }
+                    };
+                    match mark_dialog_read(conn, &reader, &companion) {
+                        Ok(Some(receipt)) => server.do_send(ChatCommand::Deliver {
+                            event: receipt_event(receipt),
+                        }),
+                        Ok(None) => {}
+                        Err(err) => eprintln!("Failed to mark messages as read: {:?}", err),
+                    }
+                });
+            }
+            ChatCommand::Replay {
+                login,
+                after_id,
+                reply_to,
+            } => {
+                let pool = self.db_pool.clone();
 
-                    // Выполняем запрос с привязкой параметров
-                    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
-                    diesel::sql_query(query)
-                        .bind::<diesel::sql_types::Text, _>(sender)  // Sender
-                        .bind::<diesel::sql_types::Text, _>(recipient)  // Recipient
-                        .bind::<diesel::sql_types::Text, _>(body)  // Body
-                        .execute(conn)
-                        .expect("Error saving message to DB");
+                spawn_blocking(move || {
+                    let loaded = pool
+                        .get()
+                        .map_err(|err| format!("{:?}", err))
+                        .and_then(|mut conn| {
+                            load_missed_messages(&mut conn, &login, after_id).map_err(|err| format!("{:?}", err))
+                        });
+
+                    let mut messages = match loaded {
+                        Ok(messages) => messages,
+                        Err(err) => {
+                            eprintln!("Failed to replay messages: {}", err);
+                            reply_to.do_send(ServerFrame::error(
+                                ERROR_INTERNAL,
+                                "Missed messages could not be loaded",
+                                None,
+                            ));
+                            return;
+                        }
+                    };
+
+                    let has_more = messages.len() > REPLAY_LIMIT;
+                    messages.truncate(REPLAY_LIMIT);
+                    let count = messages.len();
+                    let last_id = messages.last().and_then(|frame| match frame {
+                        ServerFrame::Message(m) => Some(m.id),
+                        ServerFrame::ConversationMessage(m) => Some(m.id),
+                        _ => None,
+                    });
+
+                    for frame in messages {
+                        reply_to.do_send(frame);
+                    }
+                    reply_to.do_send(ServerFrame::ReplayDone(ReplayDone {
+                        after_id,
+                        count,
+                        last_id,
+                        has_more,
+                    }));
                 });
             }
+            ChatCommand::Deliver { event } => {
+                self.broadcast(event);
+            }
+            ChatCommand::Remote { event } => {
+                self.deliver_local(&event);
+            }
         }
     }
 }
 
+#[derive(QueryableByName)]
+pub struct SavedMessage {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Bool)]
+    pub duplicate: bool,
+}
+
+// Вставляет сообщение собеседнику (recipient) или в беседу (conversation_id).
+// Повтор client_id в том же диалоге возвращает ранее сохранённую строку с duplicate;
+// NotFound - client_id уже занят сообщением в другом диалоге.
+pub fn insert_message(
+    conn: &mut PgConnection,
+    sender: &str,
+    recipient: Option<&str>,
+    conversation_id: Option<i32>,
+    body: &str,
+    attachment: Option<&AttachmentRef>,
+    client_id: Option<&str>,
+) -> QueryResult<SavedMessage> {
+    let query = r#"
+        WITH inserted AS (
+            INSERT INTO messages (sender_login, recipient_login, conversation_id, body, client_id, attachment_type, attachment_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (sender_login, client_id) WHERE client_id IS NOT NULL DO NOTHING
+            RETURNING id, created_at
+        )
+        SELECT id, created_at, FALSE AS duplicate FROM inserted
+        UNION ALL
+        SELECT id, created_at, TRUE AS duplicate
+        FROM messages
+        WHERE sender_login = $1
+          AND recipient_login IS NOT DISTINCT FROM $2
+          AND conversation_id IS NOT DISTINCT FROM $3
+          AND client_id = $5
+          AND NOT EXISTS (SELECT 1 FROM inserted)
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Text, _>(sender)
+        .bind::<Nullable<Text>, _>(recipient)
+        .bind::<Nullable<Integer>, _>(conversation_id)
+        .bind::<Text, _>(body)
+        .bind::<Nullable<Text>, _>(client_id)
+        .bind::<Nullable<Text>, _>(attachment.map(|a| a.kind()))
+        .bind::<Nullable<Integer>, _>(attachment.map(|a| a.id()))
+        .get_result::<SavedMessage>(conn)
+}
+
+// Сбой при сохранении сообщения: подробности в лог, отправителю - общая ошибка
+pub fn internal_error(err: &dyn std::fmt::Debug, client_id: Option<&str>) -> ErrorFrame {
+    eprintln!("Error saving message to DB: {:?}", err);
+    ErrorFrame::new(ERROR_INTERNAL, "Message could not be saved", client_id.map(str::to_string))
+}
+
+// Ошибка insert_message в виде кадра для отправителя
+pub fn insert_error(err: diesel::result::Error, client_id: Option<&str>) -> ErrorFrame {
+    match err {
+        diesel::result::Error::NotFound => ErrorFrame::new(
+            ERROR_INVALID_MESSAGE,
+            "client_id is already used by another message",
+            client_id.map(str::to_string),
+        ),
+        other => internal_error(&other, client_id),
+    }
+}
+
+// Сохраняет сообщение. Повтор с уже известным client_id возвращает ранее сохранённое сообщение.
+fn store_message(
+    pool: &DBPool,
+    sender: &str,
+    recipient: &str,
+    body: &str,
+    attachment: Option<&AttachmentRef>,
+    client_id: Option<&str>,
+) -> Result<(ChatMessage, bool), ErrorFrame> {
+    let conn = &mut pool.get().map_err(|err| internal_error(&err, client_id))?;
+
+    if is_blocked_between(conn, sender, recipient).map_err(|err| internal_error(&err, client_id))? {
+        return Err(ErrorFrame::new(
+            ERROR_BLOCKED,
+            "Messaging between these users is blocked",
+            client_id.map(str::to_string),
+        ));
+    }
+
+    let card = match attachment {
+        Some(reference) => match resolve_attachment(conn, reference, sender, Some(recipient)) {
+            Ok(Some(card)) => Some(card),
+            Ok(None) => {
+                return Err(ErrorFrame::new(
+                    ERROR_INVALID_ATTACHMENT,
+                    format!("Attached {} not found", reference.kind()),
+                    client_id.map(str::to_string),
+                ));
+            }
+            Err(err) => return Err(internal_error(&err, client_id)),
+        },
+        None => None,
+    };
+
+    let saved = insert_message(conn, sender, Some(recipient), None, body, attachment, client_id).map_err(|err| match err {
+        diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _) => {
+            ErrorFrame::new(ERROR_UNKNOWN_RECIPIENT, "Recipient not found", client_id.map(str::to_string))
+        }
+        other => insert_error(other, client_id),
+    })?;
+
+    let message = ChatMessage {
+        id: saved.id,
+        client_id: client_id.map(str::to_string),
+        sender: sender.to_string(),
+        recipient: recipient.to_string(),
+        body: body.to_string(),
+        attachment: card,
+        created_at: saved.created_at,
+    };
+    Ok((message, saved.duplicate))
+}
+
+// Если получатель не в сети ни на одном узле, оставляет ему уведомление.
+// Для сообщения из одного вложения в превью идёт название продукта.
+async fn notify_if_offline(pool: DBPool, redis_pool: RedisPool, message: ChatMessage) {
+    if chat_bus::is_online(&redis_pool, &message.recipient).await {
+        return;
+    }
+
+    let preview = match message.attachment.as_ref().and_then(|c| c.items.first()) {
+        Some(item) if message.body.trim().is_empty() => item.product_name.clone(),
+        _ => message.body.clone(),
+    };
+    let saved = spawn_blocking(move || {
+        let conn = &mut pool.get().map_err(|err| format!("{:?}", err))?;
+        notify_offline_message(conn, &message.recipient, &message.sender, &preview).map_err(|err| format!("{:?}", err))
+    })
+    .await;
+
+    match saved {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => eprintln!("Failed to save offline message notification: {}", err),
+        Err(err) => eprintln!("Offline notification task failed: {:?}", err),
+    }
+}
+
+#[derive(QueryableByName)]
+struct MissedMessage {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    client_id: Option<String>,
+
+    #[diesel(sql_type = Text)]
+    sender_login: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    recipient_login: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    conversation_id: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    body: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    attachment_type: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    attachment_id: Option<i32>,
+
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+}
+
+
+// Входящие, отправленные с других устройств и сообщения бесед (кроме тех, с кем блокировка)
+// после after_id, от старых к новым.
+// Загружается на одно больше лимита, чтобы понять, остались ли ещё.
+fn load_missed_messages(
+    conn: &mut PgConnection,
+    login: &str,
+    after_id: i32,
+) -> Result<Vec<ServerFrame>, diesel::result::Error> {
+    let missed = diesel::sql_query(
+        r#"
+        SELECT
+            id, client_id, sender_login, recipient_login, conversation_id, body,
+            attachment_type, attachment_id, created_at
+        FROM messages AS m
+        WHERE ((recipient_login = $1 AND NOT hidden_for_recipient)
+            OR (sender_login = $1 AND NOT hidden_for_sender)
+            OR (conversation_id IN (SELECT conversation_id FROM conversation_members WHERE user_login = $1)
+                AND NOT EXISTS (
+                    SELECT 1 FROM user_blocks AS b
+                    WHERE (b.blocker_login = $1 AND b.blocked_login = m.sender_login)
+                       OR (b.blocker_login = m.sender_login AND b.blocked_login = $1)
+                )))
+          AND id > $2
+          AND deleted_at IS NULL
+        ORDER BY id
+        LIMIT $3
+        "#,
+    )
+    .bind::<Text, _>(login)
+    .bind::<Integer, _>(after_id)
+    .bind::<BigInt, _>(REPLAY_LIMIT as i64 + 1)
+    .load::<MissedMessage>(conn)?;
+
+    let refs: Vec<AttachmentRef> = missed
+        .iter()
+        .filter_map(|m| attachment_ref(m.attachment_type.as_deref(), m.attachment_id))
+        .collect();
+    let cards = load_attachments(conn, &refs)?;
+
+    Ok(missed
+        .into_iter()
+        .filter_map(|m| {
+            let attachment = attachment_ref(m.attachment_type.as_deref(), m.attachment_id)
+                .and_then(|r| cards.get(&r).cloned());
+            match (m.recipient_login, m.conversation_id) {
+                (Some(recipient), _) => Some(ServerFrame::Message(ChatMessage {
+                    id: m.id,
+                    client_id: m.client_id,
+                    sender: m.sender_login,
+                    recipient,
+                    body: m.body,
+                    attachment,
+                    created_at: m.created_at,
+                })),
+                (None, Some(conversation_id)) => Some(ServerFrame::ConversationMessage(ConversationMessage {
+                    id: m.id,
+                    conversation_id,
+                    client_id: m.client_id,
+                    sender: m.sender_login,
+                    body: m.body,
+                    attachment,
+                    created_at: m.created_at,
+                })),
+                (None, None) => None,
+            }
+        })
+        .collect())
+}
+
+fn receipt_event(receipt: ReadReceipt) -> ClusterEvent {
+    ClusterEvent {
+        recipients: vec![receipt.companion.clone(), receipt.reader.clone()],
+        frame: ServerFrame::Read(receipt),
+    }
+}
+
 pub struct ChatSession {
     pub login: String,
     pub addr: Addr<ChatServer>,
     disconnected: Arc<AtomicBool>,
+    // последнее сообщение, которое клиент видел до переподключения
+    last_seen_id: Option<i32>,
+    limits: Arc<ChatLimits>,
+}
+
+impl ChatSession {
+    fn send_frame(ctx: &mut ws::WebsocketContext<Self>, frame: &ServerFrame) {
+        let outbound = OutboundFrame {
+            v: PROTOCOL_VERSION,
+            frame,
+        };
+        if let Ok(text) = serde_json::to_string(&outbound) {
+            ctx.text(text);
+        }
+    }
+
+    // Проверки, общие для личных сообщений и сообщений в беседы.
+    // Отклонённое сообщение получает кадр ошибки, и возвращается false.
+    fn accept_message(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        client_id: Option<&str>,
+        body: &str,
+        has_attachment: bool,
+    ) -> bool {
+        let invalid = if client_id.is_some_and(|id| id.is_empty() || id.len() > MAX_CLIENT_ID_LEN) {
+            Some(format!("client_id must be 1 to {} characters long", MAX_CLIENT_ID_LEN))
+        } else if body.trim().is_empty() && !has_attachment {
+            Some("Message body is empty".to_string())
+        } else {
+            None
+        };
+
+        if let Some(message) = invalid {
+            Self::send_frame(ctx, &ServerFrame::error(ERROR_INVALID_MESSAGE, message, client_id.map(str::to_string)));
+            return false;
+        }
+
+        if let Err(error) = self.limits.check_message(&self.login, body, client_id) {
+            Self::send_frame(ctx, &ServerFrame::Error(error));
+            return false;
+        }
+        true
+    }
+
+    // Разбор кадра клиента; на всё, что нельзя обработать, клиент получает кадр error
+    fn handle_frame(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let frame = match InboundFrame::parse(text) {
+            Ok(frame) => frame,
+            Err(error) => {
+                Self::send_frame(ctx, &ServerFrame::Error(error));
+                return;
+            }
+        };
+
+        match frame {
+            ClientFrame::Message {
+                client_id,
+                recipient,
+                body,
+                attachment,
+            } => {
+                if recipient == self.login {
+                    let error = ServerFrame::error(ERROR_INVALID_MESSAGE, "Cannot send a message to yourself", client_id);
+                    Self::send_frame(ctx, &error);
+                    return;
+                }
+                if !self.accept_message(ctx, client_id.as_deref(), &body, attachment.is_some()) {
+                    return;
+                }
+
+                self.addr.do_send(ChatCommand::SendMessage {
+                    sender: self.login.clone(),
+                    recipient,
+                    body,
+                    attachment,
+                    client_id,
+                    reply_to: ctx.address(),
+                });
+            }
+            ClientFrame::ConversationMessage {
+                client_id,
+                conversation_id,
+                body,
+                attachment,
+            } => {
+                if !self.accept_message(ctx, client_id.as_deref(), &body, attachment.is_some()) {
+                    return;
+                }
+
+                self.addr.do_send(ChatCommand::SendToConversation {
+                    sender: self.login.clone(),
+                    conversation_id,
+                    body,
+                    attachment,
+                    client_id,
+                    reply_to: ctx.address(),
+                });
+            }
+            ClientFrame::Typing { recipient } => {
+                self.addr.do_send(ChatCommand::Typing {
+                    sender: self.login.clone(),
+                    recipient,
+                });
+            }
+            ClientFrame::Read { companion } => {
+                self.addr.do_send(ChatCommand::MarkRead {
+                    reader: self.login.clone(),
+                    companion,
+                });
+            }
+            ClientFrame::Ping { nonce } => {
+                Self::send_frame(ctx, &ServerFrame::Pong { nonce });
+            }
+        }
+    }
 }
 
 impl Actor for ChatSession {
@@ -150,9 +1017,19 @@ impl Actor for ChatSession {
 
         self.addr.do_send(ChatCommand::Connect {
             login: self.login.clone(),
-            addr: ctx.address().recipient(),
+            addr: ctx.address(),
         });
 
+        // Сессия уже зарегистрирована, поэтому новые сообщения придут в неё напрямую;
+        // пересечения с досылкой клиент отбрасывает по id
+        if let Some(after_id) = self.last_seen_id {
+            self.addr.do_send(ChatCommand::Replay {
+                login: self.login.clone(),
+                after_id,
+                reply_to: ctx.address(),
+            });
+        }
+
         ctx.run_interval(std::time::Duration::from_secs(30), |_, ctx| {
             ctx.ping(b"keep-alive");
         });
@@ -163,7 +1040,7 @@ impl Actor for ChatSession {
         if !self.disconnected.swap(true, Ordering::SeqCst) {
             self.addr.do_send(ChatCommand::Disconnect {
                 login: self.login.clone(),
-                addr: ctx.address().recipient(), // Передаем свой addr
+                addr: ctx.address(), // Передаем свой addr
             });
         }
     }
@@ -173,13 +1050,11 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for ChatSession {
     fn handle(&mut self, msg: Result<ws::Message, ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Text(text)) => {
-                if let Ok(parsed) = serde_json::from_str::<ClientMessage>(&text) {
-                    self.addr.do_send(ChatCommand::SendMessage {
-                        sender: parsed.sender,
-                        recipient: parsed.recipient,
-                        body: parsed.body,
-                    });
+                if let Err(error) = self.limits.check_frame(&self.login, &text) {
+                    Self::send_frame(ctx, &ServerFrame::Error(error));
+                    return;
                 }
+                self.handle_frame(&text, ctx);
             }
             Ok(ws::Message::Ping(msg)) => {
                 ctx.pong(&msg);
@@ -192,7 +1067,7 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for ChatSession {
                 ctx.stop();
             }
             Ok(ws::Message::Binary(_)) => {
-                // Игнорируем или логируем
+                Self::send_frame(ctx, &ServerFrame::error(ERROR_BAD_FRAME, "Binary frames are not supported", None));
             }
             Ok(ws::Message::Continuation(_)) => {
                 // Игнорируем или логируем
@@ -208,47 +1083,103 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for ChatSession {
     }
 }
 
-
-impl Handler<ClientMessage> for ChatSession {
+impl Handler<ServerFrame> for ChatSession {
     type Result = ();
 
-    fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) {
-        if let Ok(text) = serde_json::to_string(&msg) {
-            ctx.text(text);
-        }
+    fn handle(&mut self, msg: ServerFrame, ctx: &mut Self::Context) {
+        Self::send_frame(ctx, &msg);
     }
 }
 
+#[derive(Deserialize)]
+pub struct ConnectQuery {
+    last_seen_id: Option<i32>,
+    // браузер не может передать заголовок Authorization при открытии WebSocket
+    token: Option<String>,
+}
+
 // === HTTP entrypoint для WS ===
 pub async fn chat_ws(
     req: HttpRequest,
     stream: web::Payload,
     login: web::Path<String>,
+    query: web::Query<ConnectQuery>,
     srv: web::Data<Addr<ChatServer>>,
+    limits: web::Data<ChatLimits>,
 ) -> Result<HttpResponse, Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .or(query.token.as_deref());
+
+    let claims = match token.and_then(verify_jwt) {
+        Some(c) => c,
+        None => return Ok(HttpResponse::Unauthorized().body("Invalid or missing token")),
+    };
+    // подключиться можно только к своему чату
+    if claims.sub != *login {
+        return Ok(HttpResponse::Forbidden().body("Token does not match chat login"));
+    }
+
     let session = ChatSession {
         login: login.into_inner(),
         addr: srv.get_ref().clone(),
         disconnected: Arc::new(AtomicBool::new(false)),
+        last_seen_id: query.last_seen_id,
+        limits: limits.into_inner(),
     };
     ws::start(session, &req, stream)
 }
 
 #[derive(Debug, Serialize, QueryableByName)]
 pub struct MessageDto {
-    #[sql_type = "Text"]
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
     pub sender: String,
-    #[sql_type = "Text"]
+    #[diesel(sql_type = Text)]
     pub recipient: String,
-    #[sql_type = "Text"]
+    // пустой у удалённых сообщений
+    #[diesel(sql_type = Text)]
     pub body: String,
-    #[sql_type = "Timestamptz"]
+    #[diesel(sql_type = Timestamptz)]
     pub created_at: chrono::NaiveDateTime,
+    #[diesel(sql_type = Bool)]
+    pub read: bool,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub client_id: Option<String>,
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub edited_at: Option<DateTime<Utc>>,
+    #[diesel(sql_type = Bool)]
+    pub deleted: bool,
+    #[serde(skip)]
+    #[diesel(sql_type = Nullable<Text>)]
+    pub attachment_type: Option<String>,
+    #[serde(skip)]
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub attachment_id: Option<i32>,
+}
+
+impl HistoryRow for MessageDto {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn attachment_ref(&self) -> Option<AttachmentRef> {
+        attachment_ref(self.attachment_type.as_deref(), self.attachment_id)
+    }
 }
 
 #[derive(Deserialize)]
 pub struct MessageQuery {
     companion: String,
+    // более ранние сообщения, чем указанное ("загрузить ещё")
+    before_id: Option<i32>,
+    // сообщения после указанного (догрузка новых)
+    after_id: Option<i32>,
+    limit: Option<i64>,
 }
 
 #[get("/messages")]
@@ -276,46 +1207,66 @@ async fn get_my_messages(
 
     let my_login = claims.sub;
     let other_login = query.companion.clone();
+    let cursor = HistoryCursor::new(query.before_id, query.after_id, query.limit);
 
     let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
 
-    let query = r#"
-       SELECT sender_login as sender, recipient_login as recipient, body, created_at
+    let sql = cursor.query(
+        r#"
+        SELECT
+            id, sender_login as sender, recipient_login as recipient, body, created_at, read, client_id,
+            edited_at, deleted_at IS NOT NULL AS deleted, attachment_type, attachment_id
         FROM messages
-        WHERE (sender_login = $1 AND recipient_login = $2)
-           OR (sender_login = $2 AND recipient_login = $1)
-        ORDER BY created_at ASC
-    "#;
-
-    let messages = match diesel::sql_query(query)
+        WHERE ((sender_login = $1 AND recipient_login = $2 AND NOT hidden_for_sender)
+            OR (sender_login = $2 AND recipient_login = $1 AND NOT hidden_for_recipient))
+        "#,
+    );
+    let page_query = diesel::sql_query(sql)
+        .into_boxed()
         .bind::<Text, _>(&my_login)
-        .bind::<Text, _>(&other_login)
-        .load::<MessageDto>(conn)
-    {
-        Ok(results) => results,
+        .bind::<Text, _>(&other_login);
+
+    match cursor.load::<MessageDto>(conn, page_query) {
+        Ok(page) => HttpResponse::Ok().json(page),
         Err(err) => {
             eprintln!("DB error: {:?}", err);
-            return HttpResponse::InternalServerError().body("Error fetching messages");
+            HttpResponse::InternalServerError().body("Error fetching messages")
         }
-    };
-
-    HttpResponse::Ok().json(messages)
+    }
 }
 
 #[derive(Debug, Serialize, QueryableByName)]
 pub struct DialogDto {
-    #[sql_type = "Text"]
+    #[diesel(sql_type = Text)]
     pub companion: String,
-    #[sql_type = "Text"]
+    #[diesel(sql_type = Text)]
     pub last_message: String,
-    #[sql_type = "Timestamptz"]
+    #[diesel(sql_type = Integer)]
+    pub last_message_id: i32,
+    #[diesel(sql_type = Timestamptz)]
     pub last_message_time: DateTime<Utc>,
+    #[diesel(sql_type = BigInt)]
+    pub unread_count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct DialogQuery {
+    // id последнего сообщения из последнего полученного диалога
+    before_id: Option<i32>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct DialogPage {
+    items: Vec<DialogDto>,
+    next_before_id: Option<i32>,
 }
 
 #[get("/dialogs")]
 async fn get_my_dialogs(
     pool: web::Data<DBPool>,
     req: HttpRequest,
+    query: web::Query<DialogQuery>,
 ) -> HttpResponse {
     let token = match req.headers().get(header::AUTHORIZATION) {
         Some(header_value) => {
@@ -335,23 +1286,44 @@ async fn get_my_dialogs(
     };
 
     let login = claims.sub;
+    let limit = query.limit.unwrap_or(30).clamp(1, 100);
     let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
 
-    let query = r#"
-        SELECT DISTINCT ON (companion)
-            CASE 
-                WHEN sender_login = $1 THEN recipient_login
-                ELSE sender_login
-            END AS companion,
-            body AS last_message,
-            created_at AS last_message_time
-        FROM messages
-        WHERE sender_login = $1 OR recipient_login = $1
-        ORDER BY companion, created_at DESC
+    // Диалоги от свежих к старым, курсор - id последнего сообщения диалога
+    let sql = r#"
+        SELECT
+            d.companion,
+            d.last_message,
+            d.last_message_id,
+            d.last_message_time,
+            (
+                SELECT COUNT(*) FROM messages AS u
+                WHERE u.recipient_login = $1 AND u.sender_login = d.companion AND NOT u.read
+            ) AS unread_count
+        FROM (
+            SELECT DISTINCT ON (companion)
+                CASE 
+                    WHEN sender_login = $1 THEN recipient_login
+                    ELSE sender_login
+                END AS companion,
+                body AS last_message,
+                id AS last_message_id,
+                created_at AS last_message_time
+            FROM messages
+            WHERE conversation_id IS NULL
+              AND ((sender_login = $1 AND NOT hidden_for_sender)
+                OR (recipient_login = $1 AND NOT hidden_for_recipient))
+            ORDER BY companion, id DESC
+        ) AS d
+        WHERE $2::int IS NULL OR d.last_message_id < $2
+        ORDER BY d.last_message_id DESC
+        LIMIT $3
     "#;
 
-    let dialogs = match diesel::sql_query(query)
+    let dialogs = match diesel::sql_query(sql)
         .bind::<Text, _>(&login)
+        .bind::<Nullable<Integer>, _>(query.before_id)
+        .bind::<BigInt, _>(limit)
         .load::<DialogDto>(conn)
     {
         Ok(results) => results,
@@ -361,5 +1333,393 @@ async fn get_my_dialogs(
         }
     };
 
-    HttpResponse::Ok().json(dialogs)
+    let next_before_id = if dialogs.len() as i64 == limit {
+        dialogs.last().map(|d| d.last_message_id)
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(DialogPage { items: dialogs, next_before_id })
+}
+
+#[derive(QueryableByName)]
+struct ReadResult {
+    #[diesel(sql_type = BigInt)]
+    read_count: i64,
+
+    #[diesel(sql_type = Timestamptz)]
+    read_at: DateTime<Utc>,
+}
+
+// Отмечает прочитанными входящие от собеседника сообщения и уведомление о пропущенных.
+// Если читать было нечего, отчёт не создаётся.
+pub fn mark_dialog_read(
+    conn: &mut PgConnection,
+    reader: &str,
+    companion: &str,
+) -> Result<Option<ReadReceipt>, diesel::result::Error> {
+    let query = r#"
+        WITH updated AS (
+            UPDATE messages
+            SET read = TRUE, read_at = NOW()
+            WHERE recipient_login = $1 AND sender_login = $2 AND NOT read
+            RETURNING id
+        )
+        SELECT COUNT(*) AS read_count, NOW() AS read_at FROM updated
+    "#;
+
+    let result = diesel::sql_query(query)
+        .bind::<Text, _>(reader)
+        .bind::<Text, _>(companion)
+        .get_result::<ReadResult>(conn)?;
+
+    if result.read_count == 0 {
+        return Ok(None);
+    }
+
+    diesel::sql_query(
+        r#"
+        UPDATE notifications
+        SET is_read = TRUE, read_at = NOW()
+        WHERE user_login = $1 AND actor_login = $2 AND notification_type = $3 AND NOT is_read
+        "#,
+    )
+    .bind::<Text, _>(reader)
+    .bind::<Text, _>(companion)
+    .bind::<Text, _>(TYPE_OFFLINE_MESSAGE)
+    .execute(conn)?;
+
+    Ok(Some(ReadReceipt {
+        reader: reader.to_string(),
+        companion: companion.to_string(),
+        read_count: result.read_count,
+        read_at: result.read_at,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    companion: String,
+}
+
+#[derive(Serialize)]
+pub struct MarkReadResponse {
+    read_count: i64,
+}
+
+#[post("/messages/read")]
+async fn mark_messages_read(
+    pool: web::Data<DBPool>,
+    srv: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    data: web::Json<MarkReadRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match mark_dialog_read(conn, &user_login, &data.companion) {
+        Ok(Some(receipt)) => {
+            let read_count = receipt.read_count;
+            srv.do_send(ChatCommand::Deliver {
+                event: receipt_event(receipt),
+            });
+            HttpResponse::Ok().json(MarkReadResponse { read_count })
+        }
+        Ok(None) => HttpResponse::Ok().json(MarkReadResponse { read_count: 0 }),
+        Err(err) => {
+            eprintln!("DB error: {:?}", err);
+            HttpResponse::InternalServerError().body("Error marking messages as read")
+        }
+    }
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct UnreadSummary {
+    #[diesel(sql_type = BigInt)]
+    unread_count: i64,
+
+    #[diesel(sql_type = BigInt)]
+    unread_dialogs: i64,
+}
+
+// Общий счётчик непрочитанных для значка в интерфейсе
+#[get("/messages/unread")]
+async fn get_unread_summary(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let query = r#"
+        SELECT
+            COUNT(*) AS unread_count,
+            COUNT(DISTINCT sender_login) AS unread_dialogs
+        FROM messages
+        WHERE recipient_login = $1 AND NOT read
+    "#;
+
+    match diesel::sql_query(query).bind::<Text, _>(&user_login).get_result::<UnreadSummary>(conn) {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(err) => {
+            eprintln!("DB error: {:?}", err);
+            HttpResponse::InternalServerError().body("Error fetching unread counters")
+        }
+    }
+}
+
+// Сколько после отправки сообщение можно исправить
+const EDIT_WINDOW_MINUTES: i64 = 15;
+
+#[derive(Deserialize)]
+pub struct EditMessageRequest {
+    body: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteMessageRequest {
+    // true - удалить у обоих (только отправитель), false - скрыть у себя
+    #[serde(default)]
+    for_everyone: bool,
+}
+
+#[derive(QueryableByName)]
+struct MessageForUpdate {
+    #[diesel(sql_type = Text)]
+    sender_login: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    recipient_login: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    conversation_id: Option<i32>,
+
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(QueryableByName)]
+struct ChangedAt {
+    #[diesel(sql_type = Timestamptz)]
+    changed_at: DateTime<Utc>,
+}
+
+fn lock_message(conn: &mut PgConnection, message_id: i32) -> Result<MessageForUpdate, diesel::result::Error> {
+    diesel::sql_query(
+        r#"
+        SELECT sender_login, recipient_login, conversation_id, created_at, deleted_at
+        FROM messages
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind::<Integer, _>(message_id)
+    .get_result::<MessageForUpdate>(conn)
+}
+
+// Кто видит сообщение: оба собеседника или участники беседы вместе с отправителем
+fn message_audience(conn: &mut PgConnection, message: &MessageForUpdate) -> Result<Vec<String>, diesel::result::Error> {
+    let mut audience = match (&message.recipient_login, message.conversation_id) {
+        (Some(recipient), _) => vec![recipient.clone()],
+        (None, Some(conversation_id)) => member_logins(conn, conversation_id)?,
+        (None, None) => Vec::new(),
+    };
+    if !audience.contains(&message.sender_login) {
+        audience.push(message.sender_login.clone());
+    }
+    Ok(audience)
+}
+
+// Правка своего сообщения; прежний текст остаётся в message_edits
+#[post("/messages/{id}/edit")]
+async fn edit_message(
+    pool: web::Data<DBPool>,
+    srv: web::Data<Addr<ChatServer>>,
+    limits: web::Data<ChatLimits>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Json<EditMessageRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if data.body.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Message body is required");
+    }
+    if let Err(error) = limits.check_body(&data.body, None) {
+        return HttpResponse::BadRequest().body(error.message);
+    }
+
+    let message_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let message = lock_message(conn, message_id)?;
+        let audience = message_audience(conn, &message)?;
+        if message.sender_login != user_login {
+            // чужие сообщения не раскрываем
+            return Err(if audience.contains(&user_login) { ApiError::Forbidden } else { ApiError::NotFound });
+        }
+        if message.deleted_at.is_some() {
+            return Err(ApiError::Conflict("Message was deleted".to_string()));
+        }
+        if Utc::now() - message.created_at > chrono::Duration::minutes(EDIT_WINDOW_MINUTES) {
+            return Err(ApiError::Conflict("Edit window has expired".to_string()));
+        }
+
+        let query = r#"
+            WITH history AS (
+                INSERT INTO message_edits (message_id, action, previous_body)
+                SELECT id, 'edit', body FROM messages WHERE id = $1
+            )
+            UPDATE messages SET body = $2, edited_at = NOW()
+            WHERE id = $1
+            RETURNING edited_at AS changed_at
+        "#;
+
+        let edited_at = diesel::sql_query(query)
+            .bind::<Integer, _>(message_id)
+            .bind::<Text, _>(&data.body)
+            .get_result::<ChangedAt>(conn)?
+            .changed_at;
+
+        let edited = MessageEdited {
+            id: message_id,
+            sender: message.sender_login,
+            recipient: message.recipient_login,
+            conversation_id: message.conversation_id,
+            body: data.body.clone(),
+            edited_at,
+        };
+        Ok((edited, audience))
+    });
+
+    match result {
+        Ok((edited, audience)) => {
+            srv.do_send(ChatCommand::Deliver {
+                event: ClusterEvent {
+                    recipients: audience,
+                    frame: ServerFrame::MessageEdited(edited.clone()),
+                },
+            });
+            HttpResponse::Ok().json(edited)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+// Удаление у обоих участников или скрытие у себя.
+// Непрочитанное удалённое сообщение перестаёт учитываться в счётчиках.
+#[post("/messages/{id}/delete")]
+async fn delete_message(
+    pool: web::Data<DBPool>,
+    srv: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Json<DeleteMessageRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let message_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let message = lock_message(conn, message_id)?;
+        let audience = message_audience(conn, &message)?;
+        let is_sender = message.sender_login == user_login;
+        if !audience.contains(&user_login) {
+            return Err(ApiError::NotFound);
+        }
+        // в беседе флаг скрытия есть только у отправителя
+        if !data.for_everyone && !is_sender && message.conversation_id.is_some() {
+            return Err(ApiError::BadRequest(
+                "Only your own conversation messages can be hidden".to_string(),
+            ));
+        }
+
+        let deleted_at = if data.for_everyone {
+            if !is_sender {
+                return Err(ApiError::Forbidden);
+            }
+
+            match message.deleted_at {
+                Some(deleted_at) => deleted_at,
+                None => {
+                    let query = r#"
+                        WITH history AS (
+                            INSERT INTO message_edits (message_id, action, previous_body)
+                            SELECT id, 'delete', body FROM messages WHERE id = $1
+                        )
+                        UPDATE messages
+                        SET body = '', attachment_type = NULL, attachment_id = NULL,
+                            deleted_at = NOW(), read = TRUE, read_at = COALESCE(read_at, NOW())
+                        WHERE id = $1
+                        RETURNING deleted_at AS changed_at
+                    "#;
+
+                    diesel::sql_query(query)
+                        .bind::<Integer, _>(message_id)
+                        .get_result::<ChangedAt>(conn)?
+                        .changed_at
+                }
+            }
+        } else {
+            let query = if is_sender {
+                "UPDATE messages SET hidden_for_sender = TRUE WHERE id = $1 RETURNING NOW() AS changed_at"
+            } else {
+                r#"
+                UPDATE messages
+                SET hidden_for_recipient = TRUE, read = TRUE, read_at = COALESCE(read_at, NOW())
+                WHERE id = $1
+                RETURNING NOW() AS changed_at
+                "#
+            };
+
+            diesel::sql_query(query)
+                .bind::<Integer, _>(message_id)
+                .get_result::<ChangedAt>(conn)?
+                .changed_at
+        };
+
+        let deleted = MessageDeleted {
+            id: message_id,
+            sender: message.sender_login,
+            recipient: message.recipient_login,
+            conversation_id: message.conversation_id,
+            for_everyone: data.for_everyone,
+            deleted_at,
+        };
+        Ok((deleted, audience))
+    });
+
+    match result {
+        Ok((deleted, audience)) => {
+            let recipients = if deleted.for_everyone {
+                audience
+            } else {
+                vec![user_login]
+            };
+            srv.do_send(ChatCommand::Deliver {
+                event: ClusterEvent {
+                    recipients,
+                    frame: ServerFrame::MessageDeleted(deleted.clone()),
+                },
+            });
+            HttpResponse::Ok().json(deleted)
+        }
+        Err(err) => err.into_response(),
+    }
 }
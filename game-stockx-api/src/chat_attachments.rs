@@ -0,0 +1,167 @@
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Integer, Nullable, Text};
+use std::collections::{HashMap, HashSet};
+use crate::chat_protocol::{Attachment, AttachmentItem, AttachmentRef};
+use crate::collection::CountResult;
+
+#[derive(QueryableByName)]
+struct AttachmentRow {
+    #[diesel(sql_type = Text)]
+    kind: String,
+
+    #[diesel(sql_type = Integer)]
+    ref_id: i32,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    status: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    price: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    currency: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    product_name: String,
+
+    #[diesel(sql_type = Text)]
+    platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    image_url: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    from_login: Option<String>,
+}
+
+// Ссылка из столбцов attachment_type и attachment_id сообщения
+pub fn attachment_ref(kind: Option<&str>, id: Option<i32>) -> Option<AttachmentRef> {
+    AttachmentRef::from_parts(kind?, id?)
+}
+
+// Карточки для набора ссылок одним запросом. Ссылки на удалённые объекты в ответ не попадают.
+pub fn load_attachments(
+    conn: &mut PgConnection,
+    refs: &[AttachmentRef],
+) -> QueryResult<HashMap<AttachmentRef, Attachment>> {
+    let mut cards = HashMap::new();
+    let unique: Vec<&AttachmentRef> = refs.iter().collect::<HashSet<_>>().into_iter().collect();
+    if unique.is_empty() {
+        return Ok(cards);
+    }
+
+    let kinds: Vec<&str> = unique.iter().map(|r| r.kind()).collect();
+    let ids: Vec<i32> = unique.iter().map(|r| r.id()).collect();
+
+    let query = r#"
+        WITH refs AS (
+            SELECT * FROM UNNEST($1::text[], $2::int[]) AS r(kind, ref_id)
+        ),
+        items AS (
+            SELECT r.kind, r.ref_id, NULL::text AS status, NULL::int AS price, NULL::text AS currency,
+                   rel.id AS release_id, NULL::text AS from_login
+            FROM refs AS r
+            INNER JOIN releases AS rel ON r.kind = 'release' AND rel.id = r.ref_id
+            UNION ALL
+            SELECT r.kind, r.ref_id, l.status, l.price, l.currency, l.release_id, NULL::text
+            FROM refs AS r
+            INNER JOIN listings AS l ON r.kind = 'listing' AND l.id = r.ref_id
+            UNION ALL
+            SELECT r.kind, r.ref_id, t.status, NULL::int, NULL::text, ti.release_id, ti.from_login
+            FROM refs AS r
+            INNER JOIN trades AS t ON r.kind = 'trade' AND t.id = r.ref_id
+            INNER JOIN trade_items AS ti ON ti.trade_id = t.id
+        )
+        SELECT
+            i.kind,
+            i.ref_id,
+            i.status,
+            i.price,
+            i.currency,
+            i.release_id,
+            prod.id AS product_id,
+            prod.name AS product_name,
+            p.name AS platform_name,
+            reg.name AS region_name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+            i.from_login
+        FROM items AS i
+        INNER JOIN releases AS rel ON rel.id = i.release_id
+        INNER JOIN products AS prod ON prod.id = rel.product_id
+        INNER JOIN platforms AS p ON p.id = rel.platform
+        LEFT JOIN regions AS reg ON reg.id = rel.release_region
+        ORDER BY i.kind, i.ref_id, i.from_login NULLS FIRST, prod.name
+    "#;
+
+    let rows = diesel::sql_query(query)
+        .bind::<Array<Text>, _>(&kinds)
+        .bind::<Array<Integer>, _>(&ids)
+        .load::<AttachmentRow>(conn)?;
+
+    for row in rows {
+        let Some(reference) = AttachmentRef::from_parts(&row.kind, row.ref_id) else {
+            continue;
+        };
+
+        let card = cards.entry(reference).or_insert_with(|| Attachment {
+            kind: row.kind.clone(),
+            id: row.ref_id,
+            status: row.status.clone(),
+            price: row.price,
+            currency: row.currency.clone(),
+            items: Vec::new(),
+        });
+        card.items.push(AttachmentItem {
+            release_id: row.release_id,
+            product_id: row.product_id,
+            product_name: row.product_name,
+            platform_name: row.platform_name,
+            region_name: row.region_name,
+            image_url: row.image_url,
+            from_login: row.from_login,
+        });
+    }
+
+    Ok(cards)
+}
+
+// Карточка для нового сообщения. None - ссылка не существует или обмен чужой:
+// в личной переписке он должен идти между отправителем и получателем,
+// в беседе - с участием отправителя.
+pub fn resolve_attachment(
+    conn: &mut PgConnection,
+    reference: &AttachmentRef,
+    sender: &str,
+    recipient: Option<&str>,
+) -> QueryResult<Option<Attachment>> {
+    if let AttachmentRef::Trade { id } = reference {
+        let allowed = diesel::sql_query(
+            r#"
+            SELECT COUNT(*) AS total FROM trades
+            WHERE id = $1
+              AND (proposer_login = $2 OR recipient_login = $2)
+              AND ($3::text IS NULL OR proposer_login = $3 OR recipient_login = $3)
+            "#,
+        )
+        .bind::<Integer, _>(*id)
+        .bind::<Text, _>(sender)
+        .bind::<Nullable<Text>, _>(recipient)
+        .get_result::<CountResult>(conn)?
+        .total;
+
+        if allowed == 0 {
+            return Ok(None);
+        }
+    }
+
+    Ok(load_attachments(conn, std::slice::from_ref(reference))?.remove(reference))
+}
@@ -0,0 +1,281 @@
+use actix::prelude::*;
+use bb8_redis::redis::{self, AsyncCommands, RedisResult};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use crate::chat::{ChatCommand, ChatServer};
+use crate::chat_protocol::ServerFrame;
+use crate::redis::RedisPool;
+
+// Общий канал, через который узлы пересылают друг другу события чата
+const EVENTS_CHANNEL: &str = "chat:events";
+// Пульс узла: если ключ истёк, узел считается упавшим вместе со всеми его сессиями
+pub const NODE_TTL_SECS: u64 = 60;
+// Узлы, отмечавшиеся в кластере; упавшие отсюда забирает на уборку один из живых
+const NODES_KEY: &str = "chat:nodes";
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(3);
+
+fn presence_key(login: &str) -> String {
+    format!("chat:presence:{}", login)
+}
+
+fn node_key(node_id: &str) -> String {
+    format!("chat:node:{}", node_id)
+}
+
+// Пользователи, которых узел отметил в сети
+fn node_logins_key(node_id: &str) -> String {
+    format!("chat:node_logins:{}", node_id)
+}
+
+// Кадр, который нужно доставить в живые сессии перечисленных пользователей на всех узлах
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClusterEvent {
+    pub recipients: Vec<String>,
+    pub frame: ServerFrame,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    origin: String,
+    event: ClusterEvent,
+}
+
+pub async fn publish(redis_pool: &RedisPool, node_id: &str, event: ClusterEvent) {
+    let payload = match serde_json::to_string(&Envelope { origin: node_id.to_string(), event }) {
+        Ok(payload) => payload,
+        Err(e) => {
+            eprintln!("Failed to serialize chat event: {}", e);
+            return;
+        }
+    };
+
+    let result: RedisResult<i64> = match redis_pool.get().await {
+        Ok(mut conn) => conn.publish(EVENTS_CHANNEL, payload).await,
+        Err(e) => {
+            eprintln!("Failed to publish chat event: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to publish chat event: {}", e);
+    }
+}
+
+// Слушает канал событий и передаёт чужие события локальному ChatServer.
+// При обрыве соединения переподписывается.
+pub async fn run_subscriber(redis_url: String, node_id: String, server: Addr<ChatServer>) {
+    loop {
+        if let Err(e) = subscribe(&redis_url, &node_id, &server).await {
+            eprintln!("Chat events subscription failed: {}", e);
+        }
+        actix_rt::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn subscribe(redis_url: &str, node_id: &str, server: &Addr<ChatServer>) -> RedisResult<()> {
+    let client = redis::Client::open(redis_url)?;
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(EVENTS_CHANNEL).await?;
+
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let payload: String = msg.get_payload()?;
+        match serde_json::from_str::<Envelope>(&payload) {
+            Ok(envelope) if envelope.origin != node_id => {
+                server.do_send(ChatCommand::Remote { event: envelope.event });
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Malformed chat event: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn pool_error(e: bb8::RunError<redis::RedisError>) -> redis::RedisError {
+    match e {
+        bb8::RunError::User(e) => e,
+        bb8::RunError::TimedOut => redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool timed out")),
+    }
+}
+
+// Записывает число сессий пользователя на этом узле (ноль снимает отметку узла)
+// и возвращает, был ли он в сети до и после записи. Списки узлов читаются
+// в одной транзакции с записью, поэтому изменения с других узлов не вклиниваются.
+pub async fn update_presence(
+    redis_pool: &RedisPool,
+    node_id: &str,
+    login: &str,
+    sessions: usize,
+) -> RedisResult<(bool, bool)> {
+    let mut conn = redis_pool.get().await.map_err(pool_error)?;
+
+    let key = presence_key(login);
+    let mut pipe = redis::pipe();
+    pipe.atomic().hkeys(&key);
+    if sessions > 0 {
+        pipe.hset(&key, node_id, sessions).ignore();
+        pipe.sadd(node_logins_key(node_id), login).ignore();
+    } else {
+        pipe.hdel(&key, node_id).ignore();
+        pipe.srem(node_logins_key(node_id), login).ignore();
+    }
+    pipe.hkeys(&key);
+    let (before, after): (Vec<String>, Vec<String>) = pipe.query_async(&mut *conn).await?;
+
+    let nodes: Vec<String> = before.iter().chain(&after).cloned().collect::<HashSet<_>>().into_iter().collect();
+    let alive = alive_nodes(&mut *conn, &nodes).await?;
+    Ok((
+        before.iter().any(|node| alive.contains(node.as_str())),
+        after.iter().any(|node| alive.contains(node.as_str())),
+    ))
+}
+
+// Продлевает пульс узла и целиком переписывает его отметки: stale - пользователи,
+// которых узел отмечал раньше, но у которых здесь больше нет сессий.
+// Возвращает false, если записать не удалось.
+pub async fn heartbeat(
+    redis_pool: &RedisPool,
+    node_id: &str,
+    local: &HashMap<String, usize>,
+    stale: &[String],
+) -> bool {
+    let mut conn = match redis_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("Chat heartbeat failed: {}", e);
+            return false;
+        }
+    };
+
+    let logins_key = node_logins_key(node_id);
+    let mut pipe = redis::pipe();
+    pipe.atomic().set_ex(node_key(node_id), 1, NODE_TTL_SECS).ignore();
+    pipe.sadd(NODES_KEY, node_id).ignore();
+    for login in stale {
+        pipe.hdel(presence_key(login), node_id).ignore();
+        pipe.srem(&logins_key, login).ignore();
+    }
+    for (login, sessions) in local {
+        pipe.hset(presence_key(login), node_id, *sessions).ignore();
+        pipe.sadd(&logins_key, login).ignore();
+    }
+
+    let result: RedisResult<()> = pipe.query_async(&mut *conn).await;
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Chat heartbeat failed: {}", e);
+            false
+        }
+    }
+}
+
+// Снимает отметки узлов, чей пульс истёк. Каждый упавший узел забирает себе
+// один живой (тот, чей SREM из списка узлов сработал), и только он получает
+// пользователей упавшего узла, чтобы разослать их собеседникам уход из сети.
+pub async fn reap_dead_nodes(redis_pool: &RedisPool) -> RedisResult<Vec<String>> {
+    let mut conn = redis_pool.get().await.map_err(pool_error)?;
+
+    let nodes: Vec<String> = conn.smembers(NODES_KEY).await?;
+    let alive = alive_nodes(&mut *conn, &nodes).await?;
+
+    let mut logins = HashSet::new();
+    for node in nodes.iter().filter(|node| !alive.contains(node.as_str())) {
+        let claimed: bool = conn.srem(NODES_KEY, node).await?;
+        if !claimed {
+            continue;
+        }
+
+        let logins_key = node_logins_key(node);
+        let node_logins: Vec<String> = conn.smembers(&logins_key).await?;
+        let mut cleanup = redis::pipe();
+        for login in &node_logins {
+            cleanup.hdel(presence_key(login), node).ignore();
+        }
+        cleanup.del(&logins_key).ignore();
+        let _: () = cleanup.query_async(&mut *conn).await?;
+
+        logins.extend(node_logins);
+    }
+
+    Ok(logins.into_iter().collect())
+}
+
+// Узлы, чей пульс ещё не истёк
+async fn alive_nodes<C: redis::aio::ConnectionLike>(conn: &mut C, nodes: &[String]) -> RedisResult<HashSet<String>> {
+    if nodes.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let mut pipe = redis::pipe();
+    for node in nodes {
+        pipe.exists(node_key(node));
+    }
+    let alive: Vec<bool> = pipe.query_async(conn).await?;
+    Ok(nodes
+        .iter()
+        .zip(alive)
+        .filter(|(_, alive)| *alive)
+        .map(|(node, _)| node.clone())
+        .collect())
+}
+
+// Кто из перечисленных пользователей подключён хотя бы к одному живому узлу.
+// Отметки упавших узлов попутно удаляются.
+pub async fn online_logins(redis_pool: &RedisPool, logins: &[String]) -> RedisResult<HashSet<String>> {
+    let mut online = HashSet::new();
+    if logins.is_empty() {
+        return Ok(online);
+    }
+
+    let mut conn = redis_pool.get().await.map_err(pool_error)?;
+
+    let mut pipe = redis::pipe();
+    for login in logins {
+        pipe.hkeys(presence_key(login));
+    }
+    let nodes_by_login: Vec<Vec<String>> = pipe.query_async(&mut *conn).await?;
+
+    let nodes: Vec<String> = nodes_by_login
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    if nodes.is_empty() {
+        return Ok(online);
+    }
+
+    let alive = alive_nodes(&mut *conn, &nodes).await?;
+
+    let mut cleanup = redis::pipe();
+    let mut stale = false;
+    for (login, nodes) in logins.iter().zip(&nodes_by_login) {
+        for node in nodes {
+            if alive.contains(node.as_str()) {
+                online.insert(login.clone());
+            } else {
+                cleanup.hdel(presence_key(login), node).ignore();
+                stale = true;
+            }
+        }
+    }
+    if stale {
+        let _: RedisResult<()> = cleanup.query_async(&mut *conn).await;
+    }
+
+    Ok(online)
+}
+
+pub async fn is_online(redis_pool: &RedisPool, login: &str) -> bool {
+    match online_logins(redis_pool, &[login.to_string()]).await {
+        Ok(online) => !online.is_empty(),
+        Err(e) => {
+            eprintln!("Presence lookup failed: {}", e);
+            false
+        }
+    }
+}
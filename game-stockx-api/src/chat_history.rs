@@ -0,0 +1,159 @@
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{BigInt, Integer, Nullable};
+use serde::Serialize;
+use crate::chat_attachments::load_attachments;
+use crate::chat_protocol::{Attachment, AttachmentRef};
+
+// Строка истории личной переписки или беседы
+pub trait HistoryRow {
+    fn id(&self) -> i32;
+    fn attachment_ref(&self) -> Option<AttachmentRef>;
+}
+
+// Сообщение истории вместе с карточкой вложения
+#[derive(Serialize)]
+pub struct HistoryItem<T> {
+    #[serde(flatten)]
+    message: T,
+    attachment: Option<Attachment>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryPage<T> {
+    // всегда от старых к новым
+    items: Vec<HistoryItem<T>>,
+    // курсоры для следующих запросов, если в выбранном направлении есть ещё сообщения
+    next_before_id: Option<i32>,
+    next_after_id: Option<i32>,
+}
+
+// before_id - более ранние сообщения ("загрузить ещё"), after_id - догрузка новых
+pub struct HistoryCursor {
+    before_id: Option<i32>,
+    after_id: Option<i32>,
+    limit: i64,
+}
+
+impl HistoryCursor {
+    pub fn new(before_id: Option<i32>, after_id: Option<i32>, limit: Option<i64>) -> HistoryCursor {
+        HistoryCursor {
+            before_id,
+            after_id,
+            limit: limit.unwrap_or(50).clamp(1, 200),
+        }
+    }
+
+    // Без after_id читаем от новых к старым: последняя страница или страница перед before_id
+    fn forward(&self) -> bool {
+        self.after_id.is_some()
+    }
+
+    // Дописывает к выборке с условием WHERE курсоры ($3, $4) и лимит ($5)
+    pub fn query(&self, select: &str) -> String {
+        format!(
+            r#"
+            SELECT * FROM (
+                {}
+                  AND ($3::int IS NULL OR id < $3)
+                  AND ($4::int IS NULL OR id > $4)
+                ORDER BY id {}
+                LIMIT $5
+            ) AS page
+            ORDER BY id ASC
+            "#,
+            select,
+            if self.forward() { "ASC" } else { "DESC" }
+        )
+    }
+
+    // Курсоры по id страницы (по возрастанию); неполная страница означает, что дальше сообщений нет
+    fn next_cursors(&self, ids: &[i32]) -> (Option<i32>, Option<i32>) {
+        if ids.len() as i64 != self.limit {
+            return (None, None);
+        }
+        if self.forward() {
+            (None, ids.last().copied())
+        } else {
+            (ids.first().copied(), None)
+        }
+    }
+
+    // Загружает страницу по запросу из query(), в котором уже привязаны $1 и $2
+    pub fn load<T>(&self, conn: &mut PgConnection, query: BoxedSqlQuery<'_, Pg, SqlQuery>) -> QueryResult<HistoryPage<T>>
+    where
+        T: HistoryRow + QueryableByName<Pg> + 'static,
+    {
+        let messages = query
+            .bind::<Nullable<Integer>, _>(self.before_id)
+            .bind::<Nullable<Integer>, _>(self.after_id)
+            .bind::<BigInt, _>(self.limit)
+            .load::<T>(conn)?;
+
+        let ids: Vec<i32> = messages.iter().map(|m| m.id()).collect();
+        let (next_before_id, next_after_id) = self.next_cursors(&ids);
+
+        let refs: Vec<AttachmentRef> = messages.iter().filter_map(|m| m.attachment_ref()).collect();
+        let cards = load_attachments(conn, &refs)?;
+
+        let items = messages
+            .into_iter()
+            .map(|message| HistoryItem {
+                attachment: message.attachment_ref().and_then(|r| cards.get(&r).cloned()),
+                message,
+            })
+            .collect();
+
+        Ok(HistoryPage {
+            items,
+            next_before_id,
+            next_after_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn new_clamps_limit() {
+        assert_eq!(HistoryCursor::new(None, None, None).limit, 50);
+        assert_eq!(HistoryCursor::new(None, None, Some(0)).limit, 1);
+        assert_eq!(HistoryCursor::new(None, None, Some(-5)).limit, 1);
+        assert_eq!(HistoryCursor::new(None, None, Some(20)).limit, 20);
+        assert_eq!(HistoryCursor::new(None, None, Some(1000)).limit, 200);
+    }
+
+    #[test]
+    async fn backward_full_page_points_before_oldest() {
+        let cursor = HistoryCursor::new(Some(100), None, Some(3));
+        assert_eq!(cursor.next_cursors(&[97, 98, 99]), (Some(97), None));
+    }
+
+    #[test]
+    async fn forward_full_page_points_after_newest() {
+        let cursor = HistoryCursor::new(None, Some(10), Some(3));
+        assert_eq!(cursor.next_cursors(&[11, 12, 13]), (None, Some(13)));
+    }
+
+    #[test]
+    async fn partial_page_has_no_cursors() {
+        assert_eq!(HistoryCursor::new(None, None, Some(3)).next_cursors(&[1, 2]), (None, None));
+        assert_eq!(HistoryCursor::new(None, Some(10), Some(3)).next_cursors(&[11]), (None, None));
+        assert_eq!(HistoryCursor::new(None, None, Some(3)).next_cursors(&[]), (None, None));
+    }
+
+    #[test]
+    async fn query_orders_by_direction() {
+        let select = "SELECT id FROM messages WHERE true";
+        let backward = HistoryCursor::new(None, None, None).query(select);
+        assert!(backward.contains("ORDER BY id DESC"));
+        let forward = HistoryCursor::new(None, Some(1), None).query(select);
+        assert!(forward.contains("ORDER BY id ASC\n"));
+        assert!(!forward.contains("DESC"));
+        // страница всегда отдаётся от старых к новым
+        assert!(backward.trim_end().ends_with("ORDER BY id ASC"));
+    }
+}
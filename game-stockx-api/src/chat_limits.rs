@@ -0,0 +1,281 @@
+use governor::{
+    clock::{Clock, DefaultClock},
+    middleware::NoOpMiddleware,
+    state::keyed::DashMapStateStore,
+    Quota, RateLimiter,
+};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use crate::chat_protocol::{
+    ErrorFrame, ERROR_FORBIDDEN_CONTENT, ERROR_FRAME_TOO_LARGE, ERROR_MESSAGE_TOO_LONG, ERROR_RATE_LIMITED,
+};
+
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 4000;
+const DEFAULT_MAX_FRAME_BYTES: usize = 32 * 1024;
+const DEFAULT_MESSAGES_PER_MINUTE: u32 = 30;
+const DEFAULT_MESSAGE_BURST: u32 = 5;
+const DEFAULT_FRAMES_PER_SECOND: u32 = 10;
+const DEFAULT_FRAME_BURST: u32 = 20;
+
+type KeyedLimiter = RateLimiter<String, DashMapStateStore<String>, DefaultClock, NoOpMiddleware>;
+
+// Ограничения чата: размер кадра и сообщения, частота отправки и фильтр слов и ссылок.
+// Лимиты частоты считаются по логину в памяти узла, общие для всех сессий пользователя
+// на этом узле. У каждого экземпляра API свой счётчик: если сессии пользователя попали
+// на N разных узлов, он может отправить до N квот.
+pub struct ChatLimits {
+    max_message_length: usize,
+    max_frame_bytes: usize,
+    message_limiter: Arc<KeyedLimiter>,
+    frame_limiter: Arc<KeyedLimiter>,
+    // в нижнем регистре, слова разделены одним пробелом
+    banned_words: Vec<String>,
+    banned_domains: Vec<String>,
+    block_links: bool,
+}
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn keyed_limiter(quota: Quota) -> Arc<KeyedLimiter> {
+    Arc::new(RateLimiter::dashmap(quota))
+}
+
+fn non_zero(value: u32) -> NonZeroU32 {
+    NonZeroU32::new(value).unwrap_or(NonZeroU32::MIN)
+}
+
+// Текст в нижнем регистре как последовательность слов через пробел, с пробелами по краям,
+// чтобы запрещённые слова и фразы находились только целиком
+fn normalize_words(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!(" {} ", words.join(" "))
+}
+
+// Домен из слова, похожего на ссылку: https://www.example.com/path -> example.com.
+// Второе значение - была ли явная схема или www.
+fn link_host(token: &str) -> Option<(String, bool)> {
+    let token = token
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    let (rest, explicit) = match token.split_once("://") {
+        Some((scheme, rest)) if scheme == "http" || scheme == "https" => (rest.to_string(), true),
+        Some(_) => return None,
+        None => (token.clone(), token.starts_with("www.")),
+    };
+
+    let host = rest.split(['/', '?', '#', ':']).next()?.trim_start_matches("www.");
+    let (name, tld) = host.rsplit_once('.')?;
+    if name.is_empty() || tld.len() < 2 || !tld.chars().all(char::is_alphabetic) {
+        return None;
+    }
+    Some((host.to_string(), explicit))
+}
+
+fn seconds_until(not_until: &governor::NotUntil<<DefaultClock as Clock>::Instant>) -> u64 {
+    not_until.wait_time_from(DefaultClock::default().now()).as_secs().max(1)
+}
+
+impl ChatLimits {
+    // Настройки берутся из окружения:
+    // CHAT_MAX_MESSAGE_LENGTH, CHAT_MAX_FRAME_BYTES,
+    // CHAT_MESSAGES_PER_MINUTE, CHAT_MESSAGE_BURST, CHAT_FRAMES_PER_SECOND, CHAT_FRAME_BURST,
+    // CHAT_BANNED_WORDS и CHAT_BANNED_DOMAINS через запятую,
+    // CHAT_BLOCK_LINKS=true запрещает ссылки со схемой http(s):// или www.; голые домены
+    // вроде example.com пропускаются, их можно запретить через CHAT_BANNED_DOMAINS
+    pub fn from_env() -> ChatLimits {
+        let message_quota = Quota::per_minute(non_zero(env_number("CHAT_MESSAGES_PER_MINUTE", DEFAULT_MESSAGES_PER_MINUTE)))
+            .allow_burst(non_zero(env_number("CHAT_MESSAGE_BURST", DEFAULT_MESSAGE_BURST)));
+        let frame_quota = Quota::per_second(non_zero(env_number("CHAT_FRAMES_PER_SECOND", DEFAULT_FRAMES_PER_SECOND)))
+            .allow_burst(non_zero(env_number("CHAT_FRAME_BURST", DEFAULT_FRAME_BURST)));
+
+        ChatLimits {
+            max_message_length: env_number("CHAT_MAX_MESSAGE_LENGTH", DEFAULT_MAX_MESSAGE_LENGTH),
+            max_frame_bytes: env_number("CHAT_MAX_FRAME_BYTES", DEFAULT_MAX_FRAME_BYTES),
+            message_limiter: keyed_limiter(message_quota),
+            frame_limiter: keyed_limiter(frame_quota),
+            banned_words: env_list("CHAT_BANNED_WORDS")
+                .iter()
+                .map(|w| normalize_words(w).trim().to_string())
+                .filter(|w| !w.is_empty())
+                .collect(),
+            banned_domains: env_list("CHAT_BANNED_DOMAINS")
+                .into_iter()
+                .map(|d| d.trim_start_matches("www.").to_string())
+                .collect(),
+            block_links: env_number("CHAT_BLOCK_LINKS", false),
+        }
+    }
+
+    // Удаляет счётчики пользователей, чья квота уже полностью восстановилась
+    pub fn prune(&self) {
+        for limiter in [&self.message_limiter, &self.frame_limiter] {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+        }
+    }
+
+    // Любой входящий кадр: размер и частота, включая typing и ping
+    pub fn check_frame(&self, login: &str, text: &str) -> Result<(), ErrorFrame> {
+        if text.len() > self.max_frame_bytes {
+            return Err(ErrorFrame::new(
+                ERROR_FRAME_TOO_LARGE,
+                format!("Frame must not exceed {} bytes", self.max_frame_bytes),
+                None,
+            ));
+        }
+
+        self.frame_limiter.check_key(&login.to_string()).map_err(|not_until| {
+            ErrorFrame::new(
+                ERROR_RATE_LIMITED,
+                format!("Too many frames. Please try again in {} seconds", seconds_until(&not_until)),
+                None,
+            )
+        })
+    }
+
+    // Длина и содержимое текста сообщения; также при правке
+    pub fn check_body(&self, body: &str, client_id: Option<&str>) -> Result<(), ErrorFrame> {
+        let client_id = client_id.map(str::to_string);
+
+        if body.chars().count() > self.max_message_length {
+            return Err(ErrorFrame::new(
+                ERROR_MESSAGE_TOO_LONG,
+                format!("Message must not exceed {} characters", self.max_message_length),
+                client_id,
+            ));
+        }
+
+        let words = normalize_words(body);
+        if self.banned_words.iter().any(|banned| words.contains(&format!(" {} ", banned))) {
+            return Err(ErrorFrame::new(ERROR_FORBIDDEN_CONTENT, "Message contains banned words", client_id));
+        }
+
+        for (host, explicit) in body.split_whitespace().filter_map(link_host) {
+            let banned = self
+                .banned_domains
+                .iter()
+                .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+            if banned || (self.block_links && explicit) {
+                return Err(ErrorFrame::new(ERROR_FORBIDDEN_CONTENT, "Links are not allowed here", client_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Новое сообщение от пользователя: проверка содержимого, затем лимит частоты
+    pub fn check_message(&self, login: &str, body: &str, client_id: Option<&str>) -> Result<(), ErrorFrame> {
+        self.check_body(body, client_id)?;
+
+        self.message_limiter.check_key(&login.to_string()).map_err(|not_until| {
+            ErrorFrame::new(
+                ERROR_RATE_LIMITED,
+                format!("Too many messages. Please try again in {} seconds", seconds_until(&not_until)),
+                client_id.map(str::to_string),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_limits(banned_words: &[&str], banned_domains: &[&str], block_links: bool) -> ChatLimits {
+        let quota = Quota::per_minute(non_zero(DEFAULT_MESSAGES_PER_MINUTE));
+        ChatLimits {
+            max_message_length: 20,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            message_limiter: keyed_limiter(quota),
+            frame_limiter: keyed_limiter(quota),
+            banned_words: banned_words.iter().map(|w| normalize_words(w).trim().to_string()).collect(),
+            banned_domains: banned_domains.iter().map(|d| d.to_string()).collect(),
+            block_links,
+        }
+    }
+
+    fn rejected_with(result: Result<(), ErrorFrame>) -> Option<String> {
+        result.err().map(|error| error.code)
+    }
+
+    #[test]
+    async fn normalize_words_splits_on_punctuation() {
+        assert_eq!(normalize_words("Hello, World!"), " hello world ");
+        assert_eq!(normalize_words("  buy--NOW...please "), " buy now please ");
+        assert_eq!(normalize_words("Привет, МИР"), " привет мир ");
+        assert_eq!(normalize_words("?!"), "  ");
+    }
+
+    #[test]
+    async fn link_host_extracts_domain() {
+        assert_eq!(link_host("https://www.Example.com/path?q=1"), Some(("example.com".to_string(), true)));
+        assert_eq!(link_host("http://shop.example.com:8080"), Some(("shop.example.com".to_string(), true)));
+        assert_eq!(link_host("www.example.org,"), Some(("example.org".to_string(), true)));
+        assert_eq!(link_host("(example.com)"), Some(("example.com".to_string(), false)));
+    }
+
+    #[test]
+    async fn link_host_ignores_non_links() {
+        assert_eq!(link_host("ftp://example.com"), None);
+        assert_eq!(link_host("version1.2"), None);
+        assert_eq!(link_host("hello"), None);
+        assert_eq!(link_host(".com"), None);
+        assert_eq!(link_host("example.c"), None);
+    }
+
+    #[test]
+    async fn check_body_limits_length_in_characters() {
+        let limits = test_limits(&[], &[], false);
+        assert_eq!(rejected_with(limits.check_body(&"я".repeat(20), None)), None);
+        assert_eq!(
+            rejected_with(limits.check_body(&"я".repeat(21), None)).as_deref(),
+            Some(ERROR_MESSAGE_TOO_LONG)
+        );
+    }
+
+    #[test]
+    async fn check_body_matches_whole_banned_words() {
+        let limits = test_limits(&["spam", "free money"], &[], false);
+        assert_eq!(rejected_with(limits.check_body("SPAM!", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        assert_eq!(rejected_with(limits.check_body("get free  money", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        assert_eq!(rejected_with(limits.check_body("spammer", None)), None);
+        assert_eq!(rejected_with(limits.check_body("free of money", None)), None);
+    }
+
+    #[test]
+    async fn check_body_filters_links() {
+        let limits = test_limits(&[], &["bad.com"], false);
+        assert_eq!(rejected_with(limits.check_body("see bad.com", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        assert_eq!(rejected_with(limits.check_body("go to x.bad.com", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        assert_eq!(rejected_with(limits.check_body("notbad.com", None)), None);
+        assert_eq!(rejected_with(limits.check_body("https://ok.com", None)), None);
+
+        let limits = test_limits(&[], &[], true);
+        assert_eq!(rejected_with(limits.check_body("https://ok.com", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        assert_eq!(rejected_with(limits.check_body("www.ok.com", None)).as_deref(), Some(ERROR_FORBIDDEN_CONTENT));
+        // голый домен без схемы и www. пропускается
+        assert_eq!(rejected_with(limits.check_body("ok.com", None)), None);
+    }
+
+    #[test]
+    async fn check_body_keeps_client_id() {
+        let limits = test_limits(&["spam"], &[], false);
+        let error = limits.check_body("spam", Some("c1")).err().unwrap();
+        assert_eq!(error.client_id.as_deref(), Some("c1"));
+    }
+}
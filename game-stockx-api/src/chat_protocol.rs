@@ -0,0 +1,367 @@
+use actix::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::notifications::NotificationDto;
+
+// Версия протокола WebSocket-чата; кадры другой версии отклоняются с ошибкой
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const MAX_CLIENT_ID_LEN: usize = 64;
+
+pub const ERROR_BAD_FRAME: &str = "bad_frame";
+pub const ERROR_UNSUPPORTED_VERSION: &str = "unsupported_version";
+pub const ERROR_INVALID_MESSAGE: &str = "invalid_message";
+pub const ERROR_UNKNOWN_RECIPIENT: &str = "unknown_recipient";
+pub const ERROR_BLOCKED: &str = "blocked";
+pub const ERROR_INVALID_ATTACHMENT: &str = "invalid_attachment";
+pub const ERROR_UNKNOWN_CONVERSATION: &str = "unknown_conversation";
+pub const ERROR_RATE_LIMITED: &str = "rate_limited";
+pub const ERROR_FRAME_TOO_LARGE: &str = "frame_too_large";
+pub const ERROR_MESSAGE_TOO_LONG: &str = "message_too_long";
+pub const ERROR_FORBIDDEN_CONTENT: &str = "forbidden_content";
+pub const ERROR_INTERNAL: &str = "internal";
+
+// Кадр от клиента: {"v": 1, "type": "message", ...}
+#[derive(Deserialize)]
+pub struct InboundFrame {
+    pub v: u32,
+    #[serde(flatten)]
+    pub frame: ClientFrame,
+}
+
+impl InboundFrame {
+    // Разбирает кадр и проверяет версию протокола
+    pub fn parse(text: &str) -> Result<ClientFrame, ErrorFrame> {
+        let inbound = serde_json::from_str::<InboundFrame>(text)
+            .map_err(|err| ErrorFrame::new(ERROR_BAD_FRAME, err.to_string(), None))?;
+
+        if inbound.v != PROTOCOL_VERSION {
+            let message = format!("Supported protocol version is {}", PROTOCOL_VERSION);
+            return Err(ErrorFrame::new(ERROR_UNSUPPORTED_VERSION, message, None));
+        }
+        Ok(inbound.frame)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Message {
+        // генерирует клиент; повтор с тем же client_id не создаёт второе сообщение
+        client_id: Option<String>,
+        recipient: String,
+        // может быть пустым, если есть вложение
+        #[serde(default)]
+        body: String,
+        attachment: Option<AttachmentRef>,
+    },
+    // Сообщение в групповую беседу или ветку объявления/обмена
+    ConversationMessage {
+        client_id: Option<String>,
+        conversation_id: i32,
+        #[serde(default)]
+        body: String,
+        attachment: Option<AttachmentRef>,
+    },
+    Typing {
+        recipient: String,
+    },
+    Read {
+        companion: String,
+    },
+    Ping {
+        nonce: Option<String>,
+    },
+}
+
+// Кадр для клиента; версия добавляется при отправке
+#[derive(Message, Serialize, Deserialize, Clone)]
+#[rtype(result = "()")]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    Message(ChatMessage),
+    ConversationMessage(ConversationMessage),
+    MembershipChanged(MembershipChanged),
+    MessageEdited(MessageEdited),
+    MessageDeleted(MessageDeleted),
+    Ack(MessageAck),
+    Typing(TypingEvent),
+    Read(ReadReceipt),
+    Notification(NotificationDto),
+    Presence(PresenceEvent),
+    ReplayDone(ReplayDone),
+    Error(ErrorFrame),
+    Pong { nonce: Option<String> },
+}
+
+#[derive(Serialize)]
+pub struct OutboundFrame<'a> {
+    pub v: u32,
+    #[serde(flatten)]
+    pub frame: &'a ServerFrame,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    pub id: i32,
+    pub client_id: Option<String>,
+    pub sender: String,
+    pub recipient: String,
+    pub body: String,
+    pub attachment: Option<Attachment>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConversationMessage {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub client_id: Option<String>,
+    pub sender: String,
+    pub body: String,
+    pub attachment: Option<Attachment>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Участника добавили в беседу или он из неё вышел; actor - кто это сделал
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MembershipChanged {
+    pub conversation_id: i32,
+    pub login: String,
+    pub joined: bool,
+    pub actor: String,
+}
+
+// Ссылка на релиз, объявление или предложение обмена: {"type": "listing", "id": 5}
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AttachmentRef {
+    Release { id: i32 },
+    Listing { id: i32 },
+    Trade { id: i32 },
+}
+
+impl AttachmentRef {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AttachmentRef::Release { .. } => "release",
+            AttachmentRef::Listing { .. } => "listing",
+            AttachmentRef::Trade { .. } => "trade",
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            AttachmentRef::Release { id } | AttachmentRef::Listing { id } | AttachmentRef::Trade { id } => *id,
+        }
+    }
+
+    pub fn from_parts(kind: &str, id: i32) -> Option<AttachmentRef> {
+        match kind {
+            "release" => Some(AttachmentRef::Release { id }),
+            "listing" => Some(AttachmentRef::Listing { id }),
+            "trade" => Some(AttachmentRef::Trade { id }),
+            _ => None,
+        }
+    }
+}
+
+// Карточка вложения в том виде, в каком её показывает клиент
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: i32,
+    // статус объявления или обмена
+    pub status: Option<String>,
+    pub price: Option<i32>,
+    pub currency: Option<String>,
+    // у релиза и объявления один элемент, у обмена - все позиции с обеих сторон
+    pub items: Vec<AttachmentItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AttachmentItem {
+    pub release_id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+    pub platform_name: String,
+    pub region_name: Option<String>,
+    pub image_url: Option<String>,
+    // кто отдаёт позицию в обмене
+    pub from_login: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MessageEdited {
+    pub id: i32,
+    pub sender: String,
+    // у сообщения беседы вместо получателя conversation_id
+    pub recipient: Option<String>,
+    pub conversation_id: Option<i32>,
+    pub body: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+// Удаление для обоих уходит обоим участникам, скрытие у себя - только в свои сессии
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MessageDeleted {
+    pub id: i32,
+    pub sender: String,
+    pub recipient: Option<String>,
+    pub conversation_id: Option<i32>,
+    pub for_everyone: bool,
+    pub deleted_at: DateTime<Utc>,
+}
+
+// Подтверждение отправителю: сообщение сохранено под этим id
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MessageAck {
+    pub client_id: Option<String>,
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    // сообщение с таким client_id уже было сохранено раньше
+    pub duplicate: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TypingEvent {
+    pub sender: String,
+    pub recipient: String,
+}
+
+// Отчёт о прочтении: уходит отправителю и другим сессиям прочитавшего
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReadReceipt {
+    pub reader: String,
+    pub companion: String,
+    pub read_count: i64,
+    pub read_at: DateTime<Utc>,
+}
+
+// Конец досылки пропущенных сообщений после переподключения
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayDone {
+    pub after_id: i32,
+    pub count: usize,
+    // id последнего досланного сообщения; с него продолжать через GET /messages, если has_more
+    pub last_id: Option<i32>,
+    pub has_more: bool,
+}
+
+// Собеседник появился в сети или вышел из неё
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PresenceEvent {
+    pub login: String,
+    pub online: bool,
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ErrorFrame {
+    pub code: String,
+    pub message: String,
+    // к какому сообщению клиента относится ошибка
+    pub client_id: Option<String>,
+}
+
+impl ErrorFrame {
+    pub fn new(code: &str, message: impl Into<String>, client_id: Option<String>) -> ErrorFrame {
+        ErrorFrame {
+            code: code.to_string(),
+            message: message.into(),
+            client_id,
+        }
+    }
+}
+
+impl ServerFrame {
+    pub fn error(code: &str, message: impl Into<String>, client_id: Option<String>) -> ServerFrame {
+        ServerFrame::Error(ErrorFrame::new(code, message, client_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn parses_message_frame() {
+        let frame = InboundFrame::parse(r#"{"v": 1, "type": "message", "client_id": "c1", "recipient": "bob", "body": "hi"}"#);
+        match frame {
+            Ok(ClientFrame::Message { client_id, recipient, body, attachment }) => {
+                assert_eq!(client_id.as_deref(), Some("c1"));
+                assert_eq!(recipient, "bob");
+                assert_eq!(body, "hi");
+                assert!(attachment.is_none());
+            }
+            _ => panic!("expected a message frame"),
+        }
+    }
+
+    #[test]
+    async fn message_body_defaults_to_empty_with_attachment() {
+        let frame = InboundFrame::parse(
+            r#"{"v": 1, "type": "conversation_message", "conversation_id": 7, "attachment": {"type": "listing", "id": 5}}"#,
+        );
+        match frame {
+            Ok(ClientFrame::ConversationMessage { client_id, conversation_id, body, attachment }) => {
+                assert!(client_id.is_none());
+                assert_eq!(conversation_id, 7);
+                assert_eq!(body, "");
+                assert!(attachment == Some(AttachmentRef::Listing { id: 5 }));
+            }
+            _ => panic!("expected a conversation message frame"),
+        }
+    }
+
+    #[test]
+    async fn parses_service_frames() {
+        assert!(matches!(
+            InboundFrame::parse(r#"{"v": 1, "type": "typing", "recipient": "bob"}"#),
+            Ok(ClientFrame::Typing { recipient }) if recipient == "bob"
+        ));
+        assert!(matches!(
+            InboundFrame::parse(r#"{"v": 1, "type": "read", "companion": "bob"}"#),
+            Ok(ClientFrame::Read { companion }) if companion == "bob"
+        ));
+        assert!(matches!(
+            InboundFrame::parse(r#"{"v": 1, "type": "ping"}"#),
+            Ok(ClientFrame::Ping { nonce: None })
+        ));
+    }
+
+    #[test]
+    async fn rejects_other_protocol_versions() {
+        let error = InboundFrame::parse(r#"{"v": 2, "type": "ping"}"#).err().unwrap();
+        assert_eq!(error.code, ERROR_UNSUPPORTED_VERSION);
+
+        let error = InboundFrame::parse(r#"{"v": 0, "type": "typing", "recipient": "bob"}"#).err().unwrap();
+        assert_eq!(error.code, ERROR_UNSUPPORTED_VERSION);
+    }
+
+    #[test]
+    async fn rejects_malformed_frames() {
+        for text in [
+            "not json",
+            r#"{"type": "ping"}"#,
+            r#"{"v": 1, "type": "shout"}"#,
+            r#"{"v": 1, "type": "message", "body": "no recipient"}"#,
+            r#"{"v": 1, "type": "message", "recipient": "bob", "attachment": {"type": "photo", "id": 1}}"#,
+        ] {
+            let error = InboundFrame::parse(text).err().unwrap();
+            assert_eq!(error.code, ERROR_BAD_FRAME, "{}", text);
+        }
+    }
+
+    #[test]
+    async fn attachment_ref_from_parts() {
+        for kind in ["release", "listing", "trade"] {
+            let attachment = AttachmentRef::from_parts(kind, 42).unwrap();
+            assert_eq!(attachment.kind(), kind);
+            assert_eq!(attachment.id(), 42);
+        }
+        assert!(AttachmentRef::from_parts("Release", 1).is_none());
+        assert!(AttachmentRef::from_parts("photo", 1).is_none());
+    }
+}
@@ -2,13 +2,18 @@ use actix_web::{post, HttpRequest, HttpResponse, web};
 use actix_web::web::{Path};
 use crate::constants::{CONNECTION_POOL_ERROR};
 use crate::{DBPool};
-use crate::auth::{verify_jwt};
+use crate::auth::{require_login, verify_jwt};
+use crate::user_settings::load_settings;
 use diesel::prelude::*;
-use diesel::sql_types::{Text, Integer, Nullable, BigInt, Array};
+use diesel::sql_types::{Text, Integer, Nullable, BigInt, Array, Double};
 use actix_web::http::header;
 use serde::{Deserialize, Serialize};
 use crate::pagination::Pagination;
 use crate::metrics::{SUCCESSFUL_ADD_TO_COLLECTION, SUCCESSFUL_ADD_TO_WISHLIST};
+use crate::chat::ChatServer;
+use crate::listings::{insert_listing, CreateListingRequest};
+use crate::notifications;
+use actix::Addr;
 
 #[derive(Deserialize)]
 struct TrackReleaseRequest {
@@ -45,6 +50,21 @@ struct CollectionItem {
 
     #[diesel(sql_type = Nullable<Integer>)]
     price: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    price_guide_median: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    price_guide_q1: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    price_guide_q3: Option<i32>,
+
+    #[diesel(sql_type = Nullable<BigInt>)]
+    price_guide_samples: Option<i64>,
+
+    #[diesel(sql_type = Nullable<Double>)]
+    rarity_score: Option<f64>,
 }
 
 #[derive(Serialize)]
@@ -59,6 +79,13 @@ pub struct CountResult {
     pub total: i64,
 }
 
+// id строки из RETURNING id
+#[derive(QueryableByName)]
+pub struct IdResult {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+}
+
 
 #[derive(Serialize, QueryableByName)]
 struct CollectionStats {
@@ -164,11 +191,11 @@ async fn get_collection_stats(pool: web::Data<DBPool>, req: HttpRequest) -> Http
         FULL OUTER JOIN (
             SELECT 
             r.platform,
-            COUNT(uhb.release_id) AS release_count,
-            ARRAY_AGG(uhb.release_id) AS release_ids
-            FROM users_have_bids AS uhb
-            JOIN releases AS r ON uhb.release_id = r.id
-            WHERE uhb.user_login = $1
+            COUNT(DISTINCT l.release_id) AS release_count,
+            ARRAY_AGG(DISTINCT l.release_id) AS release_ids
+            FROM listings AS l
+            JOIN releases AS r ON l.release_id = r.id
+            WHERE l.seller_login = $1 AND l.status IN ('active', 'reserved')
             GROUP BY r.platform
         ) b ON COALESCE(h.platform, w.platform) = b.platform;
     "#;
@@ -222,13 +249,20 @@ async fn get_collection(pool: web::Data<DBPool>, req: HttpRequest, query: web::Q
             prod.id as product_id,
             prod.name AS product_name,
             '//89.104.66.193/static/covers-thumb/' || cover.id ||'.jpg' AS image_url,
-            reg.name AS region_name
+            reg.name AS region_name,
+            pg.median_price AS price_guide_median,
+            pg.q1_price AS price_guide_q1,
+            pg.q3_price AS price_guide_q3,
+            pg.sample_count AS price_guide_samples,
+            rr.rarity_score
         FROM public.users_have_releases AS uhr
         INNER JOIN releases AS r ON uhr.release_id = r.id
         INNER JOIN platforms AS p ON r.platform = p.id
         INNER JOIN products AS prod ON r.product_id = prod.id
         INNER JOIN covers AS cover ON cover.id = prod.cover_id
         INNER JOIN regions as reg on reg.id = r.release_region 
+        LEFT JOIN release_price_guide AS pg ON pg.release_id = r.id
+        LEFT JOIN release_rarity AS rr ON rr.release_id = r.id
         WHERE uhr.user_login = $1 AND p.id = $2
         ORDER BY prod.name
         LIMIT $3 OFFSET $4
@@ -273,6 +307,7 @@ async fn get_collection(pool: web::Data<DBPool>, req: HttpRequest, query: web::Q
 #[get("/collection-by-login/{login}")]
 async fn get_collection_by_login(
     pool: web::Data<DBPool>, 
+    req: HttpRequest,
     path: Path<String>, 
     query: web::Query<Pagination>
 ) -> HttpResponse {
@@ -288,6 +323,19 @@ async fn get_collection_by_login(
         Err(_) => return HttpResponse::InternalServerError().json("Database connection error"),
     };
     
+    // Закрытую коллекцию видит только её владелец
+    let viewer = require_login(&req).ok();
+    match load_settings(&mut conn, &login) {
+        Ok(settings) if settings.collection_private && viewer.as_deref() != Some(login.as_str()) => {
+            return HttpResponse::Forbidden().json("Collection is private");
+        }
+        Ok(_) => {}
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
     //let cat = query.cat;
     let limit = query.limit.unwrap_or(100).min(1000);
     let offset = query.offset.unwrap_or(0);
@@ -302,13 +350,20 @@ async fn get_collection_by_login(
             prod.name AS product_name,
             '//89.104.66.193/static/covers-thumb/' || cover.id ||'.jpg' AS image_url,
             reg.name AS region_name,
-            null AS price
+            null AS price,
+            pg.median_price AS price_guide_median,
+            pg.q1_price AS price_guide_q1,
+            pg.q3_price AS price_guide_q3,
+            pg.sample_count AS price_guide_samples,
+            rr.rarity_score
         FROM public.users_have_releases AS uhr
         INNER JOIN releases AS r ON uhr.release_id = r.id
         INNER JOIN platforms AS p ON r.platform = p.id
         INNER JOIN products AS prod ON r.product_id = prod.id
         INNER JOIN covers AS cover ON cover.id = prod.cover_id
         INNER JOIN regions as reg on reg.id = r.release_region 
+        LEFT JOIN release_price_guide AS pg ON pg.release_id = r.id
+        LEFT JOIN release_rarity AS rr ON rr.release_id = r.id
         WHERE uhr.user_login = $1
         ORDER BY prod.name
         LIMIT $2 OFFSET $3
@@ -332,6 +387,70 @@ async fn get_collection_by_login(
 }
 
 
+#[derive(Deserialize)]
+struct RarestQuery {
+    limit: Option<i64>,
+}
+
+// Самые редкие релизы в коллекции пользователя по оценке сообщества
+#[get("/collection/rarest")]
+async fn get_rarest_in_collection(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<RarestQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(10).clamp(1, 100);
+
+    let query = r#"
+        SELECT 
+            uhr.release_id,
+            uhr.price,
+            r.release_date,
+            r.serial,
+            p.name as platform_name,
+            prod.id as product_id,
+            prod.name AS product_name,
+            '//89.104.66.193/static/covers-thumb/' || cover.id ||'.jpg' AS image_url,
+            reg.name AS region_name,
+            pg.median_price AS price_guide_median,
+            pg.q1_price AS price_guide_q1,
+            pg.q3_price AS price_guide_q3,
+            pg.sample_count AS price_guide_samples,
+            rr.rarity_score
+        FROM public.users_have_releases AS uhr
+        INNER JOIN releases AS r ON uhr.release_id = r.id
+        INNER JOIN platforms AS p ON r.platform = p.id
+        INNER JOIN products AS prod ON r.product_id = prod.id
+        LEFT JOIN covers AS cover ON cover.id = prod.cover_id
+        LEFT JOIN regions as reg on reg.id = r.release_region 
+        LEFT JOIN release_price_guide AS pg ON pg.release_id = r.id
+        INNER JOIN release_rarity AS rr ON rr.release_id = r.id
+        WHERE uhr.user_login = $1
+        ORDER BY rr.rarity_score DESC, rr.owner_count ASC, prod.name
+        LIMIT $2
+    "#;
+
+    let result = diesel::sql_query(query)
+        .bind::<Text, _>(&user_login)
+        .bind::<BigInt, _>(limit)
+        .load::<CollectionItem>(conn);
+
+    match result {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+
 #[get("/wishlist")]
 async fn get_wishlist(pool: web::Data<DBPool>, req: HttpRequest, query: web::Query<Pagination>) -> HttpResponse {
     // Извлечение токена из заголовка
@@ -369,13 +488,20 @@ async fn get_wishlist(pool: web::Data<DBPool>, req: HttpRequest, query: web::Que
             '//89.104.66.193/static/covers-thumb/' || cover.id ||'.jpg' AS image_url,
             reg.name AS region_name,
             ARRAY[]::text[] AS serial,
-            null as price
+            null as price,
+            pg.median_price AS price_guide_median,
+            pg.q1_price AS price_guide_q1,
+            pg.q3_price AS price_guide_q3,
+            pg.sample_count AS price_guide_samples,
+            rr.rarity_score
         FROM public.users_have_wishes AS uhw
         INNER JOIN releases AS r ON uhw.release_id = r.id
         INNER JOIN platforms AS p ON r.platform = p.id
         INNER JOIN products AS prod ON r.product_id = prod.id
         INNER JOIN covers AS cover ON cover.id = prod.cover_id
         INNER JOIN regions as reg on reg.id = r.release_region 
+        LEFT JOIN release_price_guide AS pg ON pg.release_id = r.id
+        LEFT JOIN release_rarity AS rr ON rr.release_id = r.id
         WHERE uhw.user_login = $1 AND p.id = $2
         ORDER BY prod.name
         LIMIT $3 OFFSET $4
@@ -661,6 +787,7 @@ async fn remove_wish(
 #[post("/add_bid")]
 async fn add_bid(
     pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
     req: HttpRequest,
     data: web::Json<TrackReleaseRequest>,
 ) -> HttpResponse {
@@ -686,24 +813,33 @@ async fn add_bid(
 
     let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
 
-    let insert_query = r#"
-        INSERT INTO users_have_bids (release_id, user_login)
-        VALUES ($1, $2)
-        ON CONFLICT DO NOTHING
-    "#;
-
-    let result = diesel::sql_query(insert_query)
-        .bind::<Integer, _>(data.release_id)
-        .bind::<Text, _>(&user_login)
-        .execute(conn);
-
-    match result {
-        Ok(_) => HttpResponse::Ok().body({}),
+    // Флаг "готов продать" - объявление без цены, если активного ещё нет
+    let existing = diesel::sql_query(
+        r#"
+        SELECT COUNT(*) AS total FROM listings
+        WHERE release_id = $1 AND seller_login = $2 AND status IN ('active', 'reserved')
+        "#,
+    )
+    .bind::<Integer, _>(data.release_id)
+    .bind::<Text, _>(&user_login)
+    .get_result::<CountResult>(conn);
+
+    match existing {
+        Ok(count) if count.total > 0 => return HttpResponse::Ok().finish(),
+        Ok(_) => {}
         Err(err) => {
-            eprintln!("Insert error: {:?}", err);
-            HttpResponse::InternalServerError().finish()
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
         }
     }
+
+    match insert_listing(conn, &user_login, &CreateListingRequest::unpriced(data.release_id)) {
+        Ok((_, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().body({})
+        }
+        Err(err) => err.into_response(),
+    }
 }
 
 #[post("/remove_bid")]
@@ -735,8 +871,9 @@ async fn remove_bid(
     let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
 
     let delete_query = r#"
-        DELETE FROM users_have_bids
-        WHERE release_id = $1 AND user_login = $2
+        UPDATE listings
+        SET status = 'withdrawn', updated_at = NOW()
+        WHERE release_id = $1 AND seller_login = $2 AND status = 'active'
     "#;
 
     let result = diesel::sql_query(delete_query)
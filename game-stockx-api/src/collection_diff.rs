@@ -0,0 +1,158 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::trade_matches::{load_matched_releases, MatchedRelease};
+use crate::user_settings::load_settings;
+use crate::DBPool;
+
+#[derive(Deserialize)]
+pub struct CollectionDiffQuery {
+    pub platform: Option<i32>,
+}
+
+#[derive(QueryableByName)]
+struct DiffRow {
+    #[diesel(sql_type = Integer)]
+    release_id: i32,
+
+    #[diesel(sql_type = Text)]
+    category: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct PlatformDiff {
+    platform_id: i32,
+    platform_name: String,
+    // есть только у собеседника
+    only_they_own: Vec<MatchedRelease>,
+    // есть только у меня
+    only_i_own: Vec<MatchedRelease>,
+    // есть у собеседника и есть в моём вишлисте
+    on_my_wishlist: Vec<MatchedRelease>,
+}
+
+#[derive(Serialize)]
+pub struct CollectionDiffResponse {
+    user_login: String,
+    platforms: Vec<PlatformDiff>,
+}
+
+// Сравнение моей коллекции и вишлиста с коллекцией другого пользователя,
+// сгруппированное по платформам. Закрытую коллекцию сравнить нельзя.
+#[get("/collection-diff/{login}")]
+async fn get_collection_diff(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<String>,
+    query: web::Query<CollectionDiffQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let other_login = path.into_inner();
+    if other_login == user_login {
+        return HttpResponse::BadRequest().body("Cannot compare collection with itself");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_settings(conn, &other_login) {
+        Ok(settings) if settings.collection_private => {
+            return HttpResponse::Forbidden().body("Collection is private");
+        }
+        Ok(_) => {}
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let diff_query = r#"
+        WITH mine AS (
+            SELECT uhr.release_id
+            FROM users_have_releases AS uhr
+            INNER JOIN releases AS r ON r.id = uhr.release_id
+            WHERE uhr.user_login = $1 AND ($3::int IS NULL OR r.platform = $3)
+        ),
+        theirs AS (
+            SELECT uhr.release_id
+            FROM users_have_releases AS uhr
+            INNER JOIN releases AS r ON r.id = uhr.release_id
+            WHERE uhr.user_login = $2 AND ($3::int IS NULL OR r.platform = $3)
+        )
+        SELECT t.release_id, 'only_they_own' AS category
+        FROM theirs AS t
+        WHERE NOT EXISTS (SELECT 1 FROM mine AS m WHERE m.release_id = t.release_id)
+        UNION ALL
+        SELECT m.release_id, 'only_i_own' AS category
+        FROM mine AS m
+        WHERE NOT EXISTS (SELECT 1 FROM theirs AS t WHERE t.release_id = m.release_id)
+        UNION ALL
+        SELECT t.release_id, 'on_my_wishlist' AS category
+        FROM theirs AS t
+        INNER JOIN users_have_wishes AS uhw
+            ON uhw.release_id = t.release_id AND uhw.user_login = $1
+    "#;
+
+    let rows = match diesel::sql_query(diff_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Text, _>(&other_login)
+        .bind::<Nullable<Integer>, _>(query.platform)
+        .load::<DiffRow>(conn)
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let release_ids: Vec<i32> = rows.iter().map(|r| r.release_id).collect();
+    let releases = match load_matched_releases(conn, &release_ids) {
+        Ok(releases) => releases,
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut by_platform: BTreeMap<(String, i32), PlatformDiff> = BTreeMap::new();
+    for row in rows {
+        let Some(release) = releases.get(&row.release_id).cloned() else {
+            continue;
+        };
+
+        let group = by_platform
+            .entry((release.platform_name.clone(), release.platform_id))
+            .or_insert_with(|| PlatformDiff {
+                platform_id: release.platform_id,
+                platform_name: release.platform_name.clone(),
+                ..Default::default()
+            });
+
+        match row.category.as_str() {
+            "only_they_own" => group.only_they_own.push(release),
+            "only_i_own" => group.only_i_own.push(release),
+            _ => group.on_my_wishlist.push(release),
+        }
+    }
+
+    let mut platforms: Vec<PlatformDiff> = by_platform.into_values().collect();
+    for group in &mut platforms {
+        for list in [&mut group.only_they_own, &mut group.only_i_own, &mut group.on_my_wishlist] {
+            list.sort_by(|a, b| a.product_name.cmp(&b.product_name).then(a.release_id.cmp(&b.release_id)));
+        }
+    }
+
+    HttpResponse::Ok().json(CollectionDiffResponse {
+        user_login: other_login,
+        platforms,
+    })
+}
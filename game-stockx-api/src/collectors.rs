@@ -1,73 +1,218 @@
 use actix_web::{HttpRequest, HttpResponse, web, get};
+use crate::auth::require_login;
+use crate::collection::CountResult;
 use crate::constants::CONNECTION_POOL_ERROR;
-use crate::DBPool;
-use crate::auth::verify_jwt;
-use actix_web::http::header;
+use crate::{DBPool, redis::{RedisPool, RedisCacheExt}};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use diesel::sql_types::{Text, BigInt};
-use serde::Serialize;
+use diesel::sql_types::{BigInt, Double, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+
+const LEADERBOARD_CACHE_TTL: usize = 120;
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub platform: Option<i32>,
+    pub region: Option<i32>,
+    // count (по умолчанию), value, completion, rarity
+    pub sort: Option<String>,
+}
+
+#[derive(Clone, QueryableByName, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    #[diesel(sql_type = BigInt)]
+    rank: i64,
 
-#[derive(QueryableByName, Serialize)]
-struct Collector {
     #[diesel(sql_type = Text)]
     user_login: String,
 
     #[diesel(sql_type = BigInt)]
     release_count: i64,
+
+    #[diesel(sql_type = BigInt)]
+    collection_value: i64,
+
+    #[diesel(sql_type = Nullable<Double>)]
+    completion_pct: Option<f64>,
+
+    #[diesel(sql_type = Double)]
+    rarity_score: f64,
+
+    #[diesel(sql_type = Timestamptz)]
+    refreshed_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct LeaderboardPage {
+    items: Vec<LeaderboardEntry>,
+    total_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardResponse {
+    items: Vec<LeaderboardEntry>,
+    total_count: i64,
+    // место текущего пользователя в выбранном срезе
+    my_rank: Option<LeaderboardEntry>,
+}
+
+fn rank_column(sort: &str) -> Option<&'static str> {
+    match sort {
+        "count" => Some("rank_count"),
+        "value" => Some("rank_value"),
+        "completion" => Some("rank_completion"),
+        "rarity" => Some("rank_rarity"),
+        _ => None,
+    }
 }
 
+fn leaderboard_select(rank_column: &str) -> String {
+    format!(
+        r#"
+        SELECT
+            {} AS rank,
+            user_login,
+            release_count,
+            collection_value,
+            completion_pct,
+            rarity_score,
+            refreshed_at
+        FROM collector_leaderboard
+        WHERE platform_scope = $1 AND region_scope = $2
+        "#,
+        rank_column
+    )
+}
+
+fn build_cache_key(platform: i32, region: i32, sort: &str, limit: i64, offset: i64) -> String {
+    format!(
+        "collectors:platform_{}:region_{}:sort_{}:limit_{}:offset_{}",
+        platform, region, sort, limit, offset
+    )
+}
+
+// Рейтинг коллекционеров из предрасчитанной витрины collector_leaderboard
 #[get("/collectors")]
-async fn get_collectors(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
-    let token = match req.headers().get(header::AUTHORIZATION) {
-        Some(header_value) => {
-            let header_str = header_value.to_str().unwrap_or("");
-            if header_str.starts_with("Bearer ") {
-                Some(&header_str[7..])
-            } else {
-                None
-            }
-        }
-        None => None,
+async fn get_collectors(
+    pool: web::Data<DBPool>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    query: web::Query<LeaderboardQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
     };
 
-    let claims = match token.and_then(|t| verify_jwt(t)) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().body("Invalid or missing token"),
+    let sort = query.sort.clone().unwrap_or_else(|| "count".to_string());
+    let rank_column = match rank_column(&sort) {
+        Some(column) => column,
+        None => return HttpResponse::BadRequest().body("Sort must be one of: count, value, completion, rarity"),
     };
 
-    let user_login = claims.sub;
+    let platform = query.platform.unwrap_or(0);
+    let region = query.region.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let cache_key = build_cache_key(platform, region, &sort, limit, offset);
+    let mut page = None;
+
+    if let Ok(mut redis_conn) = redis_pool.get().await
+        && let Ok(Some(cached)) = redis_conn.get_json::<LeaderboardPage>(&cache_key).await
+    {
+        page = Some(cached);
+    }
 
     let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
 
-    let query = r#"
-        SELECT 
-            u.user_login,
-            COUNT(uhr.release_id) AS release_count
-        FROM 
-            users u
-        INNER JOIN 
-            users_have_releases uhr ON u.user_login = uhr.user_login
-        WHERE u.user_login <> $1 
-        GROUP BY 
-            u.user_login
-        HAVING 
-            COUNT(uhr.release_id) > 0
-        ORDER BY 
-            release_count DESC;
-    "#;
-
-    let result = diesel::sql_query(query)
+    let page = match page {
+        Some(page) => page,
+        None => match load_page(conn, rank_column, platform, region, limit, offset) {
+            Ok(page) => {
+                if let Ok(mut redis_conn) = redis_pool.get().await {
+                    let _ = redis_conn.set_json(&cache_key, &page, LEADERBOARD_CACHE_TTL).await;
+                }
+                page
+            }
+            Err(err) => {
+                eprintln!("Database error: {:?}", err);
+                return HttpResponse::InternalServerError().body("Database error");
+            }
+        },
+    };
+
+    let my_rank_query = format!("{} AND user_login = $3", leaderboard_select(rank_column));
+    let my_rank = diesel::sql_query(my_rank_query)
+        .bind::<Integer, _>(platform)
+        .bind::<Integer, _>(region)
         .bind::<Text, _>(&user_login)
-        .load::<Collector>(conn);
+        .get_result::<LeaderboardEntry>(conn)
+        .optional();
 
-    match result {
-        Ok(collectors) => {
-            // Сериализуем результат в JSON
-            HttpResponse::Ok().json(collectors)
-        }
+    match my_rank {
+        Ok(my_rank) => HttpResponse::Ok().json(LeaderboardResponse {
+            items: page.items,
+            total_count: page.total_count,
+            my_rank,
+        }),
         Err(err) => {
             eprintln!("Database error: {:?}", err);
             HttpResponse::InternalServerError().body("Database error")
         }
     }
-}
\ No newline at end of file
+}
+
+fn load_page(
+    conn: &mut PgConnection,
+    rank_column: &str,
+    platform: i32,
+    region: i32,
+    limit: i64,
+    offset: i64,
+) -> Result<LeaderboardPage, diesel::result::Error> {
+    let list_query = format!(
+        "{} ORDER BY {}, user_login LIMIT $3 OFFSET $4",
+        leaderboard_select(rank_column),
+        rank_column
+    );
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Integer, _>(platform)
+        .bind::<Integer, _>(region)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<LeaderboardEntry>(conn)?;
+
+    let total_count = diesel::sql_query(
+        "SELECT COUNT(*) AS total FROM collector_leaderboard WHERE platform_scope = $1 AND region_scope = $2",
+    )
+    .bind::<Integer, _>(platform)
+    .bind::<Integer, _>(region)
+    .get_result::<CountResult>(conn)?
+    .total;
+
+    Ok(LeaderboardPage { items, total_count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn rank_column_maps_known_sorts() {
+        assert_eq!(rank_column("count"), Some("rank_count"));
+        assert_eq!(rank_column("value"), Some("rank_value"));
+        assert_eq!(rank_column("completion"), Some("rank_completion"));
+        assert_eq!(rank_column("rarity"), Some("rank_rarity"));
+    }
+
+    #[test]
+    async fn rank_column_rejects_unknown_sorts() {
+        assert_eq!(rank_column(""), None);
+        assert_eq!(rank_column("Count"), None);
+        assert_eq!(rank_column("rank_count; DROP TABLE users"), None);
+    }
+}
@@ -0,0 +1,812 @@
+use actix::Addr;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::ApiError;
+use crate::auth::require_login;
+use crate::blocks::{blocked_logins, is_blocked_between};
+use crate::chat::{insert_error, insert_message, internal_error, ChatCommand, ChatServer};
+use crate::chat_attachments::{attachment_ref, resolve_attachment};
+use crate::chat_bus::ClusterEvent;
+use crate::chat_history::{HistoryCursor, HistoryRow};
+use crate::chat_protocol::{
+    AttachmentRef, ConversationMessage, ErrorFrame, MembershipChanged, ServerFrame, ERROR_BLOCKED,
+    ERROR_INVALID_ATTACHMENT, ERROR_UNKNOWN_CONVERSATION,
+};
+use crate::collection::IdResult;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+pub const KIND_GROUP: &str = "group";
+pub const KIND_LISTING: &str = "listing";
+pub const KIND_TRADE: &str = "trade";
+
+const ROLE_OWNER: &str = "owner";
+const ROLE_MEMBER: &str = "member";
+
+const MAX_GROUP_MEMBERS: usize = 50;
+const MAX_TITLE_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+pub struct CreateGroupRequest {
+    title: String,
+    // приглашённые, без создателя
+    members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AddMemberRequest {
+    login: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConversationMessagesQuery {
+    before_id: Option<i32>,
+    after_id: Option<i32>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct ConversationDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    // group, listing или trade
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub title: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub listing_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub trade_id: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    pub created_by: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = BigInt)]
+    pub member_count: i64,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub last_message_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub last_message: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub last_message_sender: Option<String>,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub last_message_time: Option<DateTime<Utc>>,
+
+    #[diesel(sql_type = BigInt)]
+    pub unread_count: i64,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct MemberDto {
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    // owner или member
+    #[diesel(sql_type = Text)]
+    pub role: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ConversationDetails {
+    #[serde(flatten)]
+    conversation: ConversationDto,
+    members: Vec<MemberDto>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct ConversationMessageDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub sender: String,
+    #[diesel(sql_type = Text)]
+    pub body: String,
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub client_id: Option<String>,
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub edited_at: Option<DateTime<Utc>>,
+    #[diesel(sql_type = Bool)]
+    pub deleted: bool,
+    #[serde(skip)]
+    #[diesel(sql_type = Nullable<Text>)]
+    pub attachment_type: Option<String>,
+    #[serde(skip)]
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub attachment_id: Option<i32>,
+}
+
+impl HistoryRow for ConversationMessageDto {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn attachment_ref(&self) -> Option<AttachmentRef> {
+        attachment_ref(self.attachment_type.as_deref(), self.attachment_id)
+    }
+}
+
+#[derive(Serialize)]
+pub struct ConversationReadResponse {
+    last_read_message_id: Option<i32>,
+}
+
+#[derive(QueryableByName)]
+struct Login {
+    #[diesel(sql_type = Text)]
+    user_login: String,
+}
+
+#[derive(QueryableByName)]
+struct ConversationInfo {
+    #[diesel(sql_type = Text)]
+    kind: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    my_role: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct TradeParties {
+    #[diesel(sql_type = Text)]
+    proposer_login: String,
+
+    #[diesel(sql_type = Text)]
+    recipient_login: String,
+}
+
+#[derive(QueryableByName)]
+struct LastRead {
+    #[diesel(sql_type = Nullable<Integer>)]
+    last_read_message_id: Option<i32>,
+}
+
+// $1 - логин смотрящего; беседы только те, где он участник.
+// Сообщения тех, с кем у смотрящего блокировка, не видны и не считаются непрочитанными.
+const CONVERSATION_SELECT: &str = r#"
+    SELECT
+        c.id,
+        c.kind,
+        c.title,
+        c.listing_id,
+        c.trade_id,
+        c.created_by,
+        c.created_at,
+        (SELECT COUNT(*) FROM conversation_members AS cm WHERE cm.conversation_id = c.id) AS member_count,
+        last.id AS last_message_id,
+        last.body AS last_message,
+        last.sender_login AS last_message_sender,
+        last.created_at AS last_message_time,
+        (
+            SELECT COUNT(*) FROM messages AS u
+            WHERE u.conversation_id = c.id
+              AND u.id > COALESCE(me.last_read_message_id, 0)
+              AND u.sender_login <> $1
+              AND u.deleted_at IS NULL
+              AND NOT EXISTS (
+                SELECT 1 FROM user_blocks AS b
+                WHERE (b.blocker_login = $1 AND b.blocked_login = u.sender_login)
+                   OR (b.blocker_login = u.sender_login AND b.blocked_login = $1)
+              )
+        ) AS unread_count
+    FROM conversations AS c
+    INNER JOIN conversation_members AS me ON me.conversation_id = c.id AND me.user_login = $1
+    LEFT JOIN LATERAL (
+        SELECT m.id, m.body, m.sender_login, m.created_at
+        FROM messages AS m
+        WHERE m.conversation_id = c.id AND NOT (m.sender_login = $1 AND m.hidden_for_sender)
+          AND NOT EXISTS (
+            SELECT 1 FROM user_blocks AS b
+            WHERE (b.blocker_login = $1 AND b.blocked_login = m.sender_login)
+               OR (b.blocker_login = m.sender_login AND b.blocked_login = $1)
+          )
+        ORDER BY m.id DESC
+        LIMIT 1
+    ) AS last ON TRUE
+"#;
+
+pub fn member_logins(conn: &mut PgConnection, conversation_id: i32) -> QueryResult<Vec<String>> {
+    diesel::sql_query("SELECT user_login FROM conversation_members WHERE conversation_id = $1")
+        .bind::<Integer, _>(conversation_id)
+        .load::<Login>(conn)
+        .map(|rows| rows.into_iter().map(|r| r.user_login).collect())
+}
+
+// Сохраняет сообщение в беседу; возвращает его, признак повтора и получателей для доставки.
+// В беседе на двоих блокировка запрещает писать, как и в личных сообщениях;
+// в группе сообщение просто не доставляется тем, с кем у отправителя блокировка.
+pub fn store_conversation_message(
+    pool: &DBPool,
+    sender: &str,
+    conversation_id: i32,
+    body: &str,
+    attachment: Option<&AttachmentRef>,
+    client_id: Option<&str>,
+) -> Result<(ConversationMessage, bool, Vec<String>), ErrorFrame> {
+    let conn = &mut pool.get().map_err(|err| internal_error(&err, client_id))?;
+
+    let members = member_logins(conn, conversation_id).map_err(|err| internal_error(&err, client_id))?;
+    if !members.iter().any(|m| m == sender) {
+        return Err(ErrorFrame::new(
+            ERROR_UNKNOWN_CONVERSATION,
+            "Conversation not found",
+            client_id.map(str::to_string),
+        ));
+    }
+
+    let blocked = blocked_logins(conn, sender).map_err(|err| internal_error(&err, client_id))?;
+    if members.len() == 2 && members.iter().any(|m| blocked.contains(m)) {
+        return Err(ErrorFrame::new(
+            ERROR_BLOCKED,
+            "Messaging between these users is blocked",
+            client_id.map(str::to_string),
+        ));
+    }
+
+    let card = match attachment {
+        Some(reference) => match resolve_attachment(conn, reference, sender, None) {
+            Ok(Some(card)) => Some(card),
+            Ok(None) => {
+                return Err(ErrorFrame::new(
+                    ERROR_INVALID_ATTACHMENT,
+                    format!("Attached {} not found", reference.kind()),
+                    client_id.map(str::to_string),
+                ));
+            }
+            Err(err) => return Err(internal_error(&err, client_id)),
+        },
+        None => None,
+    };
+
+    let saved = insert_message(conn, sender, None, Some(conversation_id), body, attachment, client_id)
+        .map_err(|err| insert_error(err, client_id))?;
+
+    let message = ConversationMessage {
+        id: saved.id,
+        conversation_id,
+        client_id: client_id.map(str::to_string),
+        sender: sender.to_string(),
+        body: body.to_string(),
+        attachment: card,
+        created_at: saved.created_at,
+    };
+    let recipients = members
+        .into_iter()
+        .filter(|m| m != sender && !blocked.contains(m))
+        .collect();
+    Ok((message, saved.duplicate, recipients))
+}
+
+fn load_conversation(conn: &mut PgConnection, conversation_id: i32, login: &str) -> Result<ConversationDetails, ApiError> {
+    let conversation = diesel::sql_query(format!("{} WHERE c.id = $2", CONVERSATION_SELECT))
+        .bind::<Text, _>(login)
+        .bind::<Integer, _>(conversation_id)
+        .get_result::<ConversationDto>(conn)?;
+
+    let members = diesel::sql_query(
+        r#"
+        SELECT user_login, role, joined_at
+        FROM conversation_members
+        WHERE conversation_id = $1
+        ORDER BY role = 'owner' DESC, joined_at, user_login
+        "#,
+    )
+    .bind::<Integer, _>(conversation_id)
+    .load::<MemberDto>(conn)?;
+
+    Ok(ConversationDetails { conversation, members })
+}
+
+// Вид беседы и роль в ней пользователя; NotFound, если беседы нет или он не участник
+fn conversation_info(conn: &mut PgConnection, conversation_id: i32, login: &str) -> Result<ConversationInfo, ApiError> {
+    let info = diesel::sql_query(
+        r#"
+        SELECT c.kind, m.role AS my_role
+        FROM conversations AS c
+        LEFT JOIN conversation_members AS m ON m.conversation_id = c.id AND m.user_login = $2
+        WHERE c.id = $1
+        FOR UPDATE OF c
+        "#,
+    )
+    .bind::<Integer, _>(conversation_id)
+    .bind::<Text, _>(login)
+    .get_result::<ConversationInfo>(conn)?;
+
+    if info.my_role.is_none() {
+        return Err(ApiError::NotFound);
+    }
+    Ok(info)
+}
+
+fn add_member(conn: &mut PgConnection, conversation_id: i32, login: &str, role: &str) -> Result<bool, ApiError> {
+    diesel::sql_query(
+        r#"
+        INSERT INTO conversation_members (conversation_id, user_login, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind::<Integer, _>(conversation_id)
+    .bind::<Text, _>(login)
+    .bind::<Text, _>(role)
+    .execute(conn)
+    .map(|inserted| inserted > 0)
+    .map_err(|err| match err {
+        diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _) => {
+            ApiError::BadRequest(format!("User {} not found", login))
+        }
+        other => ApiError::from(other),
+    })
+}
+
+// Ветка по объявлению или обмену: находит существующую или создаёт с обоими участниками
+fn open_thread(
+    conn: &mut PgConnection,
+    kind: &str,
+    subject_id: i32,
+    creator: &str,
+    other: &str,
+) -> Result<i32, ApiError> {
+    if is_blocked_between(conn, creator, other)? {
+        return Err(ApiError::Forbidden);
+    }
+
+    let (listing_id, trade_id, existing) = if kind == KIND_LISTING {
+        let existing = diesel::sql_query(
+            "SELECT id FROM conversations WHERE kind = 'listing' AND listing_id = $1 AND created_by = $2",
+        )
+        .bind::<Integer, _>(subject_id)
+        .bind::<Text, _>(creator)
+        .get_result::<IdResult>(conn)
+        .optional()?;
+        (Some(subject_id), None, existing)
+    } else {
+        let existing = diesel::sql_query("SELECT id FROM conversations WHERE kind = 'trade' AND trade_id = $1")
+            .bind::<Integer, _>(subject_id)
+            .get_result::<IdResult>(conn)
+            .optional()?;
+        (None, Some(subject_id), existing)
+    };
+
+    if let Some(existing) = existing {
+        return Ok(existing.id);
+    }
+
+    let conversation_id = diesel::sql_query(
+        r#"
+        INSERT INTO conversations (kind, listing_id, trade_id, created_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+    )
+    .bind::<Text, _>(kind)
+    .bind::<Nullable<Integer>, _>(listing_id)
+    .bind::<Nullable<Integer>, _>(trade_id)
+    .bind::<Text, _>(creator)
+    .get_result::<IdResult>(conn)
+    .map_err(|err| match err {
+        // ветку параллельно открыл второй участник
+        diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::Conflict("Thread is being created, retry the request".to_string())
+        }
+        other => ApiError::from(other),
+    })?
+    .id;
+
+    add_member(conn, conversation_id, creator, ROLE_MEMBER)?;
+    add_member(conn, conversation_id, other, ROLE_MEMBER)?;
+    Ok(conversation_id)
+}
+
+fn membership_event(members: Vec<String>, conversation_id: i32, login: &str, joined: bool, actor: &str) -> ClusterEvent {
+    ClusterEvent {
+        recipients: members,
+        frame: ServerFrame::MembershipChanged(MembershipChanged {
+            conversation_id,
+            login: login.to_string(),
+            joined,
+            actor: actor.to_string(),
+        }),
+    }
+}
+
+#[get("/conversations")]
+async fn get_conversations(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let query = format!(
+        "{} ORDER BY COALESCE(last.id, 0) DESC, c.id DESC LIMIT 200",
+        CONVERSATION_SELECT
+    );
+
+    match diesel::sql_query(query).bind::<Text, _>(&user_login).load::<ConversationDto>(conn) {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/conversations/{id}")]
+async fn get_conversation(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_conversation(conn, path.into_inner(), &user_login) {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Групповая беседа: создатель становится владельцем и может менять состав
+#[post("/conversations")]
+async fn create_group(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    data: web::Json<CreateGroupRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let title = data.title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+        return HttpResponse::BadRequest().body(format!("Title must be 1 to {} characters long", MAX_TITLE_LENGTH));
+    }
+
+    let mut invited: Vec<&str> = Vec::new();
+    for login in data.members.iter().map(|l| l.trim()).filter(|l| !l.is_empty() && *l != user_login) {
+        if !invited.contains(&login) {
+            invited.push(login);
+        }
+    }
+    if invited.is_empty() {
+        return HttpResponse::BadRequest().body("At least one other member is required");
+    }
+    if invited.len() + 1 > MAX_GROUP_MEMBERS {
+        return HttpResponse::BadRequest().body(format!("At most {} members per conversation", MAX_GROUP_MEMBERS));
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let conversation_id = diesel::sql_query(
+            "INSERT INTO conversations (kind, title, created_by) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind::<Text, _>(KIND_GROUP)
+        .bind::<Text, _>(title)
+        .bind::<Text, _>(&user_login)
+        .get_result::<IdResult>(conn)?
+        .id;
+
+        add_member(conn, conversation_id, &user_login, ROLE_OWNER)?;
+        for login in &invited {
+            if is_blocked_between(conn, &user_login, login)? {
+                return Err(ApiError::BadRequest(format!("User {} cannot be added", login)));
+            }
+            add_member(conn, conversation_id, login, ROLE_MEMBER)?;
+        }
+
+        load_conversation(conn, conversation_id, &user_login)
+    });
+
+    match result {
+        Ok(details) => {
+            let members: Vec<String> = details.members.iter().map(|m| m.user_login.clone()).collect();
+            for login in &invited {
+                chat.do_send(ChatCommand::Deliver {
+                    event: membership_event(members.clone(), details.conversation.id, login, true, &user_login),
+                });
+            }
+            HttpResponse::Created().json(details)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+// Ветка покупателя с продавцом по объявлению
+#[post("/listings/{id}/thread")]
+async fn open_listing_thread(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let listing_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let seller = diesel::sql_query("SELECT seller_login AS user_login FROM listings WHERE id = $1")
+            .bind::<Integer, _>(listing_id)
+            .get_result::<Login>(conn)?
+            .user_login;
+
+        if seller == user_login {
+            return Err(ApiError::BadRequest("Cannot open a thread on your own listing".to_string()));
+        }
+
+        let conversation_id = open_thread(conn, KIND_LISTING, listing_id, &user_login, &seller)?;
+        load_conversation(conn, conversation_id, &user_login)
+    });
+
+    match result {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Общая ветка участников обмена
+#[post("/trades/{id}/thread")]
+async fn open_trade_thread(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let trade_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let parties = diesel::sql_query("SELECT proposer_login, recipient_login FROM trades WHERE id = $1")
+            .bind::<Integer, _>(trade_id)
+            .get_result::<TradeParties>(conn)?;
+
+        let other = if parties.proposer_login == user_login {
+            parties.recipient_login
+        } else if parties.recipient_login == user_login {
+            parties.proposer_login
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+        let conversation_id = open_thread(conn, KIND_TRADE, trade_id, &user_login, &other)?;
+        load_conversation(conn, conversation_id, &user_login)
+    });
+
+    match result {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/conversations/{id}/members")]
+async fn add_conversation_member(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<AddMemberRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conversation_id = path.into_inner();
+    let login = data.login.trim().to_string();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let info = conversation_info(conn, conversation_id, &user_login)?;
+        if info.kind != KIND_GROUP {
+            return Err(ApiError::BadRequest("Members can only be changed in group conversations".to_string()));
+        }
+        if info.my_role.as_deref() != Some(ROLE_OWNER) {
+            return Err(ApiError::Forbidden);
+        }
+        if member_logins(conn, conversation_id)?.len() >= MAX_GROUP_MEMBERS {
+            return Err(ApiError::Conflict(format!("At most {} members per conversation", MAX_GROUP_MEMBERS)));
+        }
+        if is_blocked_between(conn, &user_login, &login)? {
+            return Err(ApiError::BadRequest(format!("User {} cannot be added", login)));
+        }
+
+        let added = add_member(conn, conversation_id, &login, ROLE_MEMBER)?;
+        Ok((load_conversation(conn, conversation_id, &user_login)?, added))
+    });
+
+    match result {
+        Ok((details, added)) => {
+            if added {
+                let members = details.members.iter().map(|m| m.user_login.clone()).collect();
+                chat.do_send(ChatCommand::Deliver {
+                    event: membership_event(members, conversation_id, &login, true, &user_login),
+                });
+            }
+            HttpResponse::Ok().json(details)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+// Владелец исключает участника, любой участник может выйти сам.
+// Вышедшего владельца сменяет самый давний участник, опустевшая беседа удаляется.
+#[post("/conversations/{id}/members/{login}/remove")]
+async fn remove_conversation_member(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<(i32, String)>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let (conversation_id, login) = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let info = conversation_info(conn, conversation_id, &user_login)?;
+        if info.kind != KIND_GROUP {
+            return Err(ApiError::BadRequest("Members can only be changed in group conversations".to_string()));
+        }
+        if login != user_login && info.my_role.as_deref() != Some(ROLE_OWNER) {
+            return Err(ApiError::Forbidden);
+        }
+
+        let removed = diesel::sql_query(
+            "DELETE FROM conversation_members WHERE conversation_id = $1 AND user_login = $2",
+        )
+        .bind::<Integer, _>(conversation_id)
+        .bind::<Text, _>(&login)
+        .execute(conn)?;
+        if removed == 0 {
+            return Err(ApiError::NotFound);
+        }
+
+        let remaining = member_logins(conn, conversation_id)?;
+        if remaining.is_empty() {
+            diesel::sql_query("DELETE FROM conversations WHERE id = $1")
+                .bind::<Integer, _>(conversation_id)
+                .execute(conn)?;
+        } else {
+            diesel::sql_query(
+                r#"
+                UPDATE conversation_members SET role = 'owner'
+                WHERE conversation_id = $1
+                  AND NOT EXISTS (
+                      SELECT 1 FROM conversation_members WHERE conversation_id = $1 AND role = 'owner'
+                  )
+                  AND user_login = (
+                      SELECT user_login FROM conversation_members
+                      WHERE conversation_id = $1
+                      ORDER BY joined_at, user_login
+                      LIMIT 1
+                  )
+                "#,
+            )
+            .bind::<Integer, _>(conversation_id)
+            .execute(conn)?;
+        }
+
+        Ok(remaining)
+    });
+
+    match result {
+        Ok(mut recipients) => {
+            recipients.push(login.clone());
+            chat.do_send(ChatCommand::Deliver {
+                event: membership_event(recipients, conversation_id, &login, false, &user_login),
+            });
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[get("/conversations/{id}/messages")]
+async fn get_conversation_messages(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    query: web::Query<ConversationMessagesQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conversation_id = path.into_inner();
+    let cursor = HistoryCursor::new(query.before_id, query.after_id, query.limit);
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match member_logins(conn, conversation_id) {
+        Ok(members) if members.contains(&user_login) => {}
+        Ok(_) => return ApiError::NotFound.into_response(),
+        Err(err) => return ApiError::from(err).into_response(),
+    }
+
+    let sql = cursor.query(
+        r#"
+        SELECT
+            id, sender_login AS sender, body, created_at, client_id,
+            edited_at, deleted_at IS NOT NULL AS deleted, attachment_type, attachment_id
+        FROM messages AS m
+        WHERE conversation_id = $1
+          AND NOT (sender_login = $2 AND hidden_for_sender)
+          AND NOT EXISTS (
+            SELECT 1 FROM user_blocks AS b
+            WHERE (b.blocker_login = $2 AND b.blocked_login = m.sender_login)
+               OR (b.blocker_login = m.sender_login AND b.blocked_login = $2)
+          )
+        "#,
+    );
+    let page_query = diesel::sql_query(sql)
+        .into_boxed()
+        .bind::<Integer, _>(conversation_id)
+        .bind::<Text, _>(&user_login);
+
+    match cursor.load::<ConversationMessageDto>(conn, page_query) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(err) => {
+            eprintln!("DB error: {:?}", err);
+            HttpResponse::InternalServerError().body("Error fetching messages")
+        }
+    }
+}
+
+// Отмечает беседу прочитанной до последнего сообщения
+#[post("/conversations/{id}/read")]
+async fn mark_conversation_read(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = diesel::sql_query(
+        r#"
+        UPDATE conversation_members AS cm
+        SET last_read_message_id = GREATEST(
+            COALESCE(cm.last_read_message_id, 0),
+            COALESCE((SELECT MAX(id) FROM messages WHERE conversation_id = cm.conversation_id), 0)
+        )
+        WHERE cm.conversation_id = $1 AND cm.user_login = $2
+        RETURNING cm.last_read_message_id
+        "#,
+    )
+    .bind::<Integer, _>(path.into_inner())
+    .bind::<Text, _>(&user_login)
+    .get_result::<LastRead>(conn);
+
+    match result {
+        Ok(read) => HttpResponse::Ok().json(ConversationReadResponse {
+            last_read_message_id: read.last_read_message_id,
+        }),
+        Err(err) => ApiError::from(err).into_response(),
+    }
+}
@@ -0,0 +1,681 @@
+use actix::Addr;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::ApiError;
+use crate::auth::require_login;
+use crate::chat::ChatServer;
+use crate::collection::{CountResult, IdResult};
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::notifications::{self, NewNotification, NotificationDto};
+use crate::DBPool;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_COMPLETED: &str = "completed";
+const STATUS_CANCELLED: &str = "cancelled";
+
+#[derive(Deserialize)]
+pub struct CreateDealRequest {
+    listing_id: i32,
+    // обязателен, если сделку открывает продавец
+    buyer_login: Option<String>,
+    // по умолчанию цена из объявления
+    unit_price: Option<i32>,
+    quantity: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct DealListQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(QueryableByName)]
+struct DealRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+
+    #[diesel(sql_type = Integer)]
+    listing_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    release_id: i32,
+
+    #[diesel(sql_type = Text)]
+    seller_login: String,
+
+    #[diesel(sql_type = Text)]
+    buyer_login: String,
+
+    #[diesel(sql_type = Integer)]
+    unit_price: i32,
+
+    #[diesel(sql_type = Text)]
+    currency: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    condition: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    quantity: i32,
+
+    #[diesel(sql_type = Bool)]
+    seller_confirmed: bool,
+
+    #[diesel(sql_type = Bool)]
+    buyer_confirmed: bool,
+
+    #[diesel(sql_type = Text)]
+    status: String,
+}
+
+#[derive(QueryableByName)]
+struct LockedListing {
+    #[diesel(sql_type = Text)]
+    seller_login: String,
+
+    #[diesel(sql_type = Integer)]
+    release_id: i32,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    price: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    currency: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    condition: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    quantity: i32,
+
+    #[diesel(sql_type = Text)]
+    status: String,
+
+    #[diesel(sql_type = Bool)]
+    expired: bool,
+}
+
+impl LockedListing {
+    fn is_available(&self) -> bool {
+        (self.status == "active" || self.status == "reserved") && !self.expired
+    }
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct DealDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub listing_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Text)]
+    pub seller_login: String,
+
+    #[diesel(sql_type = Text)]
+    pub buyer_login: String,
+
+    #[diesel(sql_type = Integer)]
+    pub unit_price: i32,
+
+    #[diesel(sql_type = Text)]
+    pub currency: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub condition: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    pub quantity: i32,
+
+    #[diesel(sql_type = Bool)]
+    pub seller_confirmed: bool,
+
+    #[diesel(sql_type = Bool)]
+    pub buyer_confirmed: bool,
+
+    #[diesel(sql_type = Text)]
+    pub status: String,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub sale_id: Option<i32>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub updated_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct DealListResponse {
+    items: Vec<DealDto>,
+    total_count: i64,
+}
+
+const DEAL_SELECT: &str = r#"
+    SELECT
+        d.id,
+        d.listing_id,
+        d.release_id,
+        prod.id AS product_id,
+        prod.name AS product_name,
+        p.name AS platform_name,
+        reg.name AS region_name,
+        '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+        d.seller_login,
+        d.buyer_login,
+        d.unit_price,
+        d.currency,
+        d.condition,
+        d.quantity,
+        d.seller_confirmed,
+        d.buyer_confirmed,
+        d.status,
+        (SELECT s.id FROM sales AS s WHERE s.deal_id = d.id LIMIT 1) AS sale_id,
+        d.created_at,
+        d.updated_at,
+        d.completed_at
+    FROM deals AS d
+    INNER JOIN releases AS r ON r.id = d.release_id
+    INNER JOIN products AS prod ON prod.id = r.product_id
+    INNER JOIN platforms AS p ON p.id = r.platform
+    LEFT JOIN regions AS reg ON reg.id = r.release_region
+"#;
+
+fn lock_listing(conn: &mut PgConnection, listing_id: i32) -> Result<LockedListing, ApiError> {
+    let query = r#"
+        SELECT
+            seller_login, release_id, price, currency, condition, quantity, status,
+            (expires_at IS NOT NULL AND expires_at <= NOW()) AS expired
+        FROM listings
+        WHERE id = $1
+        FOR UPDATE
+    "#;
+
+    Ok(diesel::sql_query(query)
+        .bind::<Integer, _>(listing_id)
+        .get_result::<LockedListing>(conn)?)
+}
+
+fn lock_deal(conn: &mut PgConnection, deal_id: i32, login: &str) -> Result<DealRow, ApiError> {
+    let query = r#"
+        SELECT
+            id, listing_id, release_id, seller_login, buyer_login, unit_price, currency,
+            condition, quantity, seller_confirmed, buyer_confirmed, status
+        FROM deals
+        WHERE id = $1 AND (seller_login = $2 OR buyer_login = $2)
+        FOR UPDATE
+    "#;
+
+    Ok(diesel::sql_query(query)
+        .bind::<Integer, _>(deal_id)
+        .bind::<Text, _>(login)
+        .get_result::<DealRow>(conn)?)
+}
+
+fn load_deal(conn: &mut PgConnection, deal_id: i32) -> Result<DealDto, ApiError> {
+    let query = format!("{} WHERE d.id = $1", DEAL_SELECT);
+
+    Ok(diesel::sql_query(query)
+        .bind::<Integer, _>(deal_id)
+        .get_result::<DealDto>(conn)?)
+}
+
+// Фиксирует продажу: пишет строку в sales, уменьшает остаток объявления
+// и переносит релиз из коллекции продавца в коллекцию покупателя
+fn complete_deal(conn: &mut PgConnection, deal: &DealRow) -> Result<(), ApiError> {
+    let listing = lock_listing(conn, deal.listing_id)?;
+
+    if !listing.is_available() {
+        return Err(ApiError::Conflict("Listing is no longer available".to_string()));
+    }
+    if listing.quantity < deal.quantity {
+        return Err(ApiError::Conflict(format!(
+            "Only {} item(s) left in the listing",
+            listing.quantity
+        )));
+    }
+
+    let remaining = listing.quantity - deal.quantity;
+
+    diesel::sql_query(
+        r#"
+        UPDATE listings
+        SET quantity = $2,
+            status = CASE WHEN $2 = 0 THEN 'sold' ELSE status END,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind::<Integer, _>(deal.listing_id)
+    .bind::<Integer, _>(remaining)
+    .execute(conn)?;
+
+    // Последний экземпляр уходит из коллекции продавца
+    if remaining == 0 {
+        let removed = diesel::sql_query(
+            "DELETE FROM users_have_releases WHERE release_id = $1 AND user_login = $2",
+        )
+        .bind::<Integer, _>(deal.release_id)
+        .bind::<Text, _>(&deal.seller_login)
+        .execute(conn)?;
+
+        if removed == 0 {
+            return Err(ApiError::Conflict(
+                "Release is no longer in the seller's collection".to_string(),
+            ));
+        }
+
+        // Остальные незавершённые сделки по этому объявлению теряют смысл
+        diesel::sql_query(
+            r#"
+            UPDATE deals
+            SET status = 'cancelled', updated_at = NOW()
+            WHERE listing_id = $1 AND id <> $2 AND status = 'pending'
+            "#,
+        )
+        .bind::<Integer, _>(deal.listing_id)
+        .bind::<Integer, _>(deal.id)
+        .execute(conn)?;
+    }
+
+    // Цена в коллекции хранится без валюты и считается в рублях,
+    // поэтому сделку в другой валюте записываем без цены
+    let price = (deal.currency == "RUB").then_some(deal.unit_price);
+    diesel::sql_query(
+        r#"
+        INSERT INTO users_have_releases (release_id, user_login, product_id, price)
+        SELECT r.id, $2, r.product_id, $3
+        FROM releases AS r
+        WHERE r.id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind::<Integer, _>(deal.release_id)
+    .bind::<Text, _>(&deal.buyer_login)
+    .bind::<Nullable<Integer>, _>(price)
+    .execute(conn)?;
+
+    diesel::sql_query("DELETE FROM users_have_wishes WHERE release_id = $1 AND user_login = $2")
+        .bind::<Integer, _>(deal.release_id)
+        .bind::<Text, _>(&deal.buyer_login)
+        .execute(conn)?;
+
+    // Купленное засчитывается в открытые заявки покупателя на этот релиз
+    diesel::sql_query(
+        r#"
+        UPDATE buy_orders
+        SET quantity = GREATEST(quantity - $3, 0),
+            status = CASE WHEN quantity - $3 <= 0 THEN 'filled' ELSE status END,
+            updated_at = NOW()
+        WHERE buyer_login = $1 AND release_id = $2 AND status = 'open'
+        "#,
+    )
+    .bind::<Text, _>(&deal.buyer_login)
+    .bind::<Integer, _>(deal.release_id)
+    .bind::<Integer, _>(deal.quantity)
+    .execute(conn)?;
+
+    diesel::sql_query(
+        r#"
+        INSERT INTO sales (
+            product_id, total_price, release_id, unit_price, quantity,
+            currency, condition, seller_login, buyer_login, deal_id
+        )
+        SELECT r.product_id, $2::bigint * $3, r.id, $2, $3, $4, $5, $6, $7, $8
+        FROM releases AS r
+        WHERE r.id = $1
+        "#,
+    )
+    .bind::<Integer, _>(deal.release_id)
+    .bind::<Integer, _>(deal.unit_price)
+    .bind::<Integer, _>(deal.quantity)
+    .bind::<Text, _>(&deal.currency)
+    .bind::<Nullable<Text>, _>(deal.condition.as_deref())
+    .bind::<Text, _>(&deal.seller_login)
+    .bind::<Text, _>(&deal.buyer_login)
+    .bind::<Integer, _>(deal.id)
+    .execute(conn)?;
+
+    diesel::sql_query(
+        r#"
+        UPDATE deals
+        SET status = 'completed', completed_at = NOW(), updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind::<Integer, _>(deal.id)
+    .execute(conn)?;
+
+    log::info!(
+        "Deal {} completed: {} x release {} from {} to {}",
+        deal.id, deal.quantity, deal.release_id, deal.seller_login, deal.buyer_login
+    );
+    Ok(())
+}
+
+// Уведомляет вторую сторону сделки о действии пользователя
+fn notify_other_party(
+    conn: &mut PgConnection,
+    deal: &DealDto,
+    actor: &str,
+    notification_type: &str,
+) -> Result<Vec<NotificationDto>, ApiError> {
+    let recipient = if deal.seller_login == actor { &deal.buyer_login } else { &deal.seller_login };
+    let notification = NewNotification {
+        notification_type,
+        actor_login: Some(actor),
+        release_id: Some(deal.release_id),
+        listing_id: Some(deal.listing_id),
+        deal_id: Some(deal.id),
+        ..Default::default()
+    };
+    Ok(notifications::notify(conn, std::slice::from_ref(recipient), &notification)?)
+}
+
+// Сделку открывает покупатель по объявлению или продавец для конкретного покупателя.
+// Сторона, открывшая сделку, считается подтвердившей её.
+#[post("/deals")]
+async fn create_deal(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    data: web::Json<CreateDealRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let quantity = data.quantity.unwrap_or(1);
+    if quantity < 1 {
+        return HttpResponse::BadRequest().body("Quantity must be at least 1");
+    }
+    if data.unit_price.is_some_and(|p| p < 0) {
+        return HttpResponse::BadRequest().body("Price must not be negative");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let listing = lock_listing(conn, data.listing_id)?;
+
+        if !listing.is_available() {
+            return Err(ApiError::Conflict("Listing is no longer available".to_string()));
+        }
+        if listing.quantity < quantity {
+            return Err(ApiError::Conflict(format!(
+                "Only {} item(s) left in the listing",
+                listing.quantity
+            )));
+        }
+
+        let is_seller = listing.seller_login == user_login;
+        let buyer_login = if is_seller {
+            data.buyer_login
+                .clone()
+                .ok_or_else(|| ApiError::BadRequest("buyer_login is required".to_string()))?
+        } else {
+            user_login.clone()
+        };
+
+        if buyer_login == listing.seller_login {
+            return Err(ApiError::BadRequest("Cannot buy your own listing".to_string()));
+        }
+
+        let unit_price = data.unit_price.or(listing.price).ok_or_else(|| {
+            ApiError::BadRequest("Listing has no price, unit_price is required".to_string())
+        })?;
+
+        let insert_query = r#"
+            INSERT INTO deals (
+                listing_id, release_id, seller_login, buyer_login, unit_price, currency,
+                condition, quantity, seller_confirmed, buyer_confirmed
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id
+        "#;
+
+        let deal_id = diesel::sql_query(insert_query)
+            .bind::<Integer, _>(data.listing_id)
+            .bind::<Integer, _>(listing.release_id)
+            .bind::<Text, _>(&listing.seller_login)
+            .bind::<Text, _>(&buyer_login)
+            .bind::<Integer, _>(unit_price)
+            .bind::<Text, _>(&listing.currency)
+            .bind::<Nullable<Text>, _>(listing.condition.as_deref())
+            .bind::<Integer, _>(quantity)
+            .bind::<Bool, _>(is_seller)
+            .bind::<Bool, _>(!is_seller)
+            .get_result::<IdResult>(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                    _,
+                ) => ApiError::BadRequest("Unknown buyer".to_string()),
+                other => ApiError::from(other),
+            })?
+            .id;
+
+        let deal = load_deal(conn, deal_id)?;
+        let sent = notify_other_party(conn, &deal, &user_login, notifications::TYPE_DEAL_PROPOSED)?;
+        Ok((deal, sent))
+    });
+
+    match result {
+        Ok((deal, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Created().json(deal)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/deals/{id}/confirm")]
+async fn confirm_deal(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let deal_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let mut deal = lock_deal(conn, deal_id, &user_login)?;
+
+        if deal.status != STATUS_PENDING {
+            return Err(ApiError::Conflict(format!("Deal is already {}", deal.status)));
+        }
+
+        if deal.seller_login == user_login {
+            deal.seller_confirmed = true;
+        } else {
+            deal.buyer_confirmed = true;
+        }
+
+        diesel::sql_query(
+            r#"
+            UPDATE deals
+            SET seller_confirmed = $2, buyer_confirmed = $3, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind::<Integer, _>(deal_id)
+        .bind::<Bool, _>(deal.seller_confirmed)
+        .bind::<Bool, _>(deal.buyer_confirmed)
+        .execute(conn)?;
+
+        let completed = deal.seller_confirmed && deal.buyer_confirmed;
+        if completed {
+            complete_deal(conn, &deal)?;
+        }
+
+        let deal = load_deal(conn, deal_id)?;
+        let sent = if completed {
+            notify_other_party(conn, &deal, &user_login, notifications::TYPE_DEAL_COMPLETED)?
+        } else {
+            Vec::new()
+        };
+        Ok((deal, sent))
+    });
+
+    match result {
+        Ok((deal, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(deal)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/deals/{id}/cancel")]
+async fn cancel_deal(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let deal_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let deal = lock_deal(conn, deal_id, &user_login)?;
+
+        if deal.status != STATUS_PENDING {
+            return Err(ApiError::Conflict(format!("Deal is already {}", deal.status)));
+        }
+
+        diesel::sql_query("UPDATE deals SET status = $2, updated_at = NOW() WHERE id = $1")
+            .bind::<Integer, _>(deal_id)
+            .bind::<Text, _>(STATUS_CANCELLED)
+            .execute(conn)?;
+
+        let deal = load_deal(conn, deal_id)?;
+        let sent = notify_other_party(conn, &deal, &user_login, notifications::TYPE_DEAL_CANCELLED)?;
+        Ok((deal, sent))
+    });
+
+    match result {
+        Ok((deal, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(deal)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[get("/deals/{id}")]
+async fn get_deal(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_deal(conn, path.into_inner()) {
+        Ok(deal) if deal.seller_login == user_login || deal.buyer_login == user_login => {
+            HttpResponse::Ok().json(deal)
+        }
+        Ok(_) => ApiError::NotFound.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[get("/deals")]
+async fn get_deals(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<DealListQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let known_status = query
+        .status
+        .as_deref()
+        .is_none_or(|s| [STATUS_PENDING, STATUS_COMPLETED, STATUS_CANCELLED].contains(&s));
+    if !known_status {
+        return HttpResponse::BadRequest().body("Unknown deal status");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = query.offset.unwrap_or(0);
+
+    let list_query = format!(
+        r#"{}
+        WHERE (d.seller_login = $1 OR d.buyer_login = $1)
+          AND ($2::text IS NULL OR d.status = $2)
+        ORDER BY d.updated_at DESC, d.id DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        DEAL_SELECT
+    );
+
+    let count_query = r#"
+        SELECT COUNT(*) AS total
+        FROM deals
+        WHERE (seller_login = $1 OR buyer_login = $1)
+          AND ($2::text IS NULL OR status = $2)
+    "#;
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<DealDto>(conn);
+
+    let total_count = diesel::sql_query(count_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .get_result::<CountResult>(conn);
+
+    match (items, total_count) {
+        (Ok(items), Ok(count)) => HttpResponse::Ok().json(DealListResponse {
+            items,
+            total_count: count.total,
+        }),
+        (Err(err), _) | (_, Err(err)) => ApiError::from(err).into_response(),
+    }
+}
@@ -0,0 +1,251 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    // id последнего полученного события, лента отдаётся от новых к старым
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct FollowDto {
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    #[diesel(sql_type = BigInt)]
+    pub release_count: i64,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub followed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct FeedEventDto {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    // acquired, wished или listed
+    #[diesel(sql_type = Text)]
+    pub event_type: String,
+
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub listing_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub listing_price: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub listing_currency: Option<String>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct FeedResponse {
+    items: Vec<FeedEventDto>,
+    next_before_id: Option<i64>,
+}
+
+#[post("/collectors/{login}/follow")]
+async fn follow(pool: web::Data<DBPool>, req: HttpRequest, path: Path<String>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let followee = path.into_inner();
+    if followee == user_login {
+        return HttpResponse::BadRequest().body("Cannot follow yourself");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = diesel::sql_query(
+        "INSERT INTO user_follows (follower_login, followee_login) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind::<Text, _>(&user_login)
+    .bind::<Text, _>(&followee)
+    .execute(conn);
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+            _,
+        )) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/collectors/{login}/unfollow")]
+async fn unfollow(pool: web::Data<DBPool>, req: HttpRequest, path: Path<String>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = diesel::sql_query("DELETE FROM user_follows WHERE follower_login = $1 AND followee_login = $2")
+        .bind::<Text, _>(&user_login)
+        .bind::<Text, _>(path.into_inner())
+        .execute(conn);
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/following")]
+async fn get_following(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let query = r#"
+        SELECT
+            f.followee_login AS user_login,
+            (SELECT COUNT(*) FROM users_have_releases AS uhr WHERE uhr.user_login = f.followee_login) AS release_count,
+            f.created_at AS followed_at
+        FROM user_follows AS f
+        WHERE f.follower_login = $1
+        ORDER BY f.created_at DESC
+    "#;
+
+    load_follows(&pool, query, &user_login)
+}
+
+#[get("/followers")]
+async fn get_followers(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let query = r#"
+        SELECT
+            f.follower_login AS user_login,
+            (SELECT COUNT(*) FROM users_have_releases AS uhr WHERE uhr.user_login = f.follower_login) AS release_count,
+            f.created_at AS followed_at
+        FROM user_follows AS f
+        WHERE f.followee_login = $1
+        ORDER BY f.created_at DESC
+    "#;
+
+    load_follows(&pool, query, &user_login)
+}
+
+fn load_follows(pool: &DBPool, query: &str, user_login: &str) -> HttpResponse {
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match diesel::sql_query(query).bind::<Text, _>(user_login).load::<FollowDto>(conn) {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Лента событий коллекций тех, на кого подписан пользователь.
+// Пополнения коллекции и вишлиста скрываются, если они закрыты владельцем.
+#[get("/feed")]
+async fn get_feed(pool: web::Data<DBPool>, req: HttpRequest, query: web::Query<FeedQuery>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(30).clamp(1, 100);
+
+    let feed_query = r#"
+        SELECT
+            e.id,
+            e.user_login,
+            e.event_type,
+            e.release_id,
+            prod.id AS product_id,
+            prod.name AS product_name,
+            p.name AS platform_name,
+            reg.name AS region_name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+            e.listing_id,
+            l.price AS listing_price,
+            l.currency AS listing_currency,
+            e.created_at
+        FROM collection_events AS e
+        INNER JOIN user_follows AS f
+            ON f.followee_login = e.user_login AND f.follower_login = $1
+        LEFT JOIN user_settings AS us ON us.user_login = e.user_login
+        INNER JOIN releases AS r ON r.id = e.release_id
+        INNER JOIN products AS prod ON prod.id = r.product_id
+        INNER JOIN platforms AS p ON p.id = r.platform
+        LEFT JOIN regions AS reg ON reg.id = r.release_region
+        LEFT JOIN listings AS l ON l.id = e.listing_id
+        WHERE ($2::bigint IS NULL OR e.id < $2)
+          AND NOT (e.event_type = 'acquired' AND COALESCE(us.collection_private, FALSE))
+          AND NOT (e.event_type = 'wished' AND COALESCE(us.wishlist_private, FALSE))
+        ORDER BY e.id DESC
+        LIMIT $3
+    "#;
+
+    let result = diesel::sql_query(feed_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<BigInt>, _>(query.before_id)
+        .bind::<BigInt, _>(limit)
+        .load::<FeedEventDto>(conn);
+
+    match result {
+        Ok(items) => {
+            let next_before_id = if items.len() as i64 == limit {
+                items.last().map(|e| e.id)
+            } else {
+                None
+            };
+            HttpResponse::Ok().json(FeedResponse { items, next_before_id })
+        }
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,577 @@
+use actix::Addr;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::{db_error_response, reference_error, ApiError};
+use crate::auth::require_login;
+use crate::buy_orders::{record_matches, BuyOrderMatch};
+use crate::chat::ChatServer;
+use crate::collection::{CountResult, IdResult};
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::notifications::{self, NewNotification, NotificationDto};
+use crate::DBPool;
+
+const LISTING_STATUSES: [&str; 4] = ["active", "reserved", "sold", "withdrawn"];
+// Статусы, которые продавец ставит сам; sold ставит только завершённая сделка
+const SELLER_STATUSES: [&str; 3] = ["active", "reserved", "withdrawn"];
+const NOT_FOUND: &str = "Listing not found";
+
+#[derive(Deserialize)]
+pub struct CreateListingRequest {
+    release_id: i32,
+    price: Option<i32>,
+    currency: Option<String>,
+    condition: Option<String>,
+    description: Option<String>,
+    quantity: Option<i32>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl CreateListingRequest {
+    // Флаг "готов продать" из старого /add_bid - объявление без цены
+    pub fn unpriced(release_id: i32) -> CreateListingRequest {
+        CreateListingRequest {
+            release_id,
+            price: None,
+            currency: None,
+            condition: None,
+            description: None,
+            quantity: None,
+            expires_at: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateListingRequest {
+    price: Option<i32>,
+    currency: Option<String>,
+    condition: Option<String>,
+    description: Option<String>,
+    quantity: Option<i32>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct ListingStatusRequest {
+    status: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListingQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct ListingDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub seller_login: String,
+
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub price: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    pub currency: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub condition: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub description: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    pub quantity: i32,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[diesel(sql_type = Text)]
+    pub status: String,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ListingListResponse {
+    items: Vec<ListingDto>,
+    total_count: i64,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct ItemCondition {
+    #[diesel(sql_type = Text)]
+    code: String,
+
+    #[diesel(sql_type = Integer)]
+    rank: i32,
+
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+pub const LISTING_SELECT: &str = r#"
+    SELECT
+        l.id,
+        l.seller_login,
+        l.release_id,
+        prod.id AS product_id,
+        prod.name AS product_name,
+        p.name AS platform_name,
+        reg.name AS region_name,
+        '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+        l.price,
+        l.currency,
+        l.condition,
+        l.description,
+        l.quantity,
+        l.expires_at,
+        l.status,
+        l.created_at,
+        l.updated_at
+    FROM listings AS l
+    INNER JOIN releases AS r ON r.id = l.release_id
+    INNER JOIN products AS prod ON prod.id = r.product_id
+    INNER JOIN platforms AS p ON p.id = r.platform
+    LEFT JOIN regions AS reg ON reg.id = r.release_region
+"#;
+
+pub fn is_valid_currency(currency: &str) -> bool {
+    currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn validate_listing(
+    price: Option<i32>,
+    currency: Option<&str>,
+    quantity: Option<i32>,
+) -> Result<(), HttpResponse> {
+    if price.is_some_and(|p| p < 0) {
+        return Err(HttpResponse::BadRequest().body("Price must not be negative"));
+    }
+    if quantity.is_some_and(|q| q < 1) {
+        return Err(HttpResponse::BadRequest().body("Quantity must be at least 1"));
+    }
+    if currency.is_some_and(|c| !is_valid_currency(c)) {
+        return Err(HttpResponse::BadRequest().body("Currency must be an ISO 4217 code"));
+    }
+    Ok(())
+}
+
+pub fn load_listing(conn: &mut PgConnection, listing_id: i32) -> Result<ListingDto, diesel::result::Error> {
+    let query = format!("{} WHERE l.id = $1", LISTING_SELECT);
+
+    diesel::sql_query(query)
+        .bind::<Integer, _>(listing_id)
+        .get_result::<ListingDto>(conn)
+}
+
+// Покупателям, чьи заявки совпали с объявлением
+fn notify_matched_buyers(
+    conn: &mut PgConnection,
+    listing: &ListingDto,
+    matches: &[BuyOrderMatch],
+) -> Result<Vec<NotificationDto>, diesel::result::Error> {
+    let mut sent = Vec::new();
+    for m in matches {
+        let notification = NewNotification {
+            notification_type: notifications::TYPE_BUY_ORDER_MATCH,
+            actor_login: Some(&listing.seller_login),
+            release_id: Some(listing.release_id),
+            listing_id: Some(listing.id),
+            buy_order_id: Some(m.buy_order_id),
+            ..Default::default()
+        };
+        sent.extend(notifications::notify(conn, std::slice::from_ref(&m.buyer_login), &notification)?);
+    }
+    Ok(sent)
+}
+
+// Новое или подешевевшее объявление может пересечься с заявками покупателей:
+// совпадения записываются, их владельцы получают уведомления
+fn match_buy_orders(
+    conn: &mut PgConnection,
+    listing_id: i32,
+) -> Result<(ListingDto, Vec<NotificationDto>), diesel::result::Error> {
+    let matches = record_matches(conn, Some(listing_id), None)?;
+    let listing = load_listing(conn, listing_id)?;
+    let sent = notify_matched_buyers(conn, &listing, &matches)?;
+    Ok((listing, sent))
+}
+
+#[post("/listings")]
+async fn create_listing(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    data: web::Json<CreateListingRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if let Err(resp) = validate_listing(data.price, data.currency.as_deref(), data.quantity) {
+        return resp;
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match insert_listing(conn, &user_login, &data) {
+        Ok((listing, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Created().json(listing)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+// Выставляет релиз из коллекции продавца: объявление, совпадения с заявками
+// и уведомления в одной транзакции. Общая для POST /listings и старого /add_bid.
+pub fn insert_listing(
+    conn: &mut PgConnection,
+    seller: &str,
+    data: &CreateListingRequest,
+) -> Result<(ListingDto, Vec<NotificationDto>), ApiError> {
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let owned = diesel::sql_query(
+            "SELECT COUNT(*) AS total FROM users_have_releases WHERE release_id = $1 AND user_login = $2",
+        )
+        .bind::<Integer, _>(data.release_id)
+        .bind::<Text, _>(seller)
+        .get_result::<CountResult>(conn)?;
+
+        if owned.total == 0 {
+            return Err(ApiError::BadRequest("Release is not in your collection".to_string()));
+        }
+
+        let insert_query = r#"
+            INSERT INTO listings (seller_login, release_id, price, currency, condition, description, quantity, expires_at)
+            VALUES ($1, $2, $3, COALESCE($4, 'RUB'), $5, $6, COALESCE($7, 1), $8)
+            RETURNING id
+        "#;
+
+        let row = diesel::sql_query(insert_query)
+            .bind::<Text, _>(seller)
+            .bind::<Integer, _>(data.release_id)
+            .bind::<Nullable<Integer>, _>(data.price)
+            .bind::<Nullable<Text>, _>(data.currency.as_deref())
+            .bind::<Nullable<Text>, _>(data.condition.as_deref())
+            .bind::<Nullable<Text>, _>(data.description.as_deref())
+            .bind::<Nullable<Integer>, _>(data.quantity)
+            .bind::<Nullable<Timestamptz>, _>(data.expires_at)
+            .get_result::<IdResult>(conn)
+            .map_err(reference_error)?;
+
+        let (listing, mut sent) = match_buy_orders(conn, row.id)?;
+        sent.extend(notifications::notify_wishlist_listing(
+            conn,
+            listing.id,
+            listing.release_id,
+            &listing.seller_login,
+        )?);
+        Ok((listing, sent))
+    })
+}
+
+#[post("/listings/{id}/update")]
+async fn update_listing(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<UpdateListingRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if let Err(resp) = validate_listing(data.price, data.currency.as_deref(), data.quantity) {
+        return resp;
+    }
+
+    let listing_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    // Редактировать можно только актуальные объявления
+    let update_query = r#"
+        UPDATE listings
+        SET price = COALESCE($3, price),
+            currency = COALESCE($4, currency),
+            condition = COALESCE($5, condition),
+            description = COALESCE($6, description),
+            quantity = COALESCE($7, quantity),
+            expires_at = COALESCE($8, expires_at),
+            updated_at = NOW()
+        WHERE id = $1 AND seller_login = $2 AND status IN ('active', 'reserved')
+        RETURNING id
+    "#;
+
+    let result = conn.transaction(|conn| {
+        let row = diesel::sql_query(update_query)
+            .bind::<Integer, _>(listing_id)
+            .bind::<Text, _>(&user_login)
+            .bind::<Nullable<Integer>, _>(data.price)
+            .bind::<Nullable<Text>, _>(data.currency.as_deref())
+            .bind::<Nullable<Text>, _>(data.condition.as_deref())
+            .bind::<Nullable<Text>, _>(data.description.as_deref())
+            .bind::<Nullable<Integer>, _>(data.quantity)
+            .bind::<Nullable<Timestamptz>, _>(data.expires_at)
+            .get_result::<IdResult>(conn)?;
+        match_buy_orders(conn, row.id)
+    });
+
+    match result {
+        Ok((listing, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(listing)
+        }
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[post("/listings/{id}/status")]
+async fn set_listing_status(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<ListingStatusRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if !SELLER_STATUSES.contains(&data.status.as_str()) {
+        return HttpResponse::BadRequest().body("Listing status must be active, reserved or withdrawn");
+    }
+
+    let listing_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    // Проданные и снятые объявления не возвращаются в продажу
+    let update_query = r#"
+        UPDATE listings
+        SET status = $3, updated_at = NOW()
+        WHERE id = $1 AND seller_login = $2 AND status IN ('active', 'reserved')
+        RETURNING id
+    "#;
+
+    let result = conn.transaction(|conn| {
+        let row = diesel::sql_query(update_query)
+            .bind::<Integer, _>(listing_id)
+            .bind::<Text, _>(&user_login)
+            .bind::<Text, _>(&data.status)
+            .get_result::<IdResult>(conn)?;
+        // вернувшееся в продажу объявление заново сверяется с открытыми заявками
+        if data.status == "active" {
+            match_buy_orders(conn, row.id)
+        } else {
+            Ok((load_listing(conn, row.id)?, Vec::new()))
+        }
+    });
+
+    match result {
+        Ok((listing, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(listing)
+        }
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[get("/listings/my")]
+async fn get_my_listings(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<ListingQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if query.status.as_deref().is_some_and(|s| !LISTING_STATUSES.contains(&s)) {
+        return HttpResponse::BadRequest().body("Unknown listing status");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = query.offset.unwrap_or(0);
+
+    let list_query = format!(
+        r#"{}
+        WHERE l.seller_login = $1 AND ($2::text IS NULL OR l.status = $2)
+        ORDER BY l.updated_at DESC, l.id DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        LISTING_SELECT
+    );
+
+    let count_query = r#"
+        SELECT COUNT(*) AS total FROM listings AS l
+        WHERE l.seller_login = $1 AND ($2::text IS NULL OR l.status = $2)
+    "#;
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<ListingDto>(conn);
+
+    let count = diesel::sql_query(count_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Text>, _>(query.status.as_deref())
+        .get_result::<CountResult>(conn);
+
+    match (items, count) {
+        (Ok(items), Ok(count)) => HttpResponse::Ok().json(ListingListResponse {
+            items,
+            total_count: count.total,
+        }),
+        (Err(err), _) | (_, Err(err)) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+// Активные объявления по релизу, от дешёвых к дорогим
+#[get("/releases/{id}/listings")]
+async fn get_release_listings(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    query: web::Query<ListingQuery>,
+) -> HttpResponse {
+    if let Err(resp) = require_login(&req) {
+        return resp;
+    }
+
+    let release_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = query.offset.unwrap_or(0);
+
+    let list_query = format!(
+        r#"{}
+        WHERE l.release_id = $1
+          AND l.status = 'active'
+          AND (l.expires_at IS NULL OR l.expires_at > NOW())
+        ORDER BY l.price ASC NULLS LAST, l.created_at ASC
+        LIMIT $2 OFFSET $3
+        "#,
+        LISTING_SELECT
+    );
+
+    let count_query = r#"
+        SELECT COUNT(*) AS total FROM listings AS l
+        WHERE l.release_id = $1
+          AND l.status = 'active'
+          AND (l.expires_at IS NULL OR l.expires_at > NOW())
+    "#;
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Integer, _>(release_id)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<ListingDto>(conn);
+
+    let count = diesel::sql_query(count_query)
+        .bind::<Integer, _>(release_id)
+        .get_result::<CountResult>(conn);
+
+    match (items, count) {
+        (Ok(items), Ok(count)) => HttpResponse::Ok().json(ListingListResponse {
+            items,
+            total_count: count.total,
+        }),
+        (Err(err), _) | (_, Err(err)) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[get("/listings/conditions")]
+async fn get_conditions(pool: web::Data<DBPool>) -> HttpResponse {
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match diesel::sql_query("SELECT code, rank, name FROM item_conditions ORDER BY rank")
+        .load::<ItemCondition>(conn)
+    {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[get("/listings/{id}")]
+async fn get_listing(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    if let Err(resp) = require_login(&req) {
+        return resp;
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_listing(conn, path.into_inner()) {
+        Ok(listing) => HttpResponse::Ok().json(listing),
+        Err(err) => db_error_response(err, NOT_FOUND),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn validate_listing_accepts_empty_and_valid_values() {
+        assert!(validate_listing(None, None, None).is_ok());
+        assert!(validate_listing(Some(0), Some("RUB"), Some(1)).is_ok());
+        assert!(validate_listing(Some(1500), Some("USD"), Some(3)).is_ok());
+    }
+
+    #[test]
+    async fn validate_listing_rejects_bad_values() {
+        assert!(validate_listing(Some(-1), None, None).is_err());
+        assert!(validate_listing(None, None, Some(0)).is_err());
+        assert!(validate_listing(None, Some("rub"), None).is_err());
+        assert!(validate_listing(None, Some("RUBL"), None).is_err());
+    }
+
+    #[test]
+    async fn is_valid_currency_requires_three_uppercase_letters() {
+        assert!(is_valid_currency("RUB"));
+        assert!(is_valid_currency("EUR"));
+        assert!(!is_valid_currency(""));
+        assert!(!is_valid_currency("RU"));
+        assert!(!is_valid_currency("RUBL"));
+        assert!(!is_valid_currency("Rub"));
+        assert!(!is_valid_currency("R1B"));
+        assert!(!is_valid_currency("РУБ"));
+    }
+}
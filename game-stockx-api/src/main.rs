@@ -27,10 +27,32 @@ mod collection;
 mod collectors;
 mod platforms;
 mod chat;
+mod chat_attachments;
+mod chat_bus;
+mod chat_history;
+mod chat_limits;
+mod chat_protocol;
 mod redis;
 mod metrics;
 mod metrics_middleware;
 mod simple_rate_limiter;
+mod api_error;
+mod trade_matches;
+mod trades;
+mod listings;
+mod buy_orders;
+mod deals;
+mod reviews;
+mod user_settings;
+mod collection_diff;
+mod follows;
+mod stats_refresher;
+mod recommendations;
+mod notifications;
+mod presence;
+mod blocks;
+mod abuse_reports;
+mod conversations;
 
 use crate::simple_rate_limiter::GovernorRateLimiter;
 use crate::metrics::metrics_endpoint;
@@ -60,9 +82,21 @@ async fn main() -> io::Result<()> {
         .await
         .expect("Failed to create Redis pool");
 
+    // Лимиты и фильтр сообщений чата, общие для всех воркеров
+    let chat_limits = web::Data::new(chat_limits::ChatLimits::from_env());
+
     // Создание серверного экземпляра ChatServer
-    let chat_server = chat::ChatServer::new(pool.clone()).start();
+    let chat_server = chat::ChatServer::new(
+        pool.clone(),
+        redis_pool.clone(),
+        redis_url.clone(),
+        chat_limits.clone().into_inner(),
+    )
+    .start();
     let chat_server_data = web::Data::new(chat_server);
+
+    // Фоновый пересчёт редкости релизов и рейтинга коллекционеров
+    stats_refresher::StatsRefresher::new(pool.clone()).start();
     
     // Настройка rate limiting - исправленные параметры
     let rate_limiter = GovernorRateLimiter::per_ip_with_whitelist(
@@ -87,6 +121,7 @@ async fn main() -> io::Result<()> {
             .app_data(web::Data::new(redis_pool.clone()))
             .app_data(web::Data::new(pool.clone()))
             .app_data(chat_server_data.clone())
+            .app_data(chat_limits.clone())
             .wrap(middleware::Logger::default())
             .wrap(
                 Cors::default()
@@ -99,6 +134,7 @@ async fn main() -> io::Result<()> {
                 web::scope("/api")
                     .service(product_list::list)
                     .service(product_details::get)
+                    .service(product_details::get_price_history)
                     .service(register::register)
                     .service(auth::login)
                     .service(collection::add_release)
@@ -106,6 +142,7 @@ async fn main() -> io::Result<()> {
                     .service(collection::remove_release)
                     .service(collection::add_wish)
                     .service(collection::remove_wish)
+                    .service(collection::get_rarest_in_collection)
                     .service(collection::get_collection)
                     .service(collection::get_collection_by_login)
                     .service(collection::get_wishlist)
@@ -116,6 +153,67 @@ async fn main() -> io::Result<()> {
                     .service(platforms::get_platforms)
                     .service(chat::get_my_messages)
                     .service(chat::get_my_dialogs)
+                    .service(chat::mark_messages_read)
+                    .service(chat::get_unread_summary)
+                    .service(chat::edit_message)
+                    .service(chat::delete_message)
+                    .service(presence::get_presence)
+                    .service(blocks::block_user)
+                    .service(blocks::unblock_user)
+                    .service(blocks::get_blocks)
+                    .service(abuse_reports::create_report)
+                    .service(abuse_reports::get_my_reports)
+                    .service(conversations::get_conversations)
+                    .service(conversations::get_conversation)
+                    .service(conversations::create_group)
+                    .service(conversations::open_listing_thread)
+                    .service(conversations::open_trade_thread)
+                    .service(conversations::add_conversation_member)
+                    .service(conversations::remove_conversation_member)
+                    .service(conversations::get_conversation_messages)
+                    .service(conversations::mark_conversation_read)
+                    .service(trade_matches::get_trade_matches)
+                    .service(trades::get_trades)
+                    .service(trades::get_trade)
+                    .service(trades::propose_trade)
+                    .service(trades::counter_trade)
+                    .service(trades::accept_trade)
+                    .service(trades::reject_trade)
+                    .service(trades::cancel_trade)
+                    .service(listings::get_my_listings)
+                    .service(listings::get_conditions)
+                    .service(listings::get_listing)
+                    .service(listings::create_listing)
+                    .service(listings::update_listing)
+                    .service(listings::set_listing_status)
+                    .service(listings::get_release_listings)
+                    .service(buy_orders::get_my_buy_orders)
+                    .service(buy_orders::get_buy_order_matches)
+                    .service(buy_orders::create_buy_order)
+                    .service(buy_orders::update_buy_order)
+                    .service(buy_orders::cancel_buy_order)
+                    .service(deals::get_deals)
+                    .service(deals::get_deal)
+                    .service(deals::create_deal)
+                    .service(deals::confirm_deal)
+                    .service(deals::cancel_deal)
+                    .service(reviews::get_product_reviews)
+                    .service(reviews::save_review)
+                    .service(reviews::delete_review)
+                    .service(reviews::vote_review)
+                    .service(user_settings::get_settings)
+                    .service(user_settings::update_settings)
+                    .service(collection_diff::get_collection_diff)
+                    .service(follows::follow)
+                    .service(follows::unfollow)
+                    .service(follows::get_following)
+                    .service(follows::get_followers)
+                    .service(follows::get_feed)
+                    .service(recommendations::get_recommendations)
+                    .service(notifications::get_notifications)
+                    .service(notifications::mark_notifications_read)
+                    .service(notifications::get_preferences)
+                    .service(notifications::update_preferences)
             )
             // Регистрация маршрута WebSocket для чата
             .service(web::resource("/ws/{login}").to(chat::chat_ws))
@@ -0,0 +1,452 @@
+use actix::Addr;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::auth::require_login;
+use crate::chat::{ChatCommand, ChatServer};
+use crate::collection::CountResult;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+pub const TYPE_WISHLIST_LISTING: &str = "wishlist_listing";
+pub const TYPE_BUY_ORDER_MATCH: &str = "buy_order_match";
+pub const TYPE_OFFLINE_MESSAGE: &str = "offline_message";
+pub const TYPE_TRADE_PROPOSED: &str = "trade_proposed";
+pub const TYPE_TRADE_COUNTERED: &str = "trade_countered";
+pub const TYPE_TRADE_ACCEPTED: &str = "trade_accepted";
+pub const TYPE_TRADE_REJECTED: &str = "trade_rejected";
+pub const TYPE_TRADE_CANCELLED: &str = "trade_cancelled";
+pub const TYPE_DEAL_PROPOSED: &str = "deal_proposed";
+pub const TYPE_DEAL_COMPLETED: &str = "deal_completed";
+pub const TYPE_DEAL_CANCELLED: &str = "deal_cancelled";
+
+const NOTIFICATION_TYPES: [&str; 11] = [
+    TYPE_WISHLIST_LISTING,
+    TYPE_BUY_ORDER_MATCH,
+    TYPE_OFFLINE_MESSAGE,
+    TYPE_TRADE_PROPOSED,
+    TYPE_TRADE_COUNTERED,
+    TYPE_TRADE_ACCEPTED,
+    TYPE_TRADE_REJECTED,
+    TYPE_TRADE_CANCELLED,
+    TYPE_DEAL_PROPOSED,
+    TYPE_DEAL_COMPLETED,
+    TYPE_DEAL_CANCELLED,
+];
+
+// Длина превью сообщения в уведомлении о пропущенном сообщении
+const MESSAGE_PREVIEW_LEN: usize = 140;
+
+#[derive(Clone, Serialize, Deserialize, QueryableByName)]
+pub struct NotificationDto {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    #[diesel(sql_type = Text)]
+    pub notification_type: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub actor_login: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub release_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub product_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub listing_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub buy_order_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub trade_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub deal_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub body: Option<String>,
+
+    #[diesel(sql_type = Bool)]
+    pub is_read: bool,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+}
+
+// Описание события; получатели передаются отдельно
+#[derive(Default)]
+pub struct NewNotification<'a> {
+    pub notification_type: &'a str,
+    pub actor_login: Option<&'a str>,
+    pub release_id: Option<i32>,
+    pub listing_id: Option<i32>,
+    pub buy_order_id: Option<i32>,
+    pub trade_id: Option<i32>,
+    pub deal_id: Option<i32>,
+    pub body: Option<&'a str>,
+}
+
+#[derive(QueryableByName)]
+struct NotificationId {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+}
+
+#[derive(QueryableByName)]
+struct LoginResult {
+    #[diesel(sql_type = Text)]
+    user_login: String,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct NotificationPreference {
+    #[diesel(sql_type = Text)]
+    pub notification_type: String,
+
+    #[diesel(sql_type = Bool)]
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct NotificationsQuery {
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+    pub unread_only: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct NotificationsResponse {
+    items: Vec<NotificationDto>,
+    unread_count: i64,
+    next_before_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    // без списка отмечаются прочитанными все уведомления
+    ids: Option<Vec<i64>>,
+}
+
+#[derive(Serialize)]
+pub struct UnreadCountResponse {
+    unread_count: i64,
+}
+
+const NOTIFICATION_SELECT: &str = r#"
+    SELECT
+        n.id,
+        n.user_login,
+        n.notification_type,
+        n.actor_login,
+        n.release_id,
+        prod.name AS product_name,
+        '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+        n.listing_id,
+        n.buy_order_id,
+        n.trade_id,
+        n.deal_id,
+        n.body,
+        n.is_read,
+        n.created_at
+    FROM notifications AS n
+    LEFT JOIN releases AS r ON r.id = n.release_id
+    LEFT JOIN products AS prod ON prod.id = r.product_id
+"#;
+
+fn load_notifications(conn: &mut PgConnection, ids: &[i64]) -> Result<Vec<NotificationDto>, diesel::result::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query = format!("{} WHERE n.id = ANY($1) ORDER BY n.id", NOTIFICATION_SELECT);
+    diesel::sql_query(query)
+        .bind::<Array<BigInt>, _>(ids)
+        .load::<NotificationDto>(conn)
+}
+
+// Сохраняет уведомление для каждого получателя, у которого этот тип не отключён.
+// Возвращает созданные уведомления для отправки в живые сессии через push.
+pub fn notify(
+    conn: &mut PgConnection,
+    recipients: &[String],
+    notification: &NewNotification,
+) -> Result<Vec<NotificationDto>, diesel::result::Error> {
+    if recipients.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let insert_query = r#"
+        INSERT INTO notifications (
+            user_login, notification_type, actor_login, release_id,
+            listing_id, buy_order_id, trade_id, deal_id, body
+        )
+        SELECT DISTINCT u.login, $2, $3, $4, $5, $6, $7, $8, $9
+        FROM UNNEST($1::text[]) AS u(login)
+        WHERE u.login IS DISTINCT FROM $3
+          AND NOT EXISTS (
+              SELECT 1 FROM notification_preferences AS np
+              WHERE np.user_login = u.login AND np.notification_type = $2 AND NOT np.enabled
+          )
+        RETURNING id
+    "#;
+
+    let ids: Vec<i64> = diesel::sql_query(insert_query)
+        .bind::<Array<Text>, _>(recipients)
+        .bind::<Text, _>(notification.notification_type)
+        .bind::<Nullable<Text>, _>(notification.actor_login)
+        .bind::<Nullable<Integer>, _>(notification.release_id)
+        .bind::<Nullable<Integer>, _>(notification.listing_id)
+        .bind::<Nullable<Integer>, _>(notification.buy_order_id)
+        .bind::<Nullable<Integer>, _>(notification.trade_id)
+        .bind::<Nullable<Integer>, _>(notification.deal_id)
+        .bind::<Nullable<Text>, _>(notification.body)
+        .load::<NotificationId>(conn)?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+
+    load_notifications(conn, &ids)
+}
+
+// Уведомляет тех, у кого релиз в вишлисте, о новом объявлении о продаже
+pub fn notify_wishlist_listing(
+    conn: &mut PgConnection,
+    listing_id: i32,
+    release_id: i32,
+    seller_login: &str,
+) -> Result<Vec<NotificationDto>, diesel::result::Error> {
+    let wishers: Vec<String> = diesel::sql_query(
+        "SELECT user_login FROM users_have_wishes WHERE release_id = $1 AND user_login <> $2",
+    )
+    .bind::<Integer, _>(release_id)
+    .bind::<Text, _>(seller_login)
+    .load::<LoginResult>(conn)?
+    .into_iter()
+    .map(|row| row.user_login)
+    .collect();
+
+    notify(conn, &wishers, &NewNotification {
+        notification_type: TYPE_WISHLIST_LISTING,
+        actor_login: Some(seller_login),
+        release_id: Some(release_id),
+        listing_id: Some(listing_id),
+        ..Default::default()
+    })
+}
+
+// Пропущенные сообщения от одного собеседника схлопываются в одно непрочитанное уведомление
+pub fn notify_offline_message(
+    conn: &mut PgConnection,
+    recipient: &str,
+    sender: &str,
+    body: &str,
+) -> Result<(), diesel::result::Error> {
+    let preview: String = body.chars().take(MESSAGE_PREVIEW_LEN).collect();
+
+    let updated = diesel::sql_query(
+        r#"
+        UPDATE notifications
+        SET body = $3, created_at = NOW()
+        WHERE user_login = $1 AND actor_login = $2 AND notification_type = $4 AND NOT is_read
+        "#,
+    )
+    .bind::<Text, _>(recipient)
+    .bind::<Text, _>(sender)
+    .bind::<Text, _>(&preview)
+    .bind::<Text, _>(TYPE_OFFLINE_MESSAGE)
+    .execute(conn)?;
+
+    if updated == 0 {
+        notify(conn, &[recipient.to_string()], &NewNotification {
+            notification_type: TYPE_OFFLINE_MESSAGE,
+            actor_login: Some(sender),
+            body: Some(&preview),
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+// Доставка уже сохранённых уведомлений в открытые WebSocket-сессии получателей
+pub fn push(chat: &Addr<ChatServer>, notifications: Vec<NotificationDto>) {
+    if !notifications.is_empty() {
+        chat.do_send(ChatCommand::Notify { notifications });
+    }
+}
+
+fn count_unread(conn: &mut PgConnection, login: &str) -> Result<i64, diesel::result::Error> {
+    diesel::sql_query("SELECT COUNT(*) AS total FROM notifications WHERE user_login = $1 AND NOT is_read")
+        .bind::<Text, _>(login)
+        .get_result::<CountResult>(conn)
+        .map(|row| row.total)
+}
+
+#[get("/notifications")]
+async fn get_notifications(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<NotificationsQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(30).clamp(1, 100);
+
+    let list_query = format!(
+        r#"{}
+        WHERE n.user_login = $1
+          AND ($2::bigint IS NULL OR n.id < $2)
+          AND (NOT $3 OR NOT n.is_read)
+        ORDER BY n.id DESC
+        LIMIT $4
+        "#,
+        NOTIFICATION_SELECT
+    );
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<BigInt>, _>(query.before_id)
+        .bind::<Bool, _>(query.unread_only.unwrap_or(false))
+        .bind::<BigInt, _>(limit)
+        .load::<NotificationDto>(conn);
+
+    match (items, count_unread(conn, &user_login)) {
+        (Ok(items), Ok(unread_count)) => {
+            let next_before_id = if items.len() as i64 == limit {
+                items.last().map(|n| n.id)
+            } else {
+                None
+            };
+            HttpResponse::Ok().json(NotificationsResponse { items, unread_count, next_before_id })
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/notifications/read")]
+async fn mark_notifications_read(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    data: web::Json<MarkReadRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let update_query = r#"
+        UPDATE notifications
+        SET is_read = TRUE, read_at = NOW()
+        WHERE user_login = $1 AND NOT is_read AND ($2::bigint[] IS NULL OR id = ANY($2))
+    "#;
+
+    let result = diesel::sql_query(update_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Array<BigInt>>, _>(data.ids.as_deref())
+        .execute(conn)
+        .and_then(|_| count_unread(conn, &user_login));
+
+    match result {
+        Ok(unread_count) => HttpResponse::Ok().json(UnreadCountResponse { unread_count }),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn load_preferences(conn: &mut PgConnection, login: &str) -> Result<Vec<NotificationPreference>, diesel::result::Error> {
+    let query = r#"
+        SELECT t.notification_type, COALESCE(np.enabled, TRUE) AS enabled
+        FROM UNNEST($2::text[]) WITH ORDINALITY AS t(notification_type, position)
+        LEFT JOIN notification_preferences AS np
+            ON np.notification_type = t.notification_type AND np.user_login = $1
+        ORDER BY t.position
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Text, _>(login)
+        .bind::<Array<Text>, _>(&NOTIFICATION_TYPES[..])
+        .load::<NotificationPreference>(conn)
+}
+
+#[get("/notifications/preferences")]
+async fn get_preferences(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_preferences(conn, &user_login) {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Принимает объект вида {"trade_proposed": false, "wishlist_listing": true}
+#[post("/notifications/preferences")]
+async fn update_preferences(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    data: web::Json<HashMap<String, bool>>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if let Some(unknown) = data.keys().find(|t| !NOTIFICATION_TYPES.contains(&t.as_str())) {
+        return HttpResponse::BadRequest().body(format!("Unknown notification type: {}", unknown));
+    }
+
+    let (types, enabled): (Vec<String>, Vec<bool>) = data.into_inner().into_iter().unzip();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let upsert_query = r#"
+        INSERT INTO notification_preferences (user_login, notification_type, enabled)
+        SELECT $1, t.notification_type, t.enabled
+        FROM UNNEST($2::text[], $3::boolean[]) AS t(notification_type, enabled)
+        ON CONFLICT (user_login, notification_type) DO UPDATE
+        SET enabled = EXCLUDED.enabled, updated_at = NOW()
+    "#;
+
+    let result = diesel::sql_query(upsert_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Array<Text>, _>(&types)
+        .bind::<Array<Bool>, _>(&enabled)
+        .execute(conn)
+        .and_then(|_| load_preferences(conn, &user_login));
+
+    match result {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
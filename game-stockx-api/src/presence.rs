@@ -0,0 +1,202 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Bool, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::auth::require_login;
+use crate::chat_bus::{self, ClusterEvent};
+use crate::chat_protocol::{PresenceEvent, ServerFrame};
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::redis::RedisPool;
+use crate::user_settings::load_settings;
+use crate::DBPool;
+
+const MAX_PRESENCE_LOGINS: usize = 100;
+
+#[derive(Deserialize)]
+pub struct PresenceQuery {
+    // логины через запятую
+    pub logins: String,
+}
+
+#[derive(Serialize)]
+pub struct PresenceDto {
+    pub login: String,
+    // null, если пользователь скрывает свой статус или между вами есть блокировка
+    pub online: Option<bool>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(QueryableByName)]
+struct PresenceRow {
+    #[diesel(sql_type = Text)]
+    login: String,
+
+    #[diesel(sql_type = Bool)]
+    hidden: bool,
+
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(QueryableByName)]
+struct Companion {
+    #[diesel(sql_type = Text)]
+    login: String,
+}
+
+#[derive(QueryableByName)]
+struct LastSeen {
+    #[diesel(sql_type = Timestamptz)]
+    last_seen_at: DateTime<Utc>,
+}
+
+// Пользователь вошёл в сеть или вышел из неё на всех узлах.
+// При выходе запоминает время; возвращает событие для собеседников, если статус не скрыт.
+pub fn presence_changed(
+    conn: &mut PgConnection,
+    login: &str,
+    online: bool,
+) -> Result<Option<ClusterEvent>, diesel::result::Error> {
+    let last_seen_at = if online {
+        None
+    } else {
+        let saved = diesel::sql_query(
+            r#"
+            INSERT INTO user_presence (user_login, last_seen_at)
+            VALUES ($1, NOW())
+            ON CONFLICT (user_login) DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at
+            RETURNING last_seen_at
+            "#,
+        )
+        .bind::<Text, _>(login)
+        .get_result::<LastSeen>(conn)?;
+        Some(saved.last_seen_at)
+    };
+
+    if load_settings(conn, login)?.hide_online_status {
+        return Ok(None);
+    }
+
+    let companions = diesel::sql_query(
+        r#"
+        SELECT DISTINCT c.login
+        FROM (
+            SELECT CASE WHEN sender_login = $1 THEN recipient_login ELSE sender_login END AS login
+            FROM messages
+            WHERE conversation_id IS NULL AND (sender_login = $1 OR recipient_login = $1)
+        ) AS c
+        WHERE NOT EXISTS (
+            SELECT 1 FROM user_blocks AS b
+            WHERE (b.blocker_login = $1 AND b.blocked_login = c.login)
+               OR (b.blocker_login = c.login AND b.blocked_login = $1)
+        )
+        "#,
+    )
+    .bind::<Text, _>(login)
+    .load::<Companion>(conn)?;
+
+    if companions.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(ClusterEvent {
+        recipients: companions.into_iter().map(|c| c.login).collect(),
+        frame: ServerFrame::Presence(PresenceEvent {
+            login: login.to_string(),
+            online,
+            last_seen_at,
+        }),
+    }))
+}
+
+// Статус перечисленных пользователей: в сети ли сейчас и когда были последний раз
+#[get("/presence")]
+async fn get_presence(
+    pool: web::Data<DBPool>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    query: web::Query<PresenceQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let mut logins: Vec<String> = Vec::new();
+    for login in query.logins.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+        if !logins.iter().any(|l| l == login) {
+            logins.push(login.to_string());
+        }
+    }
+    if logins.is_empty() {
+        return HttpResponse::BadRequest().body("At least one login is required");
+    }
+    if logins.len() > MAX_PRESENCE_LOGINS {
+        return HttpResponse::BadRequest().body(format!("At most {} logins per request", MAX_PRESENCE_LOGINS));
+    }
+
+    let rows = {
+        let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+        diesel::sql_query(
+            r#"
+            SELECT
+                u.user_login AS login,
+                COALESCE(s.hide_online_status, FALSE) OR EXISTS (
+                    SELECT 1 FROM user_blocks AS b
+                    WHERE (b.blocker_login = u.user_login AND b.blocked_login = $2)
+                       OR (b.blocker_login = $2 AND b.blocked_login = u.user_login)
+                ) AS hidden,
+                p.last_seen_at
+            FROM users AS u
+            LEFT JOIN user_settings AS s ON s.user_login = u.user_login
+            LEFT JOIN user_presence AS p ON p.user_login = u.user_login
+            WHERE u.user_login = ANY($1)
+            "#,
+        )
+        .bind::<Array<Text>, _>(&logins)
+        .bind::<Text, _>(&user_login)
+        .load::<PresenceRow>(conn)
+    };
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            eprintln!("Database error: {:?}", err);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let online = match chat_bus::online_logins(&redis_pool, &logins).await {
+        Ok(online) => online,
+        Err(err) => {
+            eprintln!("Presence lookup failed: {}", err);
+            return HttpResponse::ServiceUnavailable().body("Presence is temporarily unavailable");
+        }
+    };
+
+    // Порядок ответа совпадает с порядком в запросе, неизвестные логины пропускаются
+    let items: Vec<PresenceDto> = logins
+        .iter()
+        .filter_map(|login| rows.iter().find(|row| &row.login == login))
+        .map(|row| {
+            // свой статус пользователь видит всегда
+            if row.hidden && row.login != user_login {
+                PresenceDto {
+                    login: row.login.clone(),
+                    online: None,
+                    last_seen_at: None,
+                }
+            } else {
+                let is_online = online.contains(&row.login);
+                PresenceDto {
+                    login: row.login.clone(),
+                    online: Some(is_online),
+                    last_seen_at: if is_online { None } else { row.last_seen_at },
+                }
+            }
+        })
+        .collect();
+
+    HttpResponse::Ok().json(items)
+}
@@ -1,11 +1,13 @@
 use diesel::prelude::*;
 use actix_web::web::{self, Data, Path};
 use actix_web::{HttpRequest, HttpResponse};
-use diesel::sql_types::{Integer, Text, Nullable, Bool, Array, BigInt};
+use diesel::sql_types::{Integer, Text, Nullable, Bool, Array, BigInt, Double, Timestamptz};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use crate::constants::CONNECTION_POOL_ERROR;
 use actix_web::http::header;
 use crate::auth::verify_jwt;
+use crate::listings::is_valid_currency;
 use crate::{DBPool, redis::{RedisPool, RedisCacheExt}};
 
 #[derive(Debug, Clone, Deserialize, Serialize, QueryableByName)]
@@ -49,8 +51,45 @@ pub struct ProductReleaseInfo {
     #[diesel(sql_type = Nullable<Integer>)]
     pub release_status: Option<i32>,
 
-    #[diesel(sql_type = Array<Text>)]
-    pub bid_user_logins: Vec<String>,
+    #[diesel(sql_type = BigInt)]
+    pub listing_count: i64,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub lowest_price: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub lowest_price_currency: Option<String>,
+
+    #[diesel(sql_type = BigInt)]
+    pub buy_order_count: i64,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub highest_buy_price: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub highest_buy_price_currency: Option<String>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub price_guide_median: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub price_guide_q1: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub price_guide_q3: Option<i32>,
+
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub price_guide_samples: Option<i64>,
+
+    // редкость релиза среди пользователей, перцентиль 0-100
+    #[diesel(sql_type = Nullable<Double>)]
+    pub rarity_score: Option<f64>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub owner_count: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub wisher_count: Option<i32>,
 
     #[diesel(sql_type = Bool)]
     pub digital_only: bool,
@@ -104,6 +143,60 @@ struct ScreenshotUrl {
     pub image_url: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct CommunityRating {
+    #[diesel(sql_type = Nullable<Double>)]
+    pub average: Option<f64>,
+
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+
+    // количество оценок 1..10 по порядку
+    #[diesel(sql_type = Array<BigInt>)]
+    pub distribution: Vec<i64>,
+
+    #[diesel(sql_type = BigInt)]
+    pub review_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct ProductRarity {
+    #[diesel(sql_type = Double)]
+    pub rarity_score: f64,
+
+    #[diesel(sql_type = Integer)]
+    pub owner_count: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub wisher_count: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct RelatedProduct {
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Double)]
+    pub score: f64,
+
+    #[diesel(sql_type = Integer)]
+    pub co_owner_count: i32,
+}
+
+#[derive(Deserialize)]
+pub struct ProductDetailsQuery {
+    // ограничить рекомендации совладением на одной платформе
+    pub related_platform: Option<i32>,
+    // валюта лучших цен по релизам, по умолчанию RUB
+    pub currency: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProductResponse {
     pub product: ProductProperties,
@@ -111,14 +204,17 @@ pub struct ProductResponse {
     pub screenshots: Vec<String>,
     pub companies: Vec<Company>,
     pub franschises: Vec<Franschise>,
+    pub community_rating: CommunityRating,
+    pub rarity: Option<ProductRarity>,
+    pub related: Vec<RelatedProduct>,
 }
 
 fn build_product_cache_key(product_id: i32) -> String {
     format!("product_details:basic:{}", product_id)
 }
 
-fn build_bids_cache_key(product_id: i32) -> String {
-    format!("product_details:bids:{}", product_id)
+fn build_bids_cache_key(product_id: i32, currency: &str) -> String {
+    format!("product_details:bids:{}:{}", product_id, currency)
 }
 
 fn build_product_companies_cache_key(product_id: i32) -> String {
@@ -129,6 +225,18 @@ fn build_product_franschises_cache_key(product_id: i32) -> String {
     format!("product_details:franschises:{}", product_id)
 }
 
+fn build_product_rarity_cache_key(product_id: i32) -> String {
+    format!("product_details:rarity:{}", product_id)
+}
+
+fn build_product_related_cache_key(product_id: i32, platform_id: i32) -> String {
+    format!("product_details:related:{}:{}", product_id, platform_id)
+}
+
+pub fn build_product_rating_cache_key(product_id: i32) -> String {
+    format!("product_details:rating:{}", product_id)
+}
+
 // Функция для извлечения и проверки токена
 fn extract_and_verify_token(req: &HttpRequest) -> Result<String, HttpResponse> {
     let token = req
@@ -162,20 +270,16 @@ pub async fn get(
     pool: Data<DBPool>,
     redis_pool: Data<RedisPool>,
     path: Path<i32>,
+    query: web::Query<ProductDetailsQuery>,
     req: HttpRequest,
 ) -> HttpResponse {
     let product_id = path.into_inner();
 
     // Пытаемся получить и верифицировать токен
-    let user_login_result = extract_and_verify_token(&req);
-    
-    let user_login_opt = match user_login_result {
-        Ok(login) => Some(login),
-        Err(resp) => {
-            // Если токен невалиден или отсутствует, возвращаем 401
-            return resp;
-        }
-    };
+    if let Err(resp) = extract_and_verify_token(&req) {
+        // Если токен невалиден или отсутствует, возвращаем 401
+        return resp;
+    }
 
     let basic_info = match get_product_basic_info(&pool, &redis_pool, product_id).await {
         Ok(info) => info,
@@ -185,7 +289,12 @@ pub async fn get(
         }
     };
 
-    let (mut releases, screenshots) = match get_product_releases(&pool, &redis_pool, product_id).await {
+    let currency = query.currency.clone().unwrap_or_else(|| "RUB".to_string());
+    if !is_valid_currency(&currency) {
+        return HttpResponse::BadRequest().body("Currency must be a 3-letter uppercase code");
+    }
+
+    let (releases, screenshots) = match get_product_releases(&pool, &redis_pool, product_id, &currency).await {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Error getting releases: {}", e);
@@ -209,12 +318,30 @@ pub async fn get(
         }
     };
 
-    // Если пользователь авторизован, скрываем его логин из bid_user_logins
-    if let Some(login) = user_login_opt {
-        for release in &mut releases {
-            release.bid_user_logins.retain(|l| l != &login);
+    let community_rating = match get_product_rating(&pool, &redis_pool, product_id).await {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error getting community rating: {}", e);
+            return HttpResponse::InternalServerError().finish();
         }
-    }
+    };
+
+    let rarity = match get_product_rarity(&pool, &redis_pool, product_id).await {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error getting rarity: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let related_platform = query.related_platform.unwrap_or(0);
+    let related = match get_related_products(&pool, &redis_pool, product_id, related_platform).await {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error getting related products: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
 
     HttpResponse::Ok().json(ProductResponse {
         product: basic_info,
@@ -222,6 +349,9 @@ pub async fn get(
         screenshots,
         companies,
         franschises,
+        community_rating,
+        rarity,
+        related,
     })
 }
 
@@ -275,8 +405,9 @@ async fn get_product_releases(
     pool: &Data<DBPool>,
     redis_pool: &Data<RedisPool>,
     product_id: i32,
+    currency: &str,
 ) -> Result<(Vec<ProductReleaseInfo>, Vec<String>), String> {
-    let cache_key = build_bids_cache_key(product_id);
+    let cache_key = build_bids_cache_key(product_id, currency);
     
     if let Ok(mut redis_conn) = redis_pool.get().await {
         if let Ok(Some(cached)) = redis_conn.get_json::<(Vec<ProductReleaseInfo>, Vec<String>)>(&cache_key).await {
@@ -294,23 +425,55 @@ async fn get_product_releases(
             p.name AS platform_name,
             p.id AS platform_id,
             r.release_status AS release_status,
-            COALESCE(
-                ARRAY_AGG(uhb.user_login) FILTER (WHERE uhb.user_login IS NOT NULL), 
-                ARRAY[]::text[]
-            ) AS bid_user_logins,
+            ls.listing_count,
+            ls.lowest_price,
+            ls.lowest_price_currency,
+            bo.buy_order_count,
+            bo.highest_buy_price,
+            bo.highest_buy_price_currency,
+            pg.median_price AS price_guide_median,
+            pg.q1_price AS price_guide_q1,
+            pg.q3_price AS price_guide_q3,
+            pg.sample_count AS price_guide_samples,
+            rr.rarity_score,
+            rr.owner_count,
+            rr.wisher_count,
             r.digital_only AS digital_only,
             r.serial AS serial
         FROM releases AS r
         LEFT JOIN platforms AS p ON r.platform = p.id
         INNER JOIN regions AS reg ON reg.id = r.release_region
-        LEFT JOIN users_have_bids AS uhb ON uhb.release_id = r.id
+        -- цены в разных валютах не сравниваются: лучшие цены только в запрошенной,
+        -- счётчики - по всем активным объявлениям и заявкам
+        LEFT JOIN LATERAL (
+            SELECT
+                COUNT(*) AS listing_count,
+                MIN(l.price) FILTER (WHERE l.currency = $2) AS lowest_price,
+                CASE WHEN COUNT(l.price) FILTER (WHERE l.currency = $2) > 0 THEN $2 END AS lowest_price_currency
+            FROM listings AS l
+            WHERE l.release_id = r.id
+              AND l.status = 'active'
+              AND (l.expires_at IS NULL OR l.expires_at > NOW())
+        ) AS ls ON true
+        LEFT JOIN LATERAL (
+            SELECT
+                COUNT(*) AS buy_order_count,
+                MAX(o.max_price) FILTER (WHERE o.currency = $2) AS highest_buy_price,
+                CASE WHEN COUNT(o.max_price) FILTER (WHERE o.currency = $2) > 0 THEN $2 END AS highest_buy_price_currency
+            FROM buy_orders AS o
+            WHERE o.release_id = r.id
+              AND o.status = 'open'
+              AND (o.expires_at IS NULL OR o.expires_at > NOW())
+        ) AS bo ON true
+        LEFT JOIN release_price_guide AS pg ON pg.release_id = r.id
+        LEFT JOIN release_rarity AS rr ON rr.release_id = r.id
         WHERE r.product_id = $1
-        GROUP BY r.id, reg.name, p.name, p.id
         ORDER BY p.name
     "#;
 
     let releases: Vec<ProductReleaseInfo> = diesel::sql_query(releases_query)
         .bind::<Integer, _>(product_id)
+        .bind::<Text, _>(currency)
         .load(conn)
         .map_err(|e| e.to_string())?;
 
@@ -415,4 +578,280 @@ async fn get_product_franschises(
         let _ = redis_conn.set_json(&cache_key, &product_franschise, 86400).await;
     }
     Ok(product_franschise)
-}
\ No newline at end of file
+}
+
+async fn get_product_rating(
+    pool: &Data<DBPool>,
+    redis_pool: &Data<RedisPool>,
+    product_id: i32,
+) -> Result<CommunityRating, String> {
+    let cache_key = build_product_rating_cache_key(product_id);
+
+    if let Ok(mut redis_conn) = redis_pool.get().await
+        && let Ok(Some(cached)) = redis_conn.get_json::<CommunityRating>(&cache_key).await
+    {
+        return Ok(cached);
+    }
+
+    let conn = &mut pool.get().map_err(|e| e.to_string())?;
+
+    let query = r#"
+        SELECT
+            AVG(pr.rating)::double precision AS average,
+            COUNT(pr.id) AS count,
+            ARRAY(
+                SELECT COUNT(r.id)
+                FROM generate_series(1, 10) AS g(score)
+                LEFT JOIN product_reviews AS r ON r.product_id = $1 AND r.rating = g.score
+                GROUP BY g.score
+                ORDER BY g.score
+            ) AS distribution,
+            COUNT(pr.id) FILTER (WHERE pr.body IS NOT NULL AND pr.body <> '') AS review_count
+        FROM product_reviews AS pr
+        WHERE pr.product_id = $1
+    "#;
+
+    let rating = diesel::sql_query(query)
+        .bind::<Integer, _>(product_id)
+        .get_result::<CommunityRating>(conn)
+        .map_err(|e| e.to_string())?;
+
+    if let Ok(mut redis_conn) = redis_pool.get().await {
+        let _ = redis_conn.set_json(&cache_key, &rating, 3600).await;
+    }
+    Ok(rating)
+}
+
+async fn get_product_rarity(
+    pool: &Data<DBPool>,
+    redis_pool: &Data<RedisPool>,
+    product_id: i32,
+) -> Result<Option<ProductRarity>, String> {
+    let cache_key = build_product_rarity_cache_key(product_id);
+
+    if let Ok(mut redis_conn) = redis_pool.get().await
+        && let Ok(Some(cached)) = redis_conn.get_json::<Option<ProductRarity>>(&cache_key).await
+    {
+        return Ok(cached);
+    }
+
+    let conn = &mut pool.get().map_err(|e| e.to_string())?;
+
+    let rarity = diesel::sql_query(
+        "SELECT rarity_score, owner_count, wisher_count FROM product_rarity WHERE product_id = $1",
+    )
+    .bind::<Integer, _>(product_id)
+    .get_result::<ProductRarity>(conn)
+    .optional()
+    .map_err(|e| e.to_string())?;
+
+    // редкость пересчитывается фоновой задачей, чаще обновлять кэш незачем
+    if let Ok(mut redis_conn) = redis_pool.get().await {
+        let _ = redis_conn.set_json(&cache_key, &rarity, 600).await;
+    }
+    Ok(rarity)
+}
+
+async fn get_related_products(
+    pool: &Data<DBPool>,
+    redis_pool: &Data<RedisPool>,
+    product_id: i32,
+    platform_id: i32,
+) -> Result<Vec<RelatedProduct>, String> {
+    let cache_key = build_product_related_cache_key(product_id, platform_id);
+
+    if let Ok(mut redis_conn) = redis_pool.get().await
+        && let Ok(Some(cached)) = redis_conn.get_json::<Vec<RelatedProduct>>(&cache_key).await
+    {
+        return Ok(cached);
+    }
+
+    let conn = &mut pool.get().map_err(|e| e.to_string())?;
+
+    let query = r#"
+        SELECT
+            prod.id AS product_id,
+            prod.name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+            rec.score,
+            rec.co_owner_count
+        FROM product_recommendations AS rec
+        INNER JOIN products AS prod ON prod.id = rec.related_product_id
+        WHERE rec.product_id = $1 AND rec.platform_id = $2
+        ORDER BY rec.score DESC, rec.co_owner_count DESC
+        LIMIT 10
+    "#;
+
+    let related = diesel::sql_query(query)
+        .bind::<Integer, _>(product_id)
+        .bind::<Integer, _>(platform_id)
+        .load::<RelatedProduct>(conn)
+        .map_err(|e| e.to_string())?;
+
+    if let Ok(mut redis_conn) = redis_pool.get().await {
+        let _ = redis_conn.set_json(&cache_key, &related, 600).await;
+    }
+    Ok(related)
+}
+
+const PRICE_HISTORY_BUCKETS: [&str; 3] = ["day", "week", "month"];
+
+#[derive(Deserialize)]
+pub struct PriceHistoryQuery {
+    pub bucket: Option<String>,
+    pub release_id: Option<i32>,
+    pub region_id: Option<i32>,
+    pub condition: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct PricePoint {
+    #[diesel(sql_type = Timestamptz)]
+    pub bucket_start: DateTime<Utc>,
+
+    #[diesel(sql_type = Integer)]
+    pub open: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub high: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub low: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub close: i32,
+
+    #[diesel(sql_type = Double)]
+    pub median: f64,
+
+    #[diesel(sql_type = BigInt)]
+    pub volume: i64,
+
+    #[diesel(sql_type = BigInt)]
+    pub sales_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct PriceHistoryResponse {
+    pub product_id: i32,
+    pub bucket: String,
+    pub currency: String,
+    pub points: Vec<PricePoint>,
+}
+
+fn build_price_history_cache_key(product_id: i32, bucket: &str, query: &PriceHistoryQuery, currency: &str) -> String {
+    format!(
+        "product_details:price_history:{}:{}:{}:{}:{}:{}",
+        product_id,
+        bucket,
+        query.release_id.map(|id| id.to_string()).unwrap_or_default(),
+        query.region_id.map(|id| id.to_string()).unwrap_or_default(),
+        query.condition.as_deref().unwrap_or_default(),
+        currency,
+    )
+}
+
+// Свечи по завершённым продажам продукта: цена открытия/закрытия,
+// экстремумы, медиана и проданное количество за день, неделю или месяц
+#[get("/products/{id}/price-history")]
+pub async fn get_price_history(
+    pool: Data<DBPool>,
+    redis_pool: Data<RedisPool>,
+    path: Path<i32>,
+    query: web::Query<PriceHistoryQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(resp) = extract_and_verify_token(&req) {
+        return resp;
+    }
+
+    let product_id = path.into_inner();
+    let bucket = query.bucket.clone().unwrap_or_else(|| "day".to_string());
+    if !PRICE_HISTORY_BUCKETS.contains(&bucket.as_str()) {
+        return HttpResponse::BadRequest().body("Bucket must be one of: day, week, month");
+    }
+
+    let currency = query.currency.clone().unwrap_or_else(|| "RUB".to_string());
+    if !is_valid_currency(&currency) {
+        return HttpResponse::BadRequest().body("Currency must be a 3-letter uppercase code");
+    }
+
+    match get_product_price_history(&pool, &redis_pool, product_id, &bucket, &query, &currency).await {
+        Ok(points) => HttpResponse::Ok().json(PriceHistoryResponse {
+            product_id,
+            bucket,
+            currency,
+            points,
+        }),
+        Err(e) => {
+            eprintln!("Error getting price history: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn get_product_price_history(
+    pool: &Data<DBPool>,
+    redis_pool: &Data<RedisPool>,
+    product_id: i32,
+    bucket: &str,
+    filter: &PriceHistoryQuery,
+    currency: &str,
+) -> Result<Vec<PricePoint>, String> {
+    let cache_key = build_price_history_cache_key(product_id, bucket, filter, currency);
+
+    if let Ok(mut redis_conn) = redis_pool.get().await
+        && let Ok(Some(cached)) = redis_conn.get_json::<Vec<PricePoint>>(&cache_key).await
+    {
+        return Ok(cached);
+    }
+
+    let conn = &mut pool.get().map_err(|e| e.to_string())?;
+
+    // Старые записи sales хранят только общую сумму, цену за штуку для них восстанавливаем
+    let query = r#"
+        WITH filtered AS (
+            SELECT
+                s.id,
+                s.created_at,
+                COALESCE(s.unit_price, (s.total_price / NULLIF(s.quantity, 0))::int) AS price,
+                s.quantity
+            FROM sales AS s
+            LEFT JOIN releases AS r ON r.id = s.release_id
+            WHERE s.product_id = $1
+              AND COALESCE(s.currency, 'RUB') = $2
+              AND ($3::int IS NULL OR s.release_id = $3)
+              AND ($4::int IS NULL OR r.release_region = $4)
+              AND ($5::text IS NULL OR s.condition = $5)
+        )
+        SELECT
+            date_trunc($6, created_at) AT TIME ZONE 'UTC' AS bucket_start,
+            (ARRAY_AGG(price ORDER BY created_at, id))[1] AS open,
+            MAX(price) AS high,
+            MIN(price) AS low,
+            (ARRAY_AGG(price ORDER BY created_at DESC, id DESC))[1] AS close,
+            percentile_cont(0.5) WITHIN GROUP (ORDER BY price) AS median,
+            SUM(quantity)::bigint AS volume,
+            COUNT(*) AS sales_count
+        FROM filtered
+        WHERE price IS NOT NULL
+        GROUP BY 1
+        ORDER BY 1
+    "#;
+
+    let points = diesel::sql_query(query)
+        .bind::<Integer, _>(product_id)
+        .bind::<Text, _>(currency)
+        .bind::<Nullable<Integer>, _>(filter.release_id)
+        .bind::<Nullable<Integer>, _>(filter.region_id)
+        .bind::<Nullable<Text>, _>(filter.condition.as_deref())
+        .bind::<Text, _>(bucket)
+        .load::<PricePoint>(conn)
+        .map_err(|e| e.to_string())?;
+
+    if let Ok(mut redis_conn) = redis_pool.get().await {
+        let _ = redis_conn.set_json(&cache_key, &points, 300).await;
+    }
+    Ok(points)
+}
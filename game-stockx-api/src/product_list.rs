@@ -31,6 +31,13 @@ pub struct ProductListItem {
 
     #[diesel(sql_type = Nullable<Double>)]
     pub total_rating: Option<f64>,
+
+    // средняя оценка пользователей 1-10
+    #[diesel(sql_type = Nullable<Double>)]
+    pub community_rating: Option<f64>,
+
+    #[diesel(sql_type = Integer)]
+    pub community_rating_count: i32,
 }
 
 #[derive(QueryableByName)]
@@ -116,6 +123,7 @@ pub async fn list(
 
     let (order_column, order_direction, nulls_order) = match sort.as_str() {
         "date" => ("p.first_release_date", "ASC", "NULLS LAST"),
+        "community_rating" => ("prs.rating_avg", "DESC", "NULLS LAST"),
         "name" | _ => ("p.name", "ASC", "NULLS LAST"),
     };
 
@@ -126,11 +134,14 @@ pub async fn list(
             p.name AS name,
             p.first_release_date AS first_release_date,
             p.total_rating,
+            prs.rating_avg AS community_rating,
+            COALESCE(prs.rating_count, 0) AS community_rating_count,
             p.game_type,
             p.parent_game,
             '//89.104.66.193/static/covers-full/' || c.id || '.jpg' AS image_url
         FROM products p
         LEFT JOIN covers c ON p.cover_id = c.id
+        LEFT JOIN product_rating_stats prs ON prs.product_id = p.id
         WHERE EXISTS (
             SELECT 1 
             FROM product_platforms pp 
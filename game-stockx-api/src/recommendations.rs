@@ -0,0 +1,103 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Double, Integer, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+#[derive(Deserialize)]
+pub struct RecommendationQuery {
+    pub platform: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct Recommendation {
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+
+    #[diesel(sql_type = Double)]
+    pub score: f64,
+
+    // продукты из коллекции и вишлиста, из-за которых попала рекомендация
+    #[diesel(sql_type = Array<Integer>)]
+    pub because_of: Vec<i32>,
+}
+
+// «Возможно, вам понравится»: сумма близостей к продуктам из моей коллекции и вишлиста,
+// без того, что у меня уже есть или отмечено.
+#[get("/recommendations")]
+async fn get_recommendations(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<RecommendationQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let platform_id = query.platform.unwrap_or(0);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let recommendations_query = r#"
+        WITH mine AS (
+            SELECT product_id, MAX(weight) AS weight
+            FROM (
+                SELECT r.product_id, 1.0 AS weight
+                FROM users_have_releases AS uhr
+                INNER JOIN releases AS r ON r.id = uhr.release_id
+                WHERE uhr.user_login = $1 AND ($2 = 0 OR r.platform = $2)
+                UNION ALL
+                SELECT r.product_id, 0.5 AS weight
+                FROM users_have_wishes AS uhw
+                INNER JOIN releases AS r ON r.id = uhw.release_id
+                WHERE uhw.user_login = $1 AND ($2 = 0 OR r.platform = $2)
+            ) AS interest
+            GROUP BY product_id
+        ),
+        candidates AS (
+            SELECT
+                rec.related_product_id AS product_id,
+                SUM(rec.score * m.weight) AS score,
+                (ARRAY_AGG(rec.product_id ORDER BY rec.score * m.weight DESC))[1:3] AS because_of
+            FROM mine AS m
+            INNER JOIN product_recommendations AS rec
+                ON rec.product_id = m.product_id AND rec.platform_id = $2
+            WHERE rec.related_product_id NOT IN (SELECT product_id FROM mine)
+            GROUP BY rec.related_product_id
+        )
+        SELECT
+            prod.id AS product_id,
+            prod.name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url,
+            c.score::double precision AS score,
+            c.because_of
+        FROM candidates AS c
+        INNER JOIN products AS prod ON prod.id = c.product_id
+        ORDER BY c.score DESC, prod.name
+        LIMIT $3
+    "#;
+
+    let result = diesel::sql_query(recommendations_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Integer, _>(platform_id)
+        .bind::<BigInt, _>(limit)
+        .load::<Recommendation>(conn);
+
+    match result {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
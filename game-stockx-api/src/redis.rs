@@ -59,6 +59,8 @@ pub trait RedisCacheExt {
     async fn set_json<T>(&mut self, key: &str, value: &T, ttl: usize) -> Result<(), CacheError>
     where
         T: Serialize + Send + Sync + 'static;
+
+    async fn delete(&mut self, key: &str) -> Result<(), CacheError>;
 }
 
 #[async_trait]
@@ -80,4 +82,9 @@ impl RedisCacheExt for bb8_redis::redis::aio::Connection {
         let _: String = AsyncCommands::set_ex(self, key, json, ttl as u64).await?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn delete(&mut self, key: &str) -> Result<(), CacheError> {
+        let _: i64 = AsyncCommands::del(self, key).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use crate::api_error::ApiError;
+use crate::auth::require_login;
+use crate::collection::CountResult;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::product_details::build_product_rating_cache_key;
+use crate::{DBPool, redis::{RedisPool, RedisCacheExt}};
+
+const MAX_REVIEW_LENGTH: usize = 5000;
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    rating: i32,
+    body: Option<String>,
+    release_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewVoteRequest {
+    // None снимает голос
+    helpful: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewListQuery {
+    sort: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct ReviewDto {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub user_login: String,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub release_id: Option<i32>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub platform_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Integer)]
+    pub rating: i32,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub body: Option<String>,
+
+    #[diesel(sql_type = BigInt)]
+    pub helpful_count: i64,
+
+    #[diesel(sql_type = BigInt)]
+    pub unhelpful_count: i64,
+
+    // голос текущего пользователя
+    #[diesel(sql_type = Nullable<Bool>)]
+    pub my_vote: Option<bool>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Timestamptz)]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewListResponse {
+    items: Vec<ReviewDto>,
+    total_count: i64,
+}
+
+#[derive(QueryableByName)]
+struct ReviewOwner {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+
+    #[diesel(sql_type = Integer)]
+    product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    user_login: String,
+}
+
+// $1 - отзыв или продукт (см. условие), $2 - логин текущего пользователя
+const REVIEW_SELECT: &str = r#"
+    SELECT
+        pr.id,
+        pr.product_id,
+        pr.user_login,
+        pr.release_id,
+        p.name AS platform_name,
+        reg.name AS region_name,
+        pr.rating::int AS rating,
+        pr.body,
+        COUNT(v.user_login) FILTER (WHERE v.helpful) AS helpful_count,
+        COUNT(v.user_login) FILTER (WHERE NOT v.helpful) AS unhelpful_count,
+        BOOL_OR(v.helpful) FILTER (WHERE v.user_login = $2) AS my_vote,
+        pr.created_at,
+        pr.updated_at
+    FROM product_reviews AS pr
+    LEFT JOIN releases AS r ON r.id = pr.release_id
+    LEFT JOIN platforms AS p ON p.id = r.platform
+    LEFT JOIN regions AS reg ON reg.id = r.release_region
+    LEFT JOIN review_votes AS v ON v.review_id = pr.id
+"#;
+
+const REVIEW_GROUP_BY: &str = "GROUP BY pr.id, p.name, reg.name";
+
+fn load_review(conn: &mut PgConnection, review_id: i32, login: &str) -> Result<ReviewDto, ApiError> {
+    let query = format!("{} WHERE pr.id = $1 {}", REVIEW_SELECT, REVIEW_GROUP_BY);
+
+    Ok(diesel::sql_query(query)
+        .bind::<Integer, _>(review_id)
+        .bind::<Text, _>(login)
+        .get_result::<ReviewDto>(conn)?)
+}
+
+fn find_review(conn: &mut PgConnection, review_id: i32) -> Result<ReviewOwner, ApiError> {
+    Ok(diesel::sql_query("SELECT id, product_id, user_login FROM product_reviews WHERE id = $1")
+        .bind::<Integer, _>(review_id)
+        .get_result::<ReviewOwner>(conn)?)
+}
+
+async fn invalidate_rating(redis_pool: &RedisPool, product_id: i32) {
+    if let Ok(mut redis_conn) = redis_pool.get().await {
+        let _ = redis_conn.delete(&build_product_rating_cache_key(product_id)).await;
+    }
+}
+
+// Оценка продукта от 1 до 10 и необязательный текст отзыва.
+// У пользователя один отзыв на продукт, повторный запрос его обновляет.
+#[post("/products/{id}/reviews")]
+async fn save_review(
+    pool: web::Data<DBPool>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<ReviewRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if !(1..=10).contains(&data.rating) {
+        return HttpResponse::BadRequest().body("Rating must be between 1 and 10");
+    }
+
+    let body = data.body.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    if body.is_some_and(|b| b.chars().count() > MAX_REVIEW_LENGTH) {
+        return HttpResponse::BadRequest().body("Review is too long");
+    }
+
+    let product_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        if let Some(release_id) = data.release_id {
+            let belongs = diesel::sql_query(
+                "SELECT COUNT(*) AS total FROM releases WHERE id = $1 AND product_id = $2",
+            )
+            .bind::<Integer, _>(release_id)
+            .bind::<Integer, _>(product_id)
+            .get_result::<CountResult>(conn)?
+            .total;
+
+            if belongs == 0 {
+                return Err(ApiError::BadRequest("Release does not belong to this product".to_string()));
+            }
+        }
+
+        let upsert_query = r#"
+            INSERT INTO product_reviews (product_id, user_login, release_id, rating, body)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (product_id, user_login) DO UPDATE
+            SET release_id = EXCLUDED.release_id,
+                rating = EXCLUDED.rating,
+                body = EXCLUDED.body,
+                updated_at = NOW()
+            RETURNING id, product_id, user_login
+        "#;
+
+        let review = diesel::sql_query(upsert_query)
+            .bind::<Integer, _>(product_id)
+            .bind::<Text, _>(&user_login)
+            .bind::<Nullable<Integer>, _>(data.release_id)
+            .bind::<diesel::sql_types::SmallInt, _>(data.rating as i16)
+            .bind::<Nullable<Text>, _>(body)
+            .get_result::<ReviewOwner>(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                    _,
+                ) => ApiError::NotFound,
+                other => ApiError::from(other),
+            })?;
+
+        load_review(conn, review.id, &user_login)
+    });
+
+    match result {
+        Ok(review) => {
+            invalidate_rating(&redis_pool, product_id).await;
+            HttpResponse::Ok().json(review)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/reviews/{id}/delete")]
+async fn delete_review(
+    pool: web::Data<DBPool>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let review_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = find_review(conn, review_id).and_then(|review| {
+        if review.user_login != user_login {
+            return Err(ApiError::Forbidden);
+        }
+        diesel::sql_query("DELETE FROM product_reviews WHERE id = $1")
+            .bind::<Integer, _>(review.id)
+            .execute(conn)?;
+        Ok(review.product_id)
+    });
+
+    match result {
+        Ok(product_id) => {
+            invalidate_rating(&redis_pool, product_id).await;
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+// Голос «полезно/бесполезно» за чужой отзыв
+#[post("/reviews/{id}/vote")]
+async fn vote_review(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<ReviewVoteRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let review_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = find_review(conn, review_id).and_then(|review| {
+        if review.user_login == user_login {
+            return Err(ApiError::BadRequest("Cannot vote for your own review".to_string()));
+        }
+
+        match data.helpful {
+            Some(helpful) => {
+                let upsert_query = r#"
+                    INSERT INTO review_votes (review_id, user_login, helpful)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (review_id, user_login) DO UPDATE
+                    SET helpful = EXCLUDED.helpful, created_at = NOW()
+                "#;
+
+                diesel::sql_query(upsert_query)
+                    .bind::<Integer, _>(review.id)
+                    .bind::<Text, _>(&user_login)
+                    .bind::<Bool, _>(helpful)
+                    .execute(conn)?;
+            }
+            None => {
+                diesel::sql_query("DELETE FROM review_votes WHERE review_id = $1 AND user_login = $2")
+                    .bind::<Integer, _>(review.id)
+                    .bind::<Text, _>(&user_login)
+                    .execute(conn)?;
+            }
+        }
+
+        load_review(conn, review.id, &user_login)
+    });
+
+    match result {
+        Ok(review) => HttpResponse::Ok().json(review),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Отзывы о продукте: sort=helpful (по умолчанию), recent, rating_desc, rating_asc
+#[get("/products/{id}/reviews")]
+async fn get_product_reviews(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    path: Path<i32>,
+    query: web::Query<ReviewListQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let order_by = match query.sort.as_deref().unwrap_or("helpful") {
+        "helpful" => "SUM(CASE WHEN v.helpful THEN 1 WHEN NOT v.helpful THEN -1 ELSE 0 END) DESC, pr.updated_at DESC",
+        "recent" => "pr.updated_at DESC",
+        "rating_desc" => "pr.rating DESC, pr.updated_at DESC",
+        "rating_asc" => "pr.rating ASC, pr.updated_at DESC",
+        _ => return HttpResponse::BadRequest().body("Unknown sort"),
+    };
+
+    let product_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(20).min(100);
+    let offset = query.offset.unwrap_or(0);
+
+    let list_query = format!(
+        "{} WHERE pr.product_id = $1 {} ORDER BY {}, pr.id DESC LIMIT $3 OFFSET $4",
+        REVIEW_SELECT, REVIEW_GROUP_BY, order_by
+    );
+
+    let items = diesel::sql_query(list_query)
+        .bind::<Integer, _>(product_id)
+        .bind::<Text, _>(&user_login)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<ReviewDto>(conn);
+
+    let total_count = diesel::sql_query("SELECT COUNT(*) AS total FROM product_reviews WHERE product_id = $1")
+        .bind::<Integer, _>(product_id)
+        .get_result::<CountResult>(conn);
+
+    match (items, total_count) {
+        (Ok(items), Ok(count)) => HttpResponse::Ok().json(ReviewListResponse {
+            items,
+            total_count: count.total,
+        }),
+        (Err(err), _) | (_, Err(err)) => ApiError::from(err).into_response(),
+    }
+}
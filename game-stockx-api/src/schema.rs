@@ -1,5 +1,102 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    abuse_reports (id) {
+        id -> Int8,
+        reporter_login -> Text,
+        reported_login -> Text,
+        message_id -> Nullable<Int4>,
+        message_body -> Nullable<Text>,
+        message_sent_at -> Nullable<Timestamptz>,
+        reason -> Text,
+        comment -> Nullable<Text>,
+        status -> Text,
+        created_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    alternative_names (id) {
+        id -> Int4,
+        product_id -> Int4,
+        name -> Nullable<Text>,
+        comment -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    buy_order_matches (buy_order_id, listing_id) {
+        buy_order_id -> Int4,
+        listing_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    buy_orders (id) {
+        id -> Int4,
+        buyer_login -> Text,
+        release_id -> Int4,
+        max_price -> Int4,
+        currency -> Text,
+        min_condition -> Nullable<Text>,
+        quantity -> Int4,
+        note -> Nullable<Text>,
+        expires_at -> Nullable<Timestamptz>,
+        status -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    collection_events (id) {
+        id -> Int8,
+        user_login -> Text,
+        event_type -> Text,
+        release_id -> Int4,
+        listing_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    companies (id) {
+        id -> Int4,
+        changed_company_id -> Nullable<Int4>,
+        start_date -> Nullable<Int8>,
+        start_date_format -> Nullable<Int8>,
+        status -> Nullable<Int4>,
+        name -> Nullable<Text>,
+        description -> Nullable<Text>,
+        developed -> Nullable<Text>,
+        published -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    conversation_members (conversation_id, user_login) {
+        conversation_id -> Int4,
+        user_login -> Text,
+        role -> Text,
+        last_read_message_id -> Nullable<Int4>,
+        joined_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    conversations (id) {
+        id -> Int4,
+        kind -> Text,
+        title -> Nullable<Text>,
+        listing_id -> Nullable<Int4>,
+        trade_id -> Nullable<Int4>,
+        created_by -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     covers (id) {
         id -> Int4,
@@ -7,14 +104,124 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    deals (id) {
+        id -> Int4,
+        listing_id -> Int4,
+        release_id -> Int4,
+        seller_login -> Text,
+        buyer_login -> Text,
+        unit_price -> Int4,
+        currency -> Text,
+        condition -> Nullable<Text>,
+        quantity -> Int4,
+        seller_confirmed -> Bool,
+        buyer_confirmed -> Bool,
+        status -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    franschises (id) {
+        id -> Int4,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    involved_companies (id) {
+        id -> Int4,
+        company -> Nullable<Int4>,
+        game -> Nullable<Int4>,
+        developer -> Nullable<Bool>,
+        porting -> Nullable<Bool>,
+        publisher -> Nullable<Bool>,
+        supporting -> Nullable<Bool>,
+    }
+}
+
+diesel::table! {
+    item_conditions (code) {
+        code -> Text,
+        rank -> Int4,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    listings (id) {
+        id -> Int4,
+        seller_login -> Text,
+        release_id -> Int4,
+        price -> Nullable<Int4>,
+        currency -> Text,
+        condition -> Nullable<Text>,
+        description -> Nullable<Text>,
+        quantity -> Int4,
+        expires_at -> Nullable<Timestamptz>,
+        status -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    message_edits (id) {
+        id -> Int8,
+        message_id -> Int4,
+        action -> Text,
+        previous_body -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     messages (id) {
         id -> Int4,
         sender_login -> Text,
-        recipient_login -> Text,
+        recipient_login -> Nullable<Text>,
         body -> Text,
         created_at -> Timestamptz,
         read -> Bool,
+        read_at -> Nullable<Timestamptz>,
+        client_id -> Nullable<Text>,
+        edited_at -> Nullable<Timestamptz>,
+        deleted_at -> Nullable<Timestamptz>,
+        hidden_for_sender -> Bool,
+        hidden_for_recipient -> Bool,
+        attachment_type -> Nullable<Text>,
+        attachment_id -> Nullable<Int4>,
+        conversation_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    notification_preferences (user_login, notification_type) {
+        user_login -> Text,
+        notification_type -> Text,
+        enabled -> Bool,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int8,
+        user_login -> Text,
+        notification_type -> Text,
+        actor_login -> Nullable<Text>,
+        release_id -> Nullable<Int4>,
+        listing_id -> Nullable<Int4>,
+        buy_order_id -> Nullable<Int4>,
+        trade_id -> Nullable<Int4>,
+        deal_id -> Nullable<Int4>,
+        body -> Nullable<Text>,
+        is_read -> Bool,
+        created_at -> Timestamptz,
+        read_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -37,6 +244,49 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    product_rarity (product_id) {
+        product_id -> Int4,
+        owner_count -> Int4,
+        wisher_count -> Int4,
+        demand_ratio -> Float8,
+        rarity_score -> Float8,
+        refreshed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    product_rating_stats (product_id) {
+        product_id -> Int4,
+        rating_count -> Int4,
+        rating_avg -> Float8,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    product_recommendations (product_id, platform_id, related_product_id) {
+        product_id -> Int4,
+        platform_id -> Int4,
+        related_product_id -> Int4,
+        score -> Float8,
+        co_owner_count -> Int4,
+    }
+}
+
+diesel::table! {
+    product_reviews (id) {
+        id -> Int4,
+        product_id -> Int4,
+        user_login -> Text,
+        release_id -> Nullable<Int4>,
+        rating -> Int2,
+        body -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     products (id) {
         id -> Int4,
@@ -44,6 +294,9 @@ diesel::table! {
         summary -> Text,
         first_release_date -> Nullable<Int4>,
         cover_id -> Nullable<Int4>,
+        total_rating -> Nullable<Float8>,
+        game_type -> Nullable<Int4>,
+        parent_game -> Nullable<Int4>,
     }
 }
 
@@ -54,6 +307,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    release_rarity (release_id) {
+        release_id -> Int4,
+        owner_count -> Int4,
+        wisher_count -> Int4,
+        demand_ratio -> Float8,
+        rarity_score -> Float8,
+        refreshed_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     releases (id) {
         id -> Int4,
@@ -67,12 +331,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    review_votes (review_id, user_login) {
+        review_id -> Int4,
+        user_login -> Text,
+        helpful -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     sales (id) {
         id -> Int4,
         created_at -> Timestamp,
         product_id -> Int4,
-        total_price -> Int4,
+        total_price -> Int8,
+        release_id -> Nullable<Int4>,
+        unit_price -> Nullable<Int4>,
+        quantity -> Int4,
+        currency -> Nullable<Text>,
+        condition -> Nullable<Text>,
+        seller_login -> Nullable<Text>,
+        buyer_login -> Nullable<Text>,
+        deal_id -> Nullable<Int4>,
     }
 }
 
@@ -85,18 +366,77 @@ diesel::table! {
 }
 
 diesel::table! {
-    users (id) {
+    trade_history (id) {
         id -> Int4,
-        user_login -> Text,
-        password_hash -> Text,
-        created_at -> Nullable<Timestamp>,
+        trade_id -> Int4,
+        actor_login -> Text,
+        status -> Text,
+        items -> Jsonb,
+        message -> Nullable<Text>,
+        created_at -> Timestamptz,
     }
 }
 
 diesel::table! {
-    users_have_bids (release_id, user_login) {
+    trade_items (trade_id, release_id, from_login) {
+        trade_id -> Int4,
         release_id -> Int4,
+        from_login -> Text,
+    }
+}
+
+diesel::table! {
+    trades (id) {
+        id -> Int4,
+        proposer_login -> Text,
+        recipient_login -> Text,
+        awaiting_login -> Text,
+        status -> Text,
+        message -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_blocks (blocker_login, blocked_login) {
+        blocker_login -> Text,
+        blocked_login -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_follows (follower_login, followee_login) {
+        follower_login -> Text,
+        followee_login -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_presence (user_login) {
         user_login -> Text,
+        last_seen_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_settings (user_login) {
+        user_login -> Text,
+        collection_private -> Bool,
+        wishlist_private -> Bool,
+        updated_at -> Timestamptz,
+        hide_online_status -> Bool,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Int4,
+        user_login -> Text,
+        password_hash -> Text,
+        created_at -> Nullable<Timestamp>,
     }
 }
 
@@ -104,6 +444,8 @@ diesel::table! {
     users_have_releases (release_id, user_login) {
         release_id -> Int4,
         user_login -> Text,
+        price -> Nullable<Int4>,
+        product_id -> Nullable<Int4>,
     }
 }
 
@@ -114,26 +456,88 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(abuse_reports -> messages (message_id));
+diesel::joinable!(alternative_names -> products (product_id));
+diesel::joinable!(buy_order_matches -> buy_orders (buy_order_id));
+diesel::joinable!(buy_order_matches -> listings (listing_id));
+diesel::joinable!(buy_orders -> item_conditions (min_condition));
+diesel::joinable!(buy_orders -> releases (release_id));
+diesel::joinable!(collection_events -> listings (listing_id));
+diesel::joinable!(collection_events -> releases (release_id));
+diesel::joinable!(conversation_members -> conversations (conversation_id));
+diesel::joinable!(conversations -> listings (listing_id));
+diesel::joinable!(conversations -> trades (trade_id));
+diesel::joinable!(deals -> item_conditions (condition));
+diesel::joinable!(deals -> listings (listing_id));
+diesel::joinable!(deals -> releases (release_id));
+diesel::joinable!(listings -> item_conditions (condition));
+diesel::joinable!(listings -> releases (release_id));
+diesel::joinable!(message_edits -> messages (message_id));
+diesel::joinable!(messages -> conversations (conversation_id));
+diesel::joinable!(notifications -> buy_orders (buy_order_id));
+diesel::joinable!(notifications -> deals (deal_id));
+diesel::joinable!(notifications -> listings (listing_id));
+diesel::joinable!(notifications -> releases (release_id));
+diesel::joinable!(notifications -> trades (trade_id));
 diesel::joinable!(product_platforms -> platforms (platform_id));
 diesel::joinable!(product_platforms -> products (product_id));
+diesel::joinable!(product_rarity -> products (product_id));
+diesel::joinable!(product_rating_stats -> products (product_id));
+diesel::joinable!(product_reviews -> products (product_id));
+diesel::joinable!(product_reviews -> releases (release_id));
+diesel::joinable!(release_rarity -> releases (release_id));
 diesel::joinable!(releases -> products (product_id));
+diesel::joinable!(review_votes -> product_reviews (review_id));
+diesel::joinable!(sales -> deals (deal_id));
+diesel::joinable!(sales -> item_conditions (condition));
+diesel::joinable!(sales -> releases (release_id));
 diesel::joinable!(screenshots -> products (game));
-diesel::joinable!(users_have_bids -> releases (release_id));
+diesel::joinable!(trade_history -> trades (trade_id));
+diesel::joinable!(trade_items -> releases (release_id));
+diesel::joinable!(trade_items -> trades (trade_id));
 diesel::joinable!(users_have_releases -> releases (release_id));
 diesel::joinable!(users_have_wishes -> releases (release_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    abuse_reports,
+    alternative_names,
+    buy_order_matches,
+    buy_orders,
+    collection_events,
+    companies,
+    conversation_members,
+    conversations,
     covers,
+    deals,
+    franschises,
+    involved_companies,
+    item_conditions,
+    listings,
+    message_edits,
     messages,
+    notification_preferences,
+    notifications,
     platforms,
     product_platforms,
+    product_rarity,
+    product_rating_stats,
+    product_recommendations,
+    product_reviews,
     products,
     regions,
+    release_rarity,
     releases,
+    review_votes,
     sales,
     screenshots,
+    trade_history,
+    trade_items,
+    trades,
+    user_blocks,
+    user_follows,
+    user_presence,
+    user_settings,
     users,
-    users_have_bids,
     users_have_releases,
     users_have_wishes,
 );
@@ -0,0 +1,73 @@
+use actix::prelude::*;
+use actix_rt::task::spawn_blocking;
+use diesel::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::DBPool;
+
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 600;
+
+// Пересчёты выполняются по порядку: рейтинг коллекционеров использует редкость релизов
+const REFRESH_STATEMENTS: [&str; 4] = [
+    "REFRESH MATERIALIZED VIEW CONCURRENTLY release_price_guide",
+    "SELECT refresh_rarity()",
+    "REFRESH MATERIALIZED VIEW CONCURRENTLY collector_leaderboard",
+    "SELECT refresh_product_recommendations()",
+];
+
+// Периодически пересчитывает предрасчитанную статистику сообщества.
+// Интервал задаётся STATS_REFRESH_SECS (или прежней LEADERBOARD_REFRESH_SECS),
+// по умолчанию 10 минут.
+pub struct StatsRefresher {
+    db_pool: DBPool,
+    in_progress: Arc<AtomicBool>,
+}
+
+impl StatsRefresher {
+    pub fn new(db_pool: DBPool) -> StatsRefresher {
+        StatsRefresher {
+            db_pool,
+            in_progress: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn refresh(&self) {
+        // предыдущий пересчёт ещё не закончился
+        if self.in_progress.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let pool = self.db_pool.clone();
+        let in_progress = self.in_progress.clone();
+
+        spawn_blocking(move || {
+            match pool.get() {
+                Ok(mut conn) => {
+                    for statement in REFRESH_STATEMENTS {
+                        if let Err(e) = diesel::sql_query(statement).execute(&mut conn) {
+                            eprintln!("Stats refresh failed on `{}`: {}", statement, e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Stats refresh failed: {}", e),
+            }
+            in_progress.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+impl Actor for StatsRefresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let interval = std::env::var("STATS_REFRESH_SECS")
+            .or_else(|_| std::env::var("LEADERBOARD_REFRESH_SECS"))
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS);
+
+        self.refresh();
+        ctx.run_interval(Duration::from_secs(interval), |act, _| act.refresh());
+    }
+}
@@ -0,0 +1,227 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Integer, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+#[derive(Deserialize)]
+pub struct TradeMatchQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub platform: Option<i32>,
+}
+
+#[derive(QueryableByName)]
+struct PartnerRow {
+    #[diesel(sql_type = Text)]
+    user_login: String,
+
+    #[diesel(sql_type = Array<Integer>)]
+    they_offer_ids: Vec<i32>,
+
+    #[diesel(sql_type = Array<Integer>)]
+    they_want_ids: Vec<i32>,
+
+    #[diesel(sql_type = BigInt)]
+    match_score: i64,
+
+    #[diesel(sql_type = BigInt)]
+    total_count: i64,
+}
+
+#[derive(Clone, Serialize, QueryableByName)]
+pub struct MatchedRelease {
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Integer)]
+    pub platform_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TradePartner {
+    user_login: String,
+    match_score: i64,
+    // что партнёр выставил на продажу из моего вишлиста
+    they_offer: Vec<MatchedRelease>,
+    // что партнёр хочет из моих предложений
+    they_want: Vec<MatchedRelease>,
+}
+
+#[derive(Serialize)]
+pub struct TradeMatchResponse {
+    items: Vec<TradePartner>,
+    total_count: i64,
+}
+
+// Пользователи, которые предлагают то, что я хочу, и хотят то, что предлагаю я.
+// Ранжирование по размеру взаимного совпадения (минимум из двух сторон обмена).
+#[get("/trade-matches")]
+async fn get_trade_matches(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<TradeMatchQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(20).min(100);
+    let offset = query.offset.unwrap_or(0);
+
+    let partners_query = r#"
+        WITH they_offer AS (
+            SELECT DISTINCT l.seller_login AS partner, l.release_id
+            FROM listings AS l
+            INNER JOIN users_have_wishes AS my_w
+                ON my_w.release_id = l.release_id AND my_w.user_login = $1
+            INNER JOIN releases AS r ON r.id = l.release_id
+            WHERE l.seller_login <> $1
+              AND l.status = 'active'
+              AND (l.expires_at IS NULL OR l.expires_at > NOW())
+              AND ($2::int IS NULL OR r.platform = $2)
+        ),
+        they_want AS (
+            SELECT uhw.user_login AS partner, uhw.release_id
+            FROM users_have_wishes AS uhw
+            INNER JOIN releases AS r ON r.id = uhw.release_id
+            WHERE uhw.user_login <> $1
+              AND ($2::int IS NULL OR r.platform = $2)
+              -- закрытые вишлисты в подборе не участвуют
+              AND NOT EXISTS (
+                  SELECT 1 FROM user_settings AS us
+                  WHERE us.user_login = uhw.user_login AND us.wishlist_private
+              )
+              AND EXISTS (
+                  SELECT 1 FROM listings AS my_l
+                  WHERE my_l.release_id = uhw.release_id
+                    AND my_l.seller_login = $1
+                    AND my_l.status = 'active'
+                    AND (my_l.expires_at IS NULL OR my_l.expires_at > NOW())
+              )
+        ),
+        partners AS (
+            SELECT
+                o.partner,
+                o.release_ids AS they_offer_ids,
+                w.release_ids AS they_want_ids
+            FROM (
+                SELECT partner, ARRAY_AGG(release_id ORDER BY release_id) AS release_ids
+                FROM they_offer
+                GROUP BY partner
+            ) o
+            INNER JOIN (
+                SELECT partner, ARRAY_AGG(release_id ORDER BY release_id) AS release_ids
+                FROM they_want
+                GROUP BY partner
+            ) w ON w.partner = o.partner
+        )
+        SELECT
+            partner AS user_login,
+            they_offer_ids,
+            they_want_ids,
+            LEAST(cardinality(they_offer_ids), cardinality(they_want_ids))::bigint AS match_score,
+            COUNT(*) OVER () AS total_count
+        FROM partners
+        ORDER BY
+            match_score DESC,
+            cardinality(they_offer_ids) + cardinality(they_want_ids) DESC,
+            partner ASC
+        LIMIT $3 OFFSET $4
+    "#;
+
+    let partners = match diesel::sql_query(partners_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Integer>, _>(query.platform)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<PartnerRow>(conn)
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let release_ids: Vec<i32> = partners
+        .iter()
+        .flat_map(|p| p.they_offer_ids.iter().chain(p.they_want_ids.iter()).copied())
+        .collect();
+
+    let releases = match load_matched_releases(conn, &release_ids) {
+        Ok(releases) => releases,
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let resolve = |ids: &[i32]| -> Vec<MatchedRelease> {
+        ids.iter().filter_map(|id| releases.get(id).cloned()).collect()
+    };
+
+    let total_count = partners.first().map(|p| p.total_count).unwrap_or(0);
+    let items = partners
+        .iter()
+        .map(|p| TradePartner {
+            user_login: p.user_login.clone(),
+            match_score: p.match_score,
+            they_offer: resolve(&p.they_offer_ids),
+            they_want: resolve(&p.they_want_ids),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(TradeMatchResponse { items, total_count })
+}
+
+pub fn load_matched_releases(
+    conn: &mut PgConnection,
+    release_ids: &[i32],
+) -> Result<HashMap<i32, MatchedRelease>, diesel::result::Error> {
+    if release_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let query = r#"
+        SELECT
+            r.id AS release_id,
+            prod.id AS product_id,
+            prod.name AS product_name,
+            p.id AS platform_id,
+            p.name AS platform_name,
+            reg.name AS region_name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url
+        FROM releases AS r
+        INNER JOIN products AS prod ON prod.id = r.product_id
+        INNER JOIN platforms AS p ON p.id = r.platform
+        LEFT JOIN regions AS reg ON reg.id = r.release_region
+        WHERE r.id = ANY($1)
+    "#;
+
+    let rows = diesel::sql_query(query)
+        .bind::<Array<Integer>, _>(release_ids)
+        .load::<MatchedRelease>(conn)?;
+
+    Ok(rows.into_iter().map(|r| (r.release_id, r)).collect())
+}
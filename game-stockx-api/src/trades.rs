@@ -0,0 +1,829 @@
+use actix::Addr;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::web::Path;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Integer, Nullable, Text, Timestamptz};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use crate::api_error::ApiError;
+use crate::auth::require_login;
+use crate::blocks::is_blocked_between;
+use crate::chat::ChatServer;
+use crate::collection::{CountResult, IdResult};
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::notifications::{self, NewNotification, NotificationDto};
+use crate::DBPool;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_COUNTERED: &str = "countered";
+const STATUS_ACCEPTED: &str = "accepted";
+const STATUS_REJECTED: &str = "rejected";
+const STATUS_CANCELLED: &str = "cancelled";
+
+#[derive(Deserialize)]
+pub struct ProposeTradeRequest {
+    recipient: String,
+    offered_release_ids: Vec<i32>,
+    requested_release_ids: Vec<i32>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CounterTradeRequest {
+    offered_release_ids: Vec<i32>,
+    requested_release_ids: Vec<i32>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TradeListQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(QueryableByName)]
+struct TradeRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+
+    #[diesel(sql_type = Text)]
+    proposer_login: String,
+
+    #[diesel(sql_type = Text)]
+    recipient_login: String,
+
+    #[diesel(sql_type = Text)]
+    awaiting_login: String,
+
+    #[diesel(sql_type = Text)]
+    status: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    message: Option<String>,
+
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+
+    #[diesel(sql_type = Timestamptz)]
+    updated_at: DateTime<Utc>,
+}
+
+impl TradeRow {
+    fn counterparty(&self, login: &str) -> Option<&str> {
+        if self.proposer_login == login {
+            Some(&self.recipient_login)
+        } else if self.recipient_login == login {
+            Some(&self.proposer_login)
+        } else {
+            None
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.status == STATUS_PENDING || self.status == STATUS_COUNTERED
+    }
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct TradeItemDto {
+    #[diesel(sql_type = Integer)]
+    pub trade_id: i32,
+
+    #[diesel(sql_type = Integer)]
+    pub release_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub from_login: String,
+
+    #[diesel(sql_type = Integer)]
+    pub product_id: i32,
+
+    #[diesel(sql_type = Text)]
+    pub product_name: String,
+
+    #[diesel(sql_type = Text)]
+    pub platform_name: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub region_name: Option<String>,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct TradeHistoryRow {
+    #[diesel(sql_type = Text)]
+    actor_login: String,
+
+    #[diesel(sql_type = Text)]
+    status: String,
+
+    #[diesel(sql_type = Text)]
+    items: String,
+
+    #[diesel(sql_type = Nullable<Text>)]
+    message: Option<String>,
+
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct TradeHistoryDto {
+    actor_login: String,
+    status: String,
+    items: serde_json::Value,
+    message: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct TradeDto {
+    id: i32,
+    proposer_login: String,
+    recipient_login: String,
+    awaiting_login: String,
+    status: String,
+    message: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    items: Vec<TradeItemDto>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history: Option<Vec<TradeHistoryDto>>,
+}
+
+#[derive(Serialize)]
+pub struct TradeListResponse {
+    items: Vec<TradeDto>,
+    total_count: i64,
+}
+
+#[derive(QueryableByName)]
+struct MovedRelease {
+    #[diesel(sql_type = Integer)]
+    release_id: i32,
+
+    #[diesel(sql_type = Nullable<Integer>)]
+    product_id: Option<i32>,
+
+    #[diesel(sql_type = Text)]
+    from_login: String,
+}
+
+fn dedup(ids: &[i32]) -> Vec<i32> {
+    let mut seen = HashSet::new();
+    ids.iter().copied().filter(|id| seen.insert(*id)).collect()
+}
+
+fn lock_trade(conn: &mut PgConnection, trade_id: i32) -> Result<TradeRow, ApiError> {
+    let query = r#"
+        SELECT id, proposer_login, recipient_login, awaiting_login, status, message, created_at, updated_at
+        FROM trades
+        WHERE id = $1
+        FOR UPDATE
+    "#;
+
+    Ok(diesel::sql_query(query)
+        .bind::<Integer, _>(trade_id)
+        .get_result::<TradeRow>(conn)?)
+}
+
+// Проверяет, что все релизы есть в коллекции пользователя
+fn ensure_owned(conn: &mut PgConnection, login: &str, release_ids: &[i32]) -> Result<(), ApiError> {
+    if release_ids.is_empty() {
+        return Ok(());
+    }
+
+    let query = r#"
+        SELECT COUNT(*) AS total
+        FROM users_have_releases
+        WHERE user_login = $1 AND release_id = ANY($2)
+    "#;
+
+    let owned = diesel::sql_query(query)
+        .bind::<Text, _>(login)
+        .bind::<Array<Integer>, _>(release_ids)
+        .get_result::<CountResult>(conn)?;
+
+    if owned.total != release_ids.len() as i64 {
+        return Err(ApiError::Conflict(format!(
+            "User {} does not own all of the listed releases",
+            login
+        )));
+    }
+    Ok(())
+}
+
+fn replace_items(
+    conn: &mut PgConnection,
+    trade_id: i32,
+    offerer: &str,
+    offered: &[i32],
+    counterparty: &str,
+    requested: &[i32],
+) -> Result<(), ApiError> {
+    diesel::sql_query("DELETE FROM trade_items WHERE trade_id = $1")
+        .bind::<Integer, _>(trade_id)
+        .execute(conn)?;
+
+    let insert_query = r#"
+        INSERT INTO trade_items (trade_id, release_id, from_login)
+        SELECT $1, UNNEST($2::int[]), $3
+    "#;
+
+    for (from_login, ids) in [(offerer, offered), (counterparty, requested)] {
+        diesel::sql_query(insert_query)
+            .bind::<Integer, _>(trade_id)
+            .bind::<Array<Integer>, _>(ids)
+            .bind::<Text, _>(from_login)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+// Снимок текущего состава обмена сохраняется в историю вместе со статусом
+fn record_history(
+    conn: &mut PgConnection,
+    trade_id: i32,
+    actor: &str,
+    status: &str,
+    message: Option<&str>,
+) -> Result<(), ApiError> {
+    let query = r#"
+        INSERT INTO trade_history (trade_id, actor_login, status, message, items)
+        SELECT $1, $2, $3, $4, COALESCE(
+            jsonb_agg(jsonb_build_object('release_id', ti.release_id, 'from_login', ti.from_login)
+                ORDER BY ti.from_login, ti.release_id),
+            '[]'::jsonb
+        )
+        FROM trade_items AS ti
+        WHERE ti.trade_id = $1
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Integer, _>(trade_id)
+        .bind::<Text, _>(actor)
+        .bind::<Text, _>(status)
+        .bind::<Nullable<Text>, _>(message)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn set_status(
+    conn: &mut PgConnection,
+    trade_id: i32,
+    status: &str,
+    awaiting_login: &str,
+    message: Option<&str>,
+) -> Result<(), ApiError> {
+    let query = r#"
+        UPDATE trades
+        SET status = $2, awaiting_login = $3, message = COALESCE($4, message), updated_at = NOW()
+        WHERE id = $1
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Integer, _>(trade_id)
+        .bind::<Text, _>(status)
+        .bind::<Text, _>(awaiting_login)
+        .bind::<Nullable<Text>, _>(message)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn validate_offer(offered: &[i32], requested: &[i32]) -> Result<(), ApiError> {
+    if offered.is_empty() && requested.is_empty() {
+        return Err(ApiError::BadRequest("Trade must contain at least one release".to_string()));
+    }
+    Ok(())
+}
+
+// Перемещает все релизы обмена между участниками.
+// Выполняется внутри транзакции принятия обмена.
+fn transfer_items(conn: &mut PgConnection, trade: &TradeRow) -> Result<(), ApiError> {
+    let items_count = diesel::sql_query("SELECT COUNT(*) AS total FROM trade_items WHERE trade_id = $1")
+        .bind::<Integer, _>(trade.id)
+        .get_result::<CountResult>(conn)?
+        .total;
+
+    let delete_query = r#"
+        DELETE FROM users_have_releases AS uhr
+        USING trade_items AS ti
+        WHERE ti.trade_id = $1
+          AND uhr.release_id = ti.release_id
+          AND uhr.user_login = ti.from_login
+        RETURNING uhr.release_id, uhr.product_id, ti.from_login
+    "#;
+
+    let moved = diesel::sql_query(delete_query)
+        .bind::<Integer, _>(trade.id)
+        .load::<MovedRelease>(conn)?;
+
+    if moved.len() as i64 != items_count {
+        return Err(ApiError::Conflict(
+            "Some releases are no longer in the owner's collection".to_string(),
+        ));
+    }
+
+    let insert_query = r#"
+        INSERT INTO users_have_releases (release_id, user_login, product_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+    "#;
+
+    for item in &moved {
+        let receiver = if item.from_login == trade.proposer_login {
+            &trade.recipient_login
+        } else {
+            &trade.proposer_login
+        };
+
+        diesel::sql_query(insert_query)
+            .bind::<Integer, _>(item.release_id)
+            .bind::<Text, _>(receiver)
+            .bind::<Nullable<Integer>, _>(item.product_id)
+            .execute(conn)?;
+    }
+
+    // Полученные релизы убираем из вишлиста получателя,
+    // а объявления о продаже отданных релизов снимаем
+    let cleanup_wishes = r#"
+        DELETE FROM users_have_wishes AS uhw
+        USING trade_items AS ti
+        WHERE ti.trade_id = $1
+          AND uhw.release_id = ti.release_id
+          AND uhw.user_login = CASE WHEN ti.from_login = $2 THEN $3 ELSE $2 END
+    "#;
+
+    diesel::sql_query(cleanup_wishes)
+        .bind::<Integer, _>(trade.id)
+        .bind::<Text, _>(&trade.proposer_login)
+        .bind::<Text, _>(&trade.recipient_login)
+        .execute(conn)?;
+
+    let withdraw_listings = r#"
+        UPDATE listings AS l
+        SET status = 'withdrawn', updated_at = NOW()
+        FROM trade_items AS ti
+        WHERE ti.trade_id = $1
+          AND l.release_id = ti.release_id
+          AND l.seller_login = ti.from_login
+          AND l.status IN ('active', 'reserved')
+    "#;
+
+    diesel::sql_query(withdraw_listings)
+        .bind::<Integer, _>(trade.id)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+fn load_items(conn: &mut PgConnection, trade_ids: &[i32]) -> Result<Vec<TradeItemDto>, ApiError> {
+    let query = r#"
+        SELECT
+            ti.trade_id,
+            ti.release_id,
+            ti.from_login,
+            prod.id AS product_id,
+            prod.name AS product_name,
+            p.name AS platform_name,
+            reg.name AS region_name,
+            '//89.104.66.193/static/covers-thumb/' || prod.cover_id || '.jpg' AS image_url
+        FROM trade_items AS ti
+        INNER JOIN releases AS r ON r.id = ti.release_id
+        INNER JOIN products AS prod ON prod.id = r.product_id
+        INNER JOIN platforms AS p ON p.id = r.platform
+        LEFT JOIN regions AS reg ON reg.id = r.release_region
+        WHERE ti.trade_id = ANY($1)
+        ORDER BY ti.trade_id, ti.from_login, prod.name
+    "#;
+
+    Ok(diesel::sql_query(query)
+        .bind::<Array<Integer>, _>(trade_ids)
+        .load::<TradeItemDto>(conn)?)
+}
+
+fn load_history(conn: &mut PgConnection, trade_id: i32) -> Result<Vec<TradeHistoryDto>, ApiError> {
+    let query = r#"
+        SELECT actor_login, status, items::text AS items, message, created_at
+        FROM trade_history
+        WHERE trade_id = $1
+        ORDER BY created_at ASC, id ASC
+    "#;
+
+    let rows = diesel::sql_query(query)
+        .bind::<Integer, _>(trade_id)
+        .load::<TradeHistoryRow>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| TradeHistoryDto {
+            actor_login: row.actor_login,
+            status: row.status,
+            items: serde_json::from_str(&row.items).unwrap_or(serde_json::Value::Null),
+            message: row.message,
+            created_at: row.created_at,
+        })
+        .collect())
+}
+
+fn to_dto(row: TradeRow, items: &mut Vec<TradeItemDto>) -> TradeDto {
+    let (own, rest): (Vec<_>, Vec<_>) = items.drain(..).partition(|i| i.trade_id == row.id);
+    *items = rest;
+
+    TradeDto {
+        id: row.id,
+        proposer_login: row.proposer_login,
+        recipient_login: row.recipient_login,
+        awaiting_login: row.awaiting_login,
+        status: row.status,
+        message: row.message,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+        items: own,
+        history: None,
+    }
+}
+
+fn load_trade(conn: &mut PgConnection, trade_id: i32, login: &str) -> Result<TradeDto, ApiError> {
+    let query = r#"
+        SELECT id, proposer_login, recipient_login, awaiting_login, status, message, created_at, updated_at
+        FROM trades
+        WHERE id = $1
+    "#;
+
+    let row = diesel::sql_query(query)
+        .bind::<Integer, _>(trade_id)
+        .get_result::<TradeRow>(conn)?;
+
+    if row.counterparty(login).is_none() {
+        return Err(ApiError::NotFound);
+    }
+
+    let mut items = load_items(conn, &[trade_id])?;
+    let history = load_history(conn, trade_id)?;
+    let mut dto = to_dto(row, &mut items);
+    dto.history = Some(history);
+    Ok(dto)
+}
+
+fn notify_counterparty(
+    conn: &mut PgConnection,
+    trade_id: i32,
+    recipient: &str,
+    actor: &str,
+    notification_type: &str,
+) -> Result<Vec<NotificationDto>, ApiError> {
+    let notification = NewNotification {
+        notification_type,
+        actor_login: Some(actor),
+        trade_id: Some(trade_id),
+        ..Default::default()
+    };
+    Ok(notifications::notify(conn, &[recipient.to_string()], &notification)?)
+}
+
+#[post("/trades")]
+async fn propose_trade(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    data: web::Json<ProposeTradeRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    if data.recipient == user_login {
+        return HttpResponse::BadRequest().body("Cannot trade with yourself");
+    }
+
+    let offered = dedup(&data.offered_release_ids);
+    let requested = dedup(&data.requested_release_ids);
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        validate_offer(&offered, &requested)?;
+
+        let recipient_exists = diesel::sql_query("SELECT COUNT(*) AS total FROM users WHERE user_login = $1")
+            .bind::<Text, _>(&data.recipient)
+            .get_result::<CountResult>(conn)?
+            .total > 0;
+        if !recipient_exists {
+            return Err(ApiError::BadRequest("Recipient not found".to_string()));
+        }
+        if is_blocked_between(conn, &user_login, &data.recipient)? {
+            return Err(ApiError::Forbidden);
+        }
+
+        ensure_owned(conn, &user_login, &offered)?;
+        ensure_owned(conn, &data.recipient, &requested)?;
+
+        let insert_query = r#"
+            INSERT INTO trades (proposer_login, recipient_login, awaiting_login, status, message)
+            VALUES ($1, $2, $2, $3, $4)
+            RETURNING id
+        "#;
+
+        let trade_id = diesel::sql_query(insert_query)
+            .bind::<Text, _>(&user_login)
+            .bind::<Text, _>(&data.recipient)
+            .bind::<Text, _>(STATUS_PENDING)
+            .bind::<Nullable<Text>, _>(data.message.as_deref())
+            .get_result::<IdResult>(conn)?
+            .id;
+
+        replace_items(conn, trade_id, &user_login, &offered, &data.recipient, &requested)?;
+        record_history(conn, trade_id, &user_login, STATUS_PENDING, data.message.as_deref())?;
+        let sent = notify_counterparty(conn, trade_id, &data.recipient, &user_login, notifications::TYPE_TRADE_PROPOSED)?;
+        Ok((load_trade(conn, trade_id, &user_login)?, sent))
+    });
+
+    match result {
+        Ok((trade, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Created().json(trade)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/trades/{id}/counter")]
+async fn counter_trade(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+    data: web::Json<CounterTradeRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let trade_id = path.into_inner();
+    let offered = dedup(&data.offered_release_ids);
+    let requested = dedup(&data.requested_release_ids);
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let trade = lock_trade(conn, trade_id)?;
+        let counterparty = trade.counterparty(&user_login).ok_or(ApiError::NotFound)?.to_string();
+
+        if !trade.is_open() {
+            return Err(ApiError::Conflict(format!("Trade is already {}", trade.status)));
+        }
+        if trade.awaiting_login != user_login {
+            return Err(ApiError::Forbidden);
+        }
+        // после блокировки открытый обмен можно только отклонить или отменить
+        if is_blocked_between(conn, &user_login, &counterparty)? {
+            return Err(ApiError::Forbidden);
+        }
+
+        validate_offer(&offered, &requested)?;
+        ensure_owned(conn, &user_login, &offered)?;
+        ensure_owned(conn, &counterparty, &requested)?;
+
+        replace_items(conn, trade_id, &user_login, &offered, &counterparty, &requested)?;
+        set_status(conn, trade_id, STATUS_COUNTERED, &counterparty, data.message.as_deref())?;
+        record_history(conn, trade_id, &user_login, STATUS_COUNTERED, data.message.as_deref())?;
+        let sent = notify_counterparty(conn, trade_id, &counterparty, &user_login, notifications::TYPE_TRADE_COUNTERED)?;
+        Ok((load_trade(conn, trade_id, &user_login)?, sent))
+    });
+
+    match result {
+        Ok((trade, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(trade)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/trades/{id}/accept")]
+async fn accept_trade(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let trade_id = path.into_inner();
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let trade = lock_trade(conn, trade_id)?;
+        let counterparty = trade.counterparty(&user_login).ok_or(ApiError::NotFound)?.to_string();
+
+        if !trade.is_open() {
+            return Err(ApiError::Conflict(format!("Trade is already {}", trade.status)));
+        }
+        if trade.awaiting_login != user_login {
+            return Err(ApiError::Forbidden);
+        }
+        if is_blocked_between(conn, &user_login, &counterparty)? {
+            return Err(ApiError::Forbidden);
+        }
+
+        transfer_items(conn, &trade)?;
+        set_status(conn, trade_id, STATUS_ACCEPTED, &user_login, None)?;
+        record_history(conn, trade_id, &user_login, STATUS_ACCEPTED, None)?;
+        let sent = notify_counterparty(conn, trade_id, &counterparty, &user_login, notifications::TYPE_TRADE_ACCEPTED)?;
+        Ok((load_trade(conn, trade_id, &user_login)?, sent))
+    });
+
+    match result {
+        Ok((trade, sent)) => {
+            notifications::push(&chat, sent);
+            HttpResponse::Ok().json(trade)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[post("/trades/{id}/reject")]
+async fn reject_trade(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    close_trade(&pool, &chat, path.into_inner(), &user_login, STATUS_REJECTED)
+}
+
+#[post("/trades/{id}/cancel")]
+async fn cancel_trade(
+    pool: web::Data<DBPool>,
+    chat: web::Data<Addr<ChatServer>>,
+    req: HttpRequest,
+    path: Path<i32>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    close_trade(&pool, &chat, path.into_inner(), &user_login, STATUS_CANCELLED)
+}
+
+// Отклонить может тот, от кого ждут ответа; отменить - автор текущего предложения
+fn close_trade(
+    pool: &DBPool,
+    chat: &Addr<ChatServer>,
+    trade_id: i32,
+    user_login: &str,
+    status: &'static str,
+) -> HttpResponse {
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let notification_type = if status == STATUS_REJECTED {
+        notifications::TYPE_TRADE_REJECTED
+    } else {
+        notifications::TYPE_TRADE_CANCELLED
+    };
+
+    let result = conn.transaction::<_, ApiError, _>(|conn| {
+        let trade = lock_trade(conn, trade_id)?;
+        let counterparty = trade.counterparty(user_login).ok_or(ApiError::NotFound)?.to_string();
+
+        if !trade.is_open() {
+            return Err(ApiError::Conflict(format!("Trade is already {}", trade.status)));
+        }
+
+        let is_awaiting = trade.awaiting_login == user_login;
+        if (status == STATUS_REJECTED) != is_awaiting {
+            return Err(ApiError::Forbidden);
+        }
+
+        set_status(conn, trade_id, status, &trade.awaiting_login, None)?;
+        record_history(conn, trade_id, user_login, status, None)?;
+        let sent = notify_counterparty(conn, trade_id, &counterparty, user_login, notification_type)?;
+        Ok((load_trade(conn, trade_id, user_login)?, sent))
+    });
+
+    match result {
+        Ok((trade, sent)) => {
+            notifications::push(chat, sent);
+            HttpResponse::Ok().json(trade)
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[get("/trades/{id}")]
+async fn get_trade(pool: web::Data<DBPool>, req: HttpRequest, path: Path<i32>) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_trade(conn, path.into_inner(), &user_login) {
+        Ok(trade) => HttpResponse::Ok().json(trade),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[get("/trades")]
+async fn get_trades(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    query: web::Query<TradeListQuery>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let known_status = query.status.as_deref().is_none_or(|s| {
+        [STATUS_PENDING, STATUS_COUNTERED, STATUS_ACCEPTED, STATUS_REJECTED, STATUS_CANCELLED].contains(&s)
+    });
+    if !known_status {
+        return HttpResponse::BadRequest().body("Unknown trade status");
+    }
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = query.offset.unwrap_or(0);
+
+    match list_trades(conn, &user_login, query.status.as_deref(), limit, offset) {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(err) => err.into_response(),
+    }
+}
+
+fn list_trades(
+    conn: &mut PgConnection,
+    user_login: &str,
+    status: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<TradeListResponse, ApiError> {
+    let list_query = r#"
+        SELECT id, proposer_login, recipient_login, awaiting_login, status, message, created_at, updated_at
+        FROM trades
+        WHERE (proposer_login = $1 OR recipient_login = $1)
+          AND ($2::text IS NULL OR status = $2)
+        ORDER BY updated_at DESC, id DESC
+        LIMIT $3 OFFSET $4
+    "#;
+
+    let count_query = r#"
+        SELECT COUNT(*) AS total
+        FROM trades
+        WHERE (proposer_login = $1 OR recipient_login = $1)
+          AND ($2::text IS NULL OR status = $2)
+    "#;
+
+    let rows = diesel::sql_query(list_query)
+        .bind::<Text, _>(user_login)
+        .bind::<Nullable<Text>, _>(status)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<TradeRow>(conn)?;
+
+    let total_count = diesel::sql_query(count_query)
+        .bind::<Text, _>(user_login)
+        .bind::<Nullable<Text>, _>(status)
+        .get_result::<CountResult>(conn)?
+        .total;
+
+    let trade_ids: Vec<i32> = rows.iter().map(|r| r.id).collect();
+    let mut items = load_items(conn, &trade_ids)?;
+    let trades = rows.into_iter().map(|row| to_dto(row, &mut items)).collect();
+
+    Ok(TradeListResponse { items: trades, total_count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    async fn dedup_keeps_first_occurrence_order() {
+        assert_eq!(dedup(&[3, 1, 3, 2, 1]), vec![3, 1, 2]);
+        assert!(dedup(&[]).is_empty());
+    }
+
+    #[test]
+    async fn validate_offer_requires_some_release() {
+        assert!(matches!(validate_offer(&[], &[]), Err(ApiError::BadRequest(_))));
+        assert!(validate_offer(&[1], &[]).is_ok());
+        assert!(validate_offer(&[], &[2]).is_ok());
+        assert!(validate_offer(&[1], &[2]).is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use crate::auth::require_login;
+use crate::constants::CONNECTION_POOL_ERROR;
+use crate::DBPool;
+
+#[derive(Serialize, QueryableByName)]
+pub struct UserSettings {
+    #[diesel(sql_type = Bool)]
+    pub collection_private: bool,
+
+    #[diesel(sql_type = Bool)]
+    pub wishlist_private: bool,
+
+    // не показывать другим, что пользователь в сети и когда был последний раз
+    #[diesel(sql_type = Bool)]
+    pub hide_online_status: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSettingsRequest {
+    collection_private: Option<bool>,
+    wishlist_private: Option<bool>,
+    hide_online_status: Option<bool>,
+}
+
+// Настройки пользователя; для тех, кто их не менял, возвращаются значения по умолчанию
+pub fn load_settings(conn: &mut PgConnection, login: &str) -> Result<UserSettings, diesel::result::Error> {
+    let query = r#"
+        SELECT
+            COALESCE(s.collection_private, FALSE) AS collection_private,
+            COALESCE(s.wishlist_private, FALSE) AS wishlist_private,
+            COALESCE(s.hide_online_status, FALSE) AS hide_online_status
+        FROM (SELECT $1::text AS user_login) AS u
+        LEFT JOIN user_settings AS s ON s.user_login = u.user_login
+    "#;
+
+    diesel::sql_query(query)
+        .bind::<Text, _>(login)
+        .get_result::<UserSettings>(conn)
+}
+
+#[get("/settings")]
+async fn get_settings(pool: web::Data<DBPool>, req: HttpRequest) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    match load_settings(conn, &user_login) {
+        Ok(settings) => HttpResponse::Ok().json(settings),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/settings")]
+async fn update_settings(
+    pool: web::Data<DBPool>,
+    req: HttpRequest,
+    data: web::Json<UpdateSettingsRequest>,
+) -> HttpResponse {
+    let user_login = match require_login(&req) {
+        Ok(login) => login,
+        Err(resp) => return resp,
+    };
+
+    let conn = &mut pool.get().expect(CONNECTION_POOL_ERROR);
+
+    let upsert_query = r#"
+        INSERT INTO user_settings (user_login, collection_private, wishlist_private, hide_online_status)
+        VALUES ($1, COALESCE($2, FALSE), COALESCE($3, FALSE), COALESCE($4, FALSE))
+        ON CONFLICT (user_login) DO UPDATE
+        SET collection_private = COALESCE($2, user_settings.collection_private),
+            wishlist_private = COALESCE($3, user_settings.wishlist_private),
+            hide_online_status = COALESCE($4, user_settings.hide_online_status),
+            updated_at = NOW()
+    "#;
+
+    let result = diesel::sql_query(upsert_query)
+        .bind::<Text, _>(&user_login)
+        .bind::<Nullable<Bool>, _>(data.collection_private)
+        .bind::<Nullable<Bool>, _>(data.wishlist_private)
+        .bind::<Nullable<Bool>, _>(data.hide_online_status)
+        .execute(conn)
+        .and_then(|_| load_settings(conn, &user_login));
+
+    match result {
+        Ok(settings) => HttpResponse::Ok().json(settings),
+        Err(err) => {
+            eprintln!("Query error: {:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}